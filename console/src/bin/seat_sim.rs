@@ -0,0 +1,124 @@
+//! Simulated secure browser seats, for trying out the console without a room of machines.
+//!
+//! Each seat sends signed heartbeats like the app, see `console::sim`. Uses the same key as the console, from SECURE_BROWSER_PROCTOR_KEY, and checks
+//! the console's signature on every answer against `--console-key`. Without it the key the
+//! console identifies with on start is trusted, which is fine for a simulation only.
+//!
+//! Usage: seat_sim [--console <url>] [--console-key <hex>] [--seats <n>] [--interval-ms <n>]
+//!        [--violation-every <n>]
+
+use console::sim::{fetch_console_key, send, SimulatedSeat};
+use shared::proctor::KEY_ENV;
+use std::process;
+use std::time::Duration;
+
+const USAGE: &str = "usage: seat_sim [--console <url>] [--console-key <hex>] [--seats <n>] \
+                     [--interval-ms <n>] [--violation-every <n>]";
+
+struct Options {
+    console: String,
+    /// Hex ed25519 key the console signs its answers with
+    console_key: Option<String>,
+    seats: usize,
+    interval: Duration,
+    /// Heartbeats between made-up violations, 0 for none
+    violation_every: u64,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        console: "http://127.0.0.1:8787".to_string(),
+        console_key: None,
+        seats: 5,
+        interval: Duration::from_millis(2000),
+        violation_every: 10,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or(format!("{} needs a value", arg))?;
+        let number = || {
+            value
+                .parse::<u64>()
+                .map_err(|_| format!("`{}` is not a number", value))
+        };
+        match arg.as_str() {
+            "--console" => options.console = value.trim_end_matches('/').to_string(),
+            "--console-key" => options.console_key = Some(value),
+            "--seats" => options.seats = number()? as usize,
+            "--interval-ms" => options.interval = Duration::from_millis(number()?.max(1)),
+            "--violation-every" => options.violation_every = number()?,
+            _ => return Err(format!("unknown argument `{}`", arg)),
+        }
+    }
+    Ok(options)
+}
+
+async fn run_seat(number: usize, options: &Options, key: &[u8], console_key: &str) {
+    let client = reqwest::Client::new();
+    let url = format!("{}/heartbeat", options.console);
+    let mut seat = SimulatedSeat::new(number);
+    loop {
+        let heartbeat = seat.next(options.violation_every).clone();
+        // a seat that terminates still acknowledges it with one more heartbeat
+        let running = match send(&client, &url, key, console_key, &heartbeat).await {
+            Ok(response) => seat.apply(response),
+            Err(err) => {
+                eprintln!(
+                    "S{:02}: heartbeat {} failed: {}",
+                    number, heartbeat.seq, err
+                );
+                true
+            }
+        };
+        if !running {
+            let heartbeat = seat.next(0).clone();
+            let _ = send(&client, &url, key, console_key, &heartbeat).await;
+            println!("S{:02}: terminated", number);
+            return;
+        }
+        tokio::time::sleep(options.interval).await;
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(64);
+    });
+    let key = std::env::var(KEY_ENV)
+        .ok()
+        .filter(|key| !key.is_empty())
+        .unwrap_or_else(|| {
+            eprintln!("{} is not set", KEY_ENV);
+            process::exit(1);
+        });
+    let console_key = match &options.console_key {
+        Some(console_key) => console_key.clone(),
+        None => {
+            let client = reqwest::Client::new();
+            let console_key = fetch_console_key(&client, &options.console)
+                .await
+                .unwrap_or_else(|err| {
+                    eprintln!("could not get the console key: {}", err);
+                    process::exit(1);
+                });
+            println!("trusting console key {}", console_key);
+            console_key
+        }
+    };
+    let options = std::sync::Arc::new(options);
+    let key = std::sync::Arc::new(key.into_bytes());
+    let console_key = std::sync::Arc::new(console_key);
+
+    let mut seats = Vec::new();
+    for number in 1..=options.seats {
+        let (options, key, console_key) = (options.clone(), key.clone(), console_key.clone());
+        seats.push(tokio::spawn(async move {
+            run_seat(number, &options, &key, &console_key).await
+        }));
+    }
+    for seat in seats {
+        let _ = seat.await;
+    }
+}
@@ -0,0 +1,50 @@
+//! Works out the unlock code for a locked seat without the console running.
+//!
+//! Type in the challenge shown on the seat's lock screen and your proctor id, then read the
+//! printed code out to the candidate. Uses the secret from SECURE_BROWSER_UNLOCK_SECRET.
+//!
+//! Usage: unlock_code --proctor <id> <challenge>
+
+use shared::unlock::{format_code, response, SECRET_ENV};
+use std::process;
+
+const USAGE: &str = "usage: unlock_code --proctor <id> <challenge>";
+
+fn parse_args() -> Result<(String, String), String> {
+    let mut proctor = None;
+    let mut challenge = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--proctor" => proctor = Some(args.next().ok_or("--proctor needs a value")?),
+            _ if arg.starts_with("--") => return Err(format!("unknown argument `{}`", arg)),
+            _ if challenge.is_none() => challenge = Some(arg),
+            _ => return Err(format!("unexpected argument `{}`", arg)),
+        }
+    }
+    Ok((
+        proctor.ok_or("--proctor is required")?,
+        challenge.ok_or("the challenge is required")?,
+    ))
+}
+
+fn main() {
+    let (proctor, challenge) = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(64);
+    });
+    let Some(secret) = std::env::var(SECRET_ENV)
+        .ok()
+        .filter(|secret| !secret.is_empty())
+    else {
+        eprintln!("{} is not set", SECRET_ENV);
+        process::exit(1);
+    };
+    match response(secret.as_bytes(), &challenge, &proctor) {
+        Ok(code) => println!("{}", format_code(&code)),
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+//! Advertising the console over mDNS, see `shared::discovery`.
+
+use ed25519_dalek::SigningKey;
+use mdns_sd::{IfKind, ServiceDaemon, ServiceInfo};
+use shared::discovery::{public_key_hex, KEY_PROPERTY, PATH_PROPERTY, SERVICE_TYPE};
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+
+/// Reads the hex ed25519 seed from `path`, creating a new key there if the file is missing.
+///
+/// Whoever has the seed can pass for the console, so on unix a new file is readable by its
+/// owner only and an existing one that group or others can open is refused.
+pub fn load_identity(path: &Path) -> Result<SigningKey, String> {
+    let mut seed = [0u8; 32];
+    getrandom::getrandom(&mut seed).map_err(|err| err.to_string())?;
+    match create_seed(path, &seed) {
+        Ok(()) => println!("created a new console identity in {}", path.display()),
+        Err(err) if err.kind() == ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(format!("could not write {}: {}", path.display(), err)),
+    }
+    check_private(path)?;
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    let seed = hex::decode(text.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or(format!(
+            "{} does not hold a hex ed25519 seed",
+            path.display()
+        ))?;
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+/// Writes the seed to a new file, failing with `AlreadyExists` if there is one.
+fn create_seed(path: &Path, seed: &[u8; 32]) -> std::io::Result<()> {
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)?.write_all(hex::encode(seed).as_bytes())
+}
+
+#[cfg(unix)]
+fn check_private(path: &Path) -> Result<(), String> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?
+        .permissions()
+        .mode();
+    if mode & 0o077 != 0 {
+        return Err(format!(
+            "{} is open to other users (mode {:o}), `chmod 600` it or move it away for a new identity",
+            path.display(),
+            mode & 0o777
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_private(_path: &Path) -> Result<(), String> {
+    Ok(())
+}
+
+/// Registers the console, it stays advertised while the returned daemon lives.
+///
+/// With `loopback` the service is also answered on 127.0.0.1, so a seat on this machine
+/// can find it.
+pub fn advertise(
+    instance: &str,
+    listen: SocketAddr,
+    path: &str,
+    identity: &SigningKey,
+    loopback: bool,
+) -> Result<ServiceDaemon, String> {
+    let daemon = ServiceDaemon::new().map_err(|err| err.to_string())?;
+    if loopback {
+        daemon
+            .enable_interface(IfKind::LoopbackV4)
+            .map_err(|err| err.to_string())?;
+    }
+    let key = public_key_hex(identity);
+    let properties = [(PATH_PROPERTY, path), (KEY_PROPERTY, key.as_str())];
+    let host = format!("{}.local.", instance);
+    // a console bound to one address is only advertised there
+    let addresses = if listen.ip().is_unspecified() {
+        String::new()
+    } else {
+        listen.ip().to_string()
+    };
+    let info = ServiceInfo::new(
+        SERVICE_TYPE,
+        instance,
+        &host,
+        addresses.as_str(),
+        listen.port(),
+        &properties[..],
+    )
+    .map_err(|err| err.to_string())?;
+    let info = if listen.ip().is_unspecified() {
+        info.enable_addr_auto()
+    } else {
+        info
+    };
+    daemon.register(info).map_err(|err| err.to_string())?;
+    Ok(daemon)
+}
@@ -0,0 +1,7 @@
+//! Proctor console for centres without internet. It runs on the invigilator's laptop and the
+//! seats find it over mDNS or have `proctor.endpoint` pointed at it.
+
+pub mod discovery;
+pub mod seats;
+pub mod server;
+pub mod sim;
@@ -0,0 +1,171 @@
+//! Proctor console server.
+//!
+//! The proctor key comes from SECURE_BROWSER_PROCTOR_KEY and must match the seats', the
+//! console does not start without it. The API token comes from SECURE_BROWSER_CONSOLE_TOKEN,
+//! without it the API is only open on this machine. The unlock secret for issuing unlock codes
+//! comes from SECURE_BROWSER_UNLOCK_SECRET, and the release secret that opens offline exam
+//! packages at their start time from SECURE_BROWSER_RELEASE_SECRET. The seat file lists the
+//! room's seat numbers, one per line.
+//!
+//! The console signs its answers with the ed25519 key in the identity file, `--identity`,
+//! which holds the hex seed and is created on first use, readable by its owner only. The
+//! public key it prints goes in the seats' policy as `proctor.console_key`. The console is advertised over mDNS under `--name`,
+//! `--mdns-loopback` also answers on 127.0.0.1, for a seat running on the same machine.
+//!
+//! Usage: console [--listen <addr>] [--seats <file>] [--stale-secs <n>]
+//!        [--identity <file>] [--name <instance>] [--no-advertise] [--mdns-loopback]
+
+use console::discovery::{advertise, load_identity};
+use console::seats::SeatMap;
+use console::server::{router, Console};
+use shared::discovery::public_key_hex;
+use shared::proctor::KEY_ENV;
+use shared::release;
+use shared::unlock;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+
+const USAGE: &str = "usage: console [--listen <addr>] [--seats <file>] [--stale-secs <n>] \
+                     [--identity <file>] [--name <instance>] [--no-advertise] [--mdns-loopback]";
+const TOKEN_ENV: &str = "SECURE_BROWSER_CONSOLE_TOKEN";
+const DEFAULT_IDENTITY: &str = "console-identity.key";
+
+struct Options {
+    listen: String,
+    seats: Option<PathBuf>,
+    stale_secs: u64,
+    identity: PathBuf,
+    name: String,
+    advertise: bool,
+    loopback: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        listen: "0.0.0.0:8787".to_string(),
+        seats: None,
+        stale_secs: 15,
+        identity: PathBuf::from(DEFAULT_IDENTITY),
+        name: "proctor-console".to_string(),
+        advertise: true,
+        loopback: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => options.listen = value()?,
+            "--seats" => options.seats = Some(PathBuf::from(value()?)),
+            "--stale-secs" => {
+                let secs = value()?;
+                options.stale_secs = secs
+                    .parse()
+                    .map_err(|_| format!("`{}` is not a number of seconds", secs))?;
+            }
+            "--identity" => options.identity = PathBuf::from(value()?),
+            "--name" => options.name = value()?,
+            "--no-advertise" => options.advertise = false,
+            "--mdns-loopback" => options.loopback = true,
+            _ => return Err(format!("unknown argument `{}`", arg)),
+        }
+    }
+    Ok(options)
+}
+
+fn read_seats(path: &PathBuf) -> Result<Vec<String>, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+fn env(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+#[tokio::main]
+async fn main() {
+    let options = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(64);
+    });
+    let expected = match &options.seats {
+        Some(path) => read_seats(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        }),
+        None => Vec::new(),
+    };
+    let key = env(KEY_ENV).unwrap_or_else(|| {
+        eprintln!("{} is not set", KEY_ENV);
+        process::exit(1);
+    });
+    let token = env(TOKEN_ENV);
+    if token.is_none() {
+        eprintln!(
+            "{} is not set, the API only answers on this machine",
+            TOKEN_ENV
+        );
+    }
+    let identity = load_identity(&options.identity).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    println!("console identity key: {}", public_key_hex(&identity));
+
+    let seats = SeatMap::new(expected, Duration::from_secs(options.stale_secs));
+    let console = Arc::new(Console::new(
+        key.into_bytes(),
+        token,
+        identity.clone(),
+        env(unlock::SECRET_ENV).map(String::into_bytes),
+        env(release::SECRET_ENV).map(String::into_bytes),
+        seats,
+    ));
+    let listener = match tokio::net::TcpListener::bind(&options.listen).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen on {}: {}", options.listen, err);
+            process::exit(1);
+        }
+    };
+    println!("proctor console listening on {}", options.listen);
+
+    // kept alive for as long as the console runs
+    let _advertised = if options.advertise {
+        let address = listener.local_addr().unwrap_or_else(|err| {
+            eprintln!("could not read the listening address: {}", err);
+            process::exit(1);
+        });
+        match advertise(
+            &options.name,
+            address,
+            "/heartbeat",
+            &identity,
+            options.loopback,
+        ) {
+            Ok(daemon) => {
+                println!("advertised as {} over mDNS", options.name);
+                Some(daemon)
+            }
+            Err(err) => {
+                eprintln!("could not advertise over mDNS: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let app = router(console).into_make_service_with_connect_info::<SocketAddr>();
+    if let Err(err) = axum::serve(listener, app).await {
+        eprintln!("server stopped: {}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,238 @@
+//! The live seat map, built from the heartbeats of the secure browser seats.
+
+use serde::Serialize;
+use shared::fingerprint::{is_same_machine, DeviceFingerprint};
+use shared::proctor::{
+    CommandAction, Heartbeat, HeartbeatResponse, ProctorCommand, ViolationReport,
+};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Connection {
+    /// On the seat list but no heartbeat yet
+    Waiting,
+    Online,
+    /// Heartbeats stopped, the seat may be off or cut from the network
+    Offline,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Seat {
+    pub seat: String,
+    pub connection: Connection,
+    pub session_id: Option<String>,
+    /// The machine the seat checked in on, later heartbeats are matched against it
+    pub fingerprint: Option<DeviceFingerprint>,
+    /// Heartbeats for this seat came from a machine that does not match `fingerprint`
+    pub other_machine: bool,
+    /// `SessionStatus` as the app sent it, e.g. `locked`
+    pub status: Option<String>,
+    pub mode: Option<String>,
+    pub violation_count: usize,
+    pub violations: Vec<ViolationReport>,
+    /// Commands not yet acknowledged by the seat
+    pub pending: Vec<ProctorCommand>,
+    /// Challenge on the seat's lock screen, for an unlock code
+    pub unlock_challenge: Option<String>,
+    /// Another session sent heartbeats for this seat while it was online, they were refused
+    pub duplicate: bool,
+    pub last_seq: u64,
+    /// Unix timestamp in milliseconds
+    pub last_seen_at: Option<i64>,
+}
+
+impl Seat {
+    fn new(seat: String) -> Self {
+        Self {
+            seat,
+            connection: Connection::Waiting,
+            session_id: None,
+            fingerprint: None,
+            other_machine: false,
+            status: None,
+            mode: None,
+            violation_count: 0,
+            violations: Vec::new(),
+            pending: Vec::new(),
+            unlock_challenge: None,
+            duplicate: false,
+            last_seq: 0,
+            last_seen_at: None,
+        }
+    }
+
+    fn acked_violation(&self) -> u64 {
+        self.violations.last().map_or(0, |violation| violation.id)
+    }
+}
+
+/// What a heartbeat changed, for the console output.
+#[derive(Debug, Default)]
+pub struct Changes {
+    pub checked_in: bool,
+    pub new_session: bool,
+    /// The first heartbeat from a machine that is not the seat's
+    pub other_machine: bool,
+    pub status: Option<String>,
+    pub violations: Vec<ViolationReport>,
+    pub acked: Vec<ProctorCommand>,
+}
+
+#[derive(Debug)]
+pub struct SeatMap {
+    seats: BTreeMap<String, Seat>,
+    stale_ms: i64,
+}
+
+/// Seat name for a heartbeat, seats without a number are listed by session.
+pub fn seat_name(heartbeat: &Heartbeat) -> String {
+    match &heartbeat.seat {
+        Some(seat) => seat.clone(),
+        None => format!(
+            "unassigned-{}",
+            heartbeat
+                .session_id
+                .get(..8)
+                .unwrap_or(&heartbeat.session_id)
+        ),
+    }
+}
+
+impl SeatMap {
+    /// `expected` are the seats of the room, they show as waiting until they check in.
+    pub fn new(expected: impl IntoIterator<Item = String>, stale_after: Duration) -> Self {
+        Self {
+            seats: expected
+                .into_iter()
+                .map(|seat| (seat.clone(), Seat::new(seat)))
+                .collect(),
+            stale_ms: stale_after.as_millis() as i64,
+        }
+    }
+
+    /// Stores a heartbeat and returns the answer for the seat.
+    ///
+    /// A heartbeat from another session is refused while the seat's session is online, so a
+    /// second machine set to the same seat cannot take over its commands and violations. Once
+    /// the seat has gone offline the new session replaces the old one.
+    pub fn record(
+        &mut self,
+        heartbeat: &Heartbeat,
+        now: i64,
+    ) -> Result<(HeartbeatResponse, Changes), String> {
+        let name = seat_name(heartbeat);
+        let mut changes = Changes::default();
+        let stale_ms = self.stale_ms;
+        let seat = self
+            .seats
+            .entry(name.clone())
+            .or_insert_with(|| Seat::new(name));
+
+        if seat.session_id.as_deref() != Some(heartbeat.session_id.as_str()) {
+            if seat.session_id.is_some() {
+                let online = seat.last_seen_at.is_some_and(|seen| now - seen <= stale_ms);
+                if online {
+                    seat.duplicate = true;
+                    return Err(format!("seat `{}` is in use by another session", seat.seat));
+                }
+                changes.new_session = true;
+            } else {
+                changes.checked_in = true;
+            }
+            // commands and violations belong to the old session, which is gone
+            seat.session_id = Some(heartbeat.session_id.clone());
+            seat.violations.clear();
+            seat.pending.clear();
+            seat.duplicate = false;
+            seat.last_seq = 0;
+        }
+
+        if seat.status.as_deref() != Some(heartbeat.status.as_str()) {
+            changes.status = Some(heartbeat.status.clone());
+        }
+        match (&seat.fingerprint, &heartbeat.fingerprint) {
+            (Some(known), Some(current)) if is_same_machine(known, current) => {
+                // follows a part that was swapped
+                seat.fingerprint = Some(current.clone());
+            }
+            (Some(_), Some(_)) => {
+                changes.other_machine = !seat.other_machine;
+                seat.other_machine = true;
+            }
+            (None, current) => seat.fingerprint = current.clone(),
+            (Some(_), None) => {}
+        }
+        seat.status = Some(heartbeat.status.clone());
+        seat.mode = Some(heartbeat.mode.clone());
+        seat.violation_count = heartbeat.violation_count;
+        seat.unlock_challenge = heartbeat.unlock_challenge.clone();
+        seat.last_seq = seat.last_seq.max(heartbeat.seq);
+        seat.last_seen_at = Some(now);
+        seat.connection = Connection::Online;
+
+        for violation in &heartbeat.violations {
+            // resent until acknowledged, keep the first copy
+            if violation.id > seat.acked_violation() {
+                seat.violations.push(violation.clone());
+                changes.violations.push(violation.clone());
+            }
+        }
+        let (acked, pending) = seat
+            .pending
+            .drain(..)
+            .partition(|command| heartbeat.acked_commands.contains(&command.id));
+        changes.acked = acked;
+        seat.pending = pending;
+
+        let response = HeartbeatResponse {
+            commands: seat.pending.clone(),
+            acked_violation: Some(seat.acked_violation()),
+            ..HeartbeatResponse::to(heartbeat)
+        };
+        Ok((response, changes))
+    }
+
+    /// Queues a command for the seat's next heartbeat.
+    pub fn queue(
+        &mut self,
+        seat: &str,
+        id: String,
+        action: CommandAction,
+    ) -> Result<ProctorCommand, String> {
+        let seat = self
+            .seats
+            .get_mut(seat)
+            .ok_or_else(|| format!("unknown seat `{}`", seat))?;
+        if seat.session_id.is_none() {
+            return Err(format!("seat `{}` has not checked in yet", seat.seat));
+        }
+        let command = ProctorCommand { id, action };
+        seat.pending.push(command.clone());
+        Ok(command)
+    }
+
+    fn view(&self, seat: &Seat, now: i64) -> Seat {
+        let mut seat = seat.clone();
+        if let Some(seen) = seat.last_seen_at {
+            seat.connection = if now - seen > self.stale_ms {
+                Connection::Offline
+            } else {
+                Connection::Online
+            };
+        }
+        seat
+    }
+
+    pub fn seats(&self, now: i64) -> Vec<Seat> {
+        self.seats
+            .values()
+            .map(|seat| self.view(seat, now))
+            .collect()
+    }
+
+    pub fn seat(&self, seat: &str, now: i64) -> Option<Seat> {
+        self.seats.get(seat).map(|seat| self.view(seat, now))
+    }
+}
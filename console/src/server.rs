@@ -0,0 +1,336 @@
+//! The console's HTTP API.
+//!
+//! Seats post signed heartbeats to `POST /heartbeat`, see `shared::proctor`, and the console
+//! signs its answers with its identity key. The invigilator reads the seat map from
+//! `GET /api/seats` or `GET /api/seats/{seat}` and sends commands with
+//! `POST /api/seats/{seat}/commands`, the body being a command such as
+//! `{"type": "lock", "reason": "..."}`. The `/api` routes need the console token as a bearer
+//! token, without one configured they only answer on this machine. The console also proves
+//! who it is on `GET /identity`, see `shared::discovery`. With the unlock secret,
+//! `POST /api/unlock-codes` turns `{"challenge": "...", "proctor": "..."}` from a locked seat
+//! into the code that opens it, see `shared::unlock`. With the release secret, seats get the
+//! key of an offline exam package from `POST /release` once the console's clock passes its
+//! start time, see `shared::release`.
+
+use crate::seats::{seat_name, Changes, SeatMap};
+use axum::body::Bytes;
+use axum::extract::{ConnectInfo, Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use ed25519_dalek::SigningKey;
+use serde::Deserialize;
+use shared::discovery::{sign_identity, IDENTITY_PATH, NONCE_LENGTH};
+use shared::proctor::{sign_response, verify, CommandAction, Heartbeat, SIGNATURE_HEADER};
+use shared::release::{ReleaseRequest, ReleaseResponse, RELEASE_PATH};
+use shared::unlock::{format_code, response};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+pub struct Console {
+    key: Vec<u8>,
+    token: Option<String>,
+    identity: SigningKey,
+    unlock_secret: Option<Vec<u8>>,
+    release_secret: Option<Vec<u8>>,
+    seats: Mutex<SeatMap>,
+    /// Start time, keeps command ids unique across console restarts
+    started_at: i64,
+    next_command: AtomicU64,
+}
+
+impl Console {
+    pub fn new(
+        key: Vec<u8>,
+        token: Option<String>,
+        identity: SigningKey,
+        unlock_secret: Option<Vec<u8>>,
+        release_secret: Option<Vec<u8>>,
+        seats: SeatMap,
+    ) -> Self {
+        Self {
+            key,
+            token,
+            identity,
+            unlock_secret,
+            release_secret,
+            seats: Mutex::new(seats),
+            started_at: now_ms(),
+            next_command: AtomicU64::new(1),
+        }
+    }
+}
+
+type Shared = Arc<Console>;
+
+fn error(status: StatusCode, message: impl Into<String>) -> Response {
+    (status, Json(serde_json::json!({ "error": message.into() }))).into_response()
+}
+
+fn report(seat: &str, changes: &Changes) {
+    if changes.checked_in {
+        println!("seat {}: checked in", seat);
+    } else if changes.new_session {
+        println!("seat {}: new session", seat);
+    }
+    if changes.other_machine {
+        println!(
+            "seat {}: WARNING heartbeats come from another machine than the seat's",
+            seat
+        );
+    }
+    if let Some(status) = &changes.status {
+        println!("seat {}: {}", seat, status);
+    }
+    for violation in &changes.violations {
+        println!(
+            "seat {}: violation {} {}: {}",
+            seat, violation.id, violation.kind, violation.detail
+        );
+    }
+    for command in &changes.acked {
+        println!("seat {}: carried out {}", seat, command.id);
+    }
+}
+
+/// Checks the proctor key signature of a seat's request.
+fn check_signature(console: &Console, headers: &HeaderMap, body: &[u8]) -> Result<(), String> {
+    let signature = headers
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    verify(&console.key, body, signature)
+}
+
+/// A JSON answer to a seat, signed with the identity key.
+fn signed(console: &Console, value: &impl serde::Serialize) -> Response {
+    let body = serde_json::to_vec(value).expect("responses serialize");
+    (
+        [
+            ("content-type", "application/json".to_string()),
+            (SIGNATURE_HEADER, sign_response(&console.identity, &body)),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+async fn heartbeat(State(console): State<Shared>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Err(err) = check_signature(&console, &headers, &body) {
+        eprintln!("rejected heartbeat: {}", err);
+        return error(StatusCode::UNAUTHORIZED, err);
+    }
+    let heartbeat: Heartbeat = match serde_json::from_slice(&body) {
+        Ok(heartbeat) => heartbeat,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    let recorded = match console.seats.lock() {
+        Ok(mut seats) => seats.record(&heartbeat, now_ms()),
+        Err(_) => return error(StatusCode::INTERNAL_SERVER_ERROR, "seat map is unavailable"),
+    };
+    let (response, changes) = match recorded {
+        Ok(recorded) => recorded,
+        Err(err) => {
+            println!(
+                "seat {}: WARNING refused session {}, {}",
+                seat_name(&heartbeat),
+                heartbeat.session_id,
+                err
+            );
+            return error(StatusCode::CONFLICT, err);
+        }
+    };
+    report(&seat_name(&heartbeat), &changes);
+    signed(&console, &response)
+}
+
+async fn release(State(console): State<Shared>, headers: HeaderMap, body: Bytes) -> Response {
+    if let Err(err) = check_signature(&console, &headers, &body) {
+        eprintln!("rejected release request: {}", err);
+        return error(StatusCode::UNAUTHORIZED, err);
+    }
+    let Some(secret) = &console.release_secret else {
+        return error(StatusCode::NOT_FOUND, "this console has no release secret");
+    };
+    let request: ReleaseRequest = match serde_json::from_slice(&body) {
+        Ok(request) => request,
+        Err(err) => return error(StatusCode::BAD_REQUEST, err.to_string()),
+    };
+    match ReleaseResponse::to(&request, secret, now_ms()) {
+        Ok(response) => {
+            println!("released exam {}", request.exam_id);
+            signed(&console, &response)
+        }
+        Err(err) => error(StatusCode::FORBIDDEN, err),
+    }
+}
+
+#[derive(Deserialize)]
+struct IdentityQuery {
+    nonce: String,
+}
+
+async fn identity(State(console): State<Shared>, Query(query): Query<IdentityQuery>) -> Response {
+    match hex::decode(&query.nonce) {
+        Ok(nonce) if nonce.len() == NONCE_LENGTH => {
+            Json(sign_identity(&console.identity, &nonce)).into_response()
+        }
+        _ => error(
+            StatusCode::BAD_REQUEST,
+            format!("nonce must be {} bytes in hex", NONCE_LENGTH),
+        ),
+    }
+}
+
+/// Compares in constant time, so the answer time does not give away how much of a guess
+/// was right.
+fn same_token(given: &str, token: &str) -> bool {
+    given.len() == token.len()
+        && given
+            .bytes()
+            .zip(token.bytes())
+            .fold(0u8, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn authorize(
+    console: &Console,
+    peer: SocketAddr,
+    headers: &HeaderMap,
+) -> Result<(), (StatusCode, &'static str)> {
+    let Some(token) = &console.token else {
+        return if peer.ip().is_loopback() {
+            Ok(())
+        } else {
+            Err((
+                StatusCode::FORBIDDEN,
+                "set a console token to use the API from another machine",
+            ))
+        };
+    };
+    let given = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    if given.is_some_and(|given| same_token(given, token)) {
+        Ok(())
+    } else {
+        Err((StatusCode::UNAUTHORIZED, "missing or wrong console token"))
+    }
+}
+
+async fn list_seats(
+    State(console): State<Shared>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err((status, message)) = authorize(&console, peer, &headers) {
+        return error(status, message);
+    }
+    match console.seats.lock() {
+        Ok(seats) => Json(seats.seats(now_ms())).into_response(),
+        Err(_) => error(StatusCode::INTERNAL_SERVER_ERROR, "seat map is unavailable"),
+    }
+}
+
+async fn show_seat(
+    State(console): State<Shared>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(seat): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Err((status, message)) = authorize(&console, peer, &headers) {
+        return error(status, message);
+    }
+    match console
+        .seats
+        .lock()
+        .map(|seats| seats.seat(&seat, now_ms()))
+    {
+        Ok(Some(seat)) => Json(seat).into_response(),
+        Ok(None) => error(StatusCode::NOT_FOUND, format!("unknown seat `{}`", seat)),
+        Err(_) => error(StatusCode::INTERNAL_SERVER_ERROR, "seat map is unavailable"),
+    }
+}
+
+async fn send_command(
+    State(console): State<Shared>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    Path(seat): Path<String>,
+    headers: HeaderMap,
+    Json(action): Json<CommandAction>,
+) -> Response {
+    if let Err((status, message)) = authorize(&console, peer, &headers) {
+        return error(status, message);
+    }
+    let id = format!(
+        "{}-{}",
+        console.started_at,
+        console.next_command.fetch_add(1, Ordering::Relaxed)
+    );
+    let queued = match console.seats.lock() {
+        Ok(mut seats) => seats.queue(&seat, id, action),
+        Err(_) => return error(StatusCode::INTERNAL_SERVER_ERROR, "seat map is unavailable"),
+    };
+    match queued {
+        Ok(command) => {
+            println!("seat {}: queued {} {:?}", seat, command.id, command.action);
+            (StatusCode::ACCEPTED, Json(command)).into_response()
+        }
+        Err(err) => error(StatusCode::NOT_FOUND, err),
+    }
+}
+
+#[derive(Deserialize)]
+struct UnlockRequest {
+    challenge: String,
+    proctor: String,
+}
+
+async fn issue_unlock_code(
+    State(console): State<Shared>,
+    ConnectInfo(peer): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(request): Json<UnlockRequest>,
+) -> Response {
+    if let Err((status, message)) = authorize(&console, peer, &headers) {
+        return error(status, message);
+    }
+    let Some(secret) = &console.unlock_secret else {
+        return error(StatusCode::NOT_FOUND, "this console has no unlock secret");
+    };
+    match response(secret, &request.challenge, &request.proctor) {
+        Ok(code) => {
+            println!(
+                "unlock code for challenge {} issued to {}",
+                format_code(&request.challenge),
+                request.proctor.trim()
+            );
+            Json(serde_json::json!({ "code": format_code(&code) })).into_response()
+        }
+        Err(err) => error(StatusCode::BAD_REQUEST, err),
+    }
+}
+
+/// Serve with `into_make_service_with_connect_info::<SocketAddr>`, the API checks the peer.
+pub fn router(console: Shared) -> Router {
+    Router::new()
+        .route("/heartbeat", post(heartbeat))
+        .route(IDENTITY_PATH, get(identity))
+        .route(RELEASE_PATH, post(release))
+        .route("/api/seats", get(list_seats))
+        .route("/api/seats/{seat}", get(show_seat))
+        .route("/api/seats/{seat}/commands", post(send_command))
+        .route("/api/unlock-codes", post(issue_unlock_code))
+        .with_state(console)
+}
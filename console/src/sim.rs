@@ -0,0 +1,172 @@
+//! Simulated secure browser seats, for trying out the console without a room of machines.
+//!
+//! A [`SimulatedSeat`] builds heartbeats like the app, reports a made-up violation now and
+//! then and carries out the console's commands: lock and unlock change its status, terminate
+//! stops it. Used by the `seat_sim` binary and the tests.
+
+use crate::server::now_ms;
+use shared::discovery::{self, IdentityProof, IDENTITY_PATH};
+use shared::fingerprint::DeviceFingerprint;
+use shared::proctor::{
+    sign, verify_response, CommandAction, Heartbeat, HeartbeatResponse, ViolationReport,
+    NONCE_LENGTH, SIGNATURE_HEADER,
+};
+
+fn random_bytes(length: usize) -> Vec<u8> {
+    let mut bytes = vec![0; length];
+    getrandom::getrandom(&mut bytes).expect("the OS provides randomness");
+    bytes
+}
+
+/// Asks the console for its key, checking it holds the private half.
+pub async fn fetch_console_key(client: &reqwest::Client, console: &str) -> Result<String, String> {
+    let nonce = random_bytes(discovery::NONCE_LENGTH);
+    let proof: IdentityProof = client
+        .get(format!("{}{}", console, IDENTITY_PATH))
+        .query(&[("nonce", hex::encode(&nonce))])
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?
+        .json()
+        .await
+        .map_err(|err| err.to_string())?;
+    discovery::verify_identity(&proof.public_key, &nonce, &proof)?;
+    Ok(proof.public_key)
+}
+
+/// A made-up fingerprint with every component the app hashes, different for each seat.
+pub fn fingerprint(number: usize) -> DeviceFingerprint {
+    DeviceFingerprint {
+        id: format!("simulated-{}", number),
+        components: [
+            "product_uuid",
+            "board_serial",
+            "cpu_model",
+            "primary_mac",
+            "machine_id",
+        ]
+        .into_iter()
+        .map(|name| (name.to_string(), format!("{}-{}", name, number)))
+        .collect(),
+    }
+}
+
+pub struct SimulatedSeat {
+    heartbeat: Heartbeat,
+    acked_violation: u64,
+    violations: Vec<ViolationReport>,
+}
+
+impl SimulatedSeat {
+    pub fn new(number: usize) -> Self {
+        let nanos = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        Self {
+            heartbeat: Heartbeat {
+                seq: 0,
+                nonce: String::new(),
+                session_id: format!("{:032x}", nanos.wrapping_add(number as u128)),
+                seat: Some(format!("S{:02}", number)),
+                fingerprint: Some(fingerprint(number)),
+                status: "active".to_string(),
+                mode: "kiosk".to_string(),
+                violation_count: 0,
+                violations: Vec::new(),
+                acked_commands: Vec::new(),
+                unlock_challenge: None,
+                sent_at: 0,
+            },
+            acked_violation: 0,
+            violations: Vec::new(),
+        }
+    }
+
+    pub fn next(&mut self, violation_every: u64) -> &Heartbeat {
+        let heartbeat = &mut self.heartbeat;
+        heartbeat.seq += 1;
+        heartbeat.nonce = hex::encode(random_bytes(NONCE_LENGTH));
+        if violation_every > 0 && heartbeat.seq.is_multiple_of(violation_every) {
+            heartbeat.violation_count += 1;
+            self.violations.push(ViolationReport {
+                id: heartbeat.violation_count as u64,
+                kind: "focus_lost".to_string(),
+                detail: "simulated".to_string(),
+                occurred_at: now_ms(),
+            });
+        }
+        let acked = self.acked_violation;
+        heartbeat.violations = self
+            .violations
+            .iter()
+            .filter(|violation| violation.id > acked)
+            .cloned()
+            .collect();
+        heartbeat.sent_at = now_ms();
+        heartbeat
+    }
+
+    /// `SessionStatus` the seat reports, changed by lock, unlock and terminate
+    pub fn status(&self) -> &str {
+        &self.heartbeat.status
+    }
+
+    /// Carries out the answer's commands, false once the seat is terminated.
+    pub fn apply(&mut self, response: HeartbeatResponse) -> bool {
+        let seat = self.heartbeat.seat.clone().unwrap_or_default();
+        self.heartbeat.acked_commands.clear();
+        if let Some(acked) = response.acked_violation {
+            self.acked_violation = self.acked_violation.max(acked);
+        }
+        let mut running = true;
+        for command in response.commands {
+            println!("{}: {} {:?}", seat, command.id, command.action);
+            match command.action {
+                CommandAction::Lock { .. } => self.heartbeat.status = "locked".to_string(),
+                CommandAction::Unlock => self.heartbeat.status = "active".to_string(),
+                CommandAction::Terminate { .. } => {
+                    self.heartbeat.status = "ended".to_string();
+                    running = false;
+                }
+                CommandAction::ExtendTime { .. } | CommandAction::Message { .. } => {}
+            }
+            self.heartbeat.acked_commands.push(command.id);
+        }
+        running
+    }
+}
+
+/// Posts a heartbeat and checks that the answer is signed by the console and answers it.
+pub async fn send(
+    client: &reqwest::Client,
+    url: &str,
+    key: &[u8],
+    console_key: &str,
+    heartbeat: &Heartbeat,
+) -> Result<HeartbeatResponse, String> {
+    let body = serde_json::to_vec(heartbeat).map_err(|err| err.to_string())?;
+    let response = client
+        .post(url)
+        .header(SIGNATURE_HEADER, sign(key, &body))
+        .header("content-type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("console answered {}", response.status()));
+    }
+    let signature = response
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_string();
+    let bytes = response.bytes().await.map_err(|err| err.to_string())?;
+    verify_response(console_key, &bytes, &signature)?;
+    let reply: HeartbeatResponse = serde_json::from_slice(&bytes).map_err(|err| err.to_string())?;
+    reply.answers(heartbeat)?;
+    Ok(reply)
+}
@@ -0,0 +1,67 @@
+//! Loading and creating the console's identity seed.
+
+#![cfg(unix)]
+
+use console::discovery::load_identity;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+
+/// A seed file path in a directory of its own, removed again when dropped.
+struct Seed(PathBuf);
+
+impl Seed {
+    fn new(name: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("console-identity-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir.join("console-identity.key"))
+    }
+
+    fn write(&self, contents: &str, mode: u32) {
+        std::fs::write(&self.0, contents).unwrap();
+        std::fs::set_permissions(&self.0, std::fs::Permissions::from_mode(mode)).unwrap();
+    }
+}
+
+impl Drop for Seed {
+    fn drop(&mut self) {
+        if let Some(dir) = self.0.parent() {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+    }
+}
+
+#[test]
+fn a_new_identity_is_private_and_kept() {
+    let seed = Seed::new("new");
+    let created = load_identity(&seed.0).unwrap();
+    let mode = std::fs::metadata(&seed.0).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    let loaded = load_identity(&seed.0).unwrap();
+    assert_eq!(loaded.to_bytes(), created.to_bytes());
+}
+
+#[test]
+fn a_seed_others_can_read_is_refused() {
+    let seed = Seed::new("open");
+    let hex_seed = "07".repeat(32);
+    for mode in [0o644, 0o640, 0o604, 0o660] {
+        seed.write(&hex_seed, mode);
+        let err = load_identity(&seed.0).err().unwrap();
+        assert!(err.contains("open to other users"), "{:o}: {}", mode, err);
+    }
+    seed.write(&hex_seed, 0o600);
+    let loaded = load_identity(&seed.0).unwrap();
+    assert_eq!(loaded.to_bytes(), [7; 32]);
+    // the file is left as it was
+    assert_eq!(std::fs::read_to_string(&seed.0).unwrap(), hex_seed);
+}
+
+#[test]
+fn a_file_without_a_seed_is_refused() {
+    let seed = Seed::new("garbage");
+    seed.write("not a seed", 0o600);
+    let err = load_identity(&seed.0).err().unwrap();
+    assert!(err.contains("does not hold a hex ed25519 seed"), "{}", err);
+}
@@ -0,0 +1,363 @@
+//! Runs the console's server with simulated seats and drives it through its API.
+
+use console::seats::SeatMap;
+use console::server::{router, Console};
+use console::sim::{fetch_console_key, fingerprint, send, SimulatedSeat};
+use ed25519_dalek::SigningKey;
+use serde_json::{json, Value};
+use shared::discovery::public_key_hex;
+use shared::unlock::{challenge, verify_response};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+const KEY: &[u8] = b"console-test-key";
+const TOKEN: &str = "console-test-token";
+const SEED: [u8; 32] = [9; 32];
+const UNLOCK_SECRET: &[u8] = b"console-test-unlock-secret";
+
+struct TestConsole {
+    url: String,
+    console_key: String,
+    client: reqwest::Client,
+}
+
+impl TestConsole {
+    async fn start(expected: &[&str], token: Option<&str>) -> Self {
+        Self::start_with(expected, token, None).await
+    }
+
+    async fn start_with(
+        expected: &[&str],
+        token: Option<&str>,
+        unlock_secret: Option<&[u8]>,
+    ) -> Self {
+        let identity = SigningKey::from_bytes(&SEED);
+        let console_key = public_key_hex(&identity);
+        let seats = SeatMap::new(
+            expected.iter().map(|seat| seat.to_string()),
+            Duration::from_secs(15),
+        );
+        let console = Arc::new(Console::new(
+            KEY.to_vec(),
+            token.map(str::to_string),
+            identity,
+            unlock_secret.map(<[u8]>::to_vec),
+            None,
+            seats,
+        ));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let app = router(console).into_make_service_with_connect_info::<SocketAddr>();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Self {
+            url,
+            console_key,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// Sends the seat's next heartbeat and carries out the answer.
+    async fn heartbeat(&self, seat: &mut SimulatedSeat, violation_every: u64) -> bool {
+        let heartbeat = seat.next(violation_every).clone();
+        let url = format!("{}/heartbeat", self.url);
+        let response = send(&self.client, &url, KEY, &self.console_key, &heartbeat)
+            .await
+            .expect("a signed answer to the heartbeat");
+        seat.apply(response)
+    }
+
+    async fn get(&self, path: &str) -> (u16, Value) {
+        let response = self
+            .client
+            .get(format!("{}{}", self.url, path))
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.json().await.unwrap())
+    }
+
+    async fn command(&self, seat: &str, action: Value) -> (u16, Value) {
+        let response = self
+            .client
+            .post(format!("{}/api/seats/{}/commands", self.url, seat))
+            .json(&action)
+            .send()
+            .await
+            .unwrap();
+        (response.status().as_u16(), response.json().await.unwrap())
+    }
+
+    async fn unlock_code(&self, request: Value, token: Option<&str>) -> (u16, Value) {
+        let mut post = self
+            .client
+            .post(format!("{}/api/unlock-codes", self.url))
+            .json(&request);
+        if let Some(token) = token {
+            post = post.bearer_auth(token);
+        }
+        let response = post.send().await.unwrap();
+        (response.status().as_u16(), response.json().await.unwrap())
+    }
+}
+
+#[tokio::test]
+async fn seats_check_in_and_show_on_the_seat_map() {
+    let console = TestConsole::start(&["S01", "S02", "S03", "S04"], None).await;
+    assert_eq!(
+        fetch_console_key(&console.client, &console.url).await,
+        Ok(console.console_key.clone())
+    );
+
+    let mut seats: Vec<SimulatedSeat> = (1..=3).map(SimulatedSeat::new).collect();
+    for seat in &mut seats {
+        assert!(console.heartbeat(seat, 0).await);
+    }
+    // the second heartbeat of S02 reports a violation
+    assert!(console.heartbeat(&mut seats[1], 2).await);
+
+    let (status, map) = console.get("/api/seats").await;
+    assert_eq!(status, 200);
+    let map = map.as_array().unwrap();
+    let names: Vec<&str> = map
+        .iter()
+        .map(|seat| seat["seat"].as_str().unwrap())
+        .collect();
+    assert_eq!(names, ["S01", "S02", "S03", "S04"]);
+    for seat in &map[..3] {
+        assert_eq!(seat["connection"], "online");
+        assert_eq!(seat["status"], "active");
+        assert_eq!(seat["mode"], "kiosk");
+    }
+    assert_eq!(map[0]["last_seq"], 1);
+    assert_eq!(map[1]["last_seq"], 2);
+    assert_eq!(map[1]["violation_count"], 1);
+    assert_eq!(map[1]["violations"][0]["kind"], "focus_lost");
+    assert_eq!(map[3]["connection"], "waiting");
+    assert_eq!(map[3]["session_id"], Value::Null);
+
+    let (status, seat) = console.get("/api/seats/S03").await;
+    assert_eq!(status, 200);
+    assert_eq!(seat["fingerprint"]["id"], "simulated-3");
+    assert_eq!(seat["other_machine"], false);
+    let (status, _) = console.get("/api/seats/S09").await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn commands_reach_their_seat_until_acknowledged() {
+    let console = TestConsole::start(&["S01", "S02", "S03"], None).await;
+    let mut first = SimulatedSeat::new(1);
+    let mut second = SimulatedSeat::new(2);
+    console.heartbeat(&mut first, 0).await;
+    console.heartbeat(&mut second, 0).await;
+
+    let (status, command) = console
+        .command(
+            "S01",
+            json!({ "type": "lock", "reason": "phone on the desk" }),
+        )
+        .await;
+    assert_eq!(status, 202);
+    let (_, seat) = console.get("/api/seats/S01").await;
+    assert_eq!(seat["pending"][0]["id"], command["id"]);
+
+    // only the addressed seat gets it
+    assert!(console.heartbeat(&mut second, 0).await);
+    assert_eq!(second.status(), "active");
+    assert!(console.heartbeat(&mut first, 0).await);
+    assert_eq!(first.status(), "locked");
+
+    // the next heartbeat acknowledges it
+    console.heartbeat(&mut first, 0).await;
+    let (_, seat) = console.get("/api/seats/S01").await;
+    assert_eq!(seat["status"], "locked");
+    assert_eq!(seat["pending"], json!([]));
+
+    let (status, _) = console
+        .command(
+            "S02",
+            json!({ "type": "terminate", "reason": "end of exam" }),
+        )
+        .await;
+    assert_eq!(status, 202);
+    assert!(!console.heartbeat(&mut second, 0).await);
+    assert_eq!(second.status(), "ended");
+
+    // seats that are unknown or have not checked in take no commands
+    let (status, _) = console.command("S09", json!({ "type": "unlock" })).await;
+    assert_eq!(status, 404);
+    let (status, error) = console.command("S03", json!({ "type": "unlock" })).await;
+    assert_eq!(status, 404);
+    assert!(error["error"].as_str().unwrap().contains("not checked in"));
+}
+
+#[tokio::test]
+async fn the_api_needs_the_token_once_one_is_set() {
+    let console = TestConsole::start(&["S01"], Some(TOKEN)).await;
+    let (status, _) = console.get("/api/seats").await;
+    assert_eq!(status, 401);
+
+    let response = console
+        .client
+        .get(format!("{}/api/seats", console.url))
+        .bearer_auth(TOKEN)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // heartbeats are signed, not authorized by the token
+    let mut seat = SimulatedSeat::new(1);
+    assert!(console.heartbeat(&mut seat, 0).await);
+}
+
+#[tokio::test]
+async fn heartbeats_with_another_key_or_console_are_rejected() {
+    let console = TestConsole::start(&["S01"], None).await;
+    let url = format!("{}/heartbeat", console.url);
+    let mut seat = SimulatedSeat::new(1);
+
+    let heartbeat = seat.next(0).clone();
+    let err = send(
+        &console.client,
+        &url,
+        b"another key",
+        &console.console_key,
+        &heartbeat,
+    )
+    .await
+    .unwrap_err();
+    assert!(err.contains("401"), "{}", err);
+
+    // an answer signed by another console is not accepted
+    let other = public_key_hex(&SigningKey::from_bytes(&[10; 32]));
+    let heartbeat = seat.next(0).clone();
+    assert!(send(&console.client, &url, KEY, &other, &heartbeat)
+        .await
+        .is_err());
+
+    let (_, map) = console.get("/api/seats/S01").await;
+    assert_eq!(map["last_seq"], 2);
+}
+
+#[tokio::test]
+async fn a_second_session_on_a_live_seat_is_refused() {
+    let console = TestConsole::start(&["S01"], None).await;
+    let mut seat = SimulatedSeat::new(1);
+    // the second heartbeat reports a violation
+    console.heartbeat(&mut seat, 2).await;
+    console.heartbeat(&mut seat, 2).await;
+    let (status, _) = console
+        .command("S01", json!({ "type": "lock", "reason": "phone" }))
+        .await;
+    assert_eq!(status, 202);
+
+    // another machine set to the same seat
+    let mut other = SimulatedSeat::new(1);
+    let url = format!("{}/heartbeat", console.url);
+    let heartbeat = other.next(0).clone();
+    let err = send(&console.client, &url, KEY, &console.console_key, &heartbeat)
+        .await
+        .unwrap_err();
+    assert!(err.contains("409"), "{}", err);
+
+    let (_, map) = console.get("/api/seats/S01").await;
+    assert_eq!(map["duplicate"], true);
+    assert_eq!(map["last_seq"], 2);
+    assert_eq!(map["violations"].as_array().unwrap().len(), 1);
+    assert_eq!(map["pending"].as_array().unwrap().len(), 1);
+
+    // the live session still gets its command
+    assert!(console.heartbeat(&mut seat, 0).await);
+    assert_eq!(seat.status(), "locked");
+}
+
+#[tokio::test]
+async fn unlock_codes_open_the_challenge_they_were_issued_for() {
+    let console = TestConsole::start_with(&["S01"], Some(TOKEN), Some(UNLOCK_SECRET)).await;
+    let challenge = challenge("session", 1, Some(3), &[1; 16]);
+    let request = json!({ "challenge": challenge, "proctor": " Proctor 7 " });
+
+    let (status, _) = console.unlock_code(request.clone(), None).await;
+    assert_eq!(status, 401);
+
+    let (status, answer) = console.unlock_code(request, Some(TOKEN)).await;
+    assert_eq!(status, 200);
+    let code = answer["code"].as_str().unwrap();
+    assert_eq!(code.len(), 9);
+    assert_eq!(&code[4..5], "-");
+    assert_eq!(
+        verify_response(UNLOCK_SECRET, &challenge, "Proctor 7", code),
+        Ok(())
+    );
+    // the code is bound to the proctor it was issued to
+    assert!(verify_response(UNLOCK_SECRET, &challenge, "Proctor 8", code).is_err());
+
+    let (status, error) = console
+        .unlock_code(
+            json!({ "challenge": "7K2M", "proctor": "Proctor 7" }),
+            Some(TOKEN),
+        )
+        .await;
+    assert_eq!(status, 400);
+    assert!(error["error"]
+        .as_str()
+        .unwrap()
+        .contains("letters and digits"));
+    let (status, _) = console
+        .unlock_code(
+            json!({ "challenge": challenge, "proctor": "" }),
+            Some(TOKEN),
+        )
+        .await;
+    assert_eq!(status, 400);
+}
+
+#[tokio::test]
+async fn a_console_without_the_unlock_secret_issues_no_codes() {
+    let console = TestConsole::start(&["S01"], None).await;
+    let request = json!({
+        "challenge": challenge("session", 1, None, &[2; 16]),
+        "proctor": "Proctor 7",
+    });
+    let (status, _) = console.unlock_code(request, None).await;
+    assert_eq!(status, 404);
+}
+
+#[tokio::test]
+async fn heartbeats_are_matched_to_the_seats_machine() {
+    let console = TestConsole::start(&["S01"], None).await;
+    let url = format!("{}/heartbeat", console.url);
+    let mut seat = SimulatedSeat::new(1);
+    console.heartbeat(&mut seat, 0).await;
+
+    // a swapped network card is still the same machine
+    let mut heartbeat = seat.next(0).clone();
+    let mut swapped = fingerprint(1);
+    swapped
+        .components
+        .insert("primary_mac".into(), "another-card".into());
+    heartbeat.fingerprint = Some(swapped);
+    send(&console.client, &url, KEY, &console.console_key, &heartbeat)
+        .await
+        .unwrap();
+    let (_, map) = console.get("/api/seats/S01").await;
+    assert_eq!(map["other_machine"], false);
+    assert_eq!(
+        map["fingerprint"]["components"]["primary_mac"],
+        "another-card"
+    );
+
+    // the session carried on from another machine
+    let mut heartbeat = seat.next(0).clone();
+    heartbeat.fingerprint = Some(fingerprint(2));
+    send(&console.client, &url, KEY, &console.console_key, &heartbeat)
+        .await
+        .unwrap();
+    let (_, map) = console.get("/api/seats/S01").await;
+    assert_eq!(map["other_machine"], true);
+    // the seat's own machine is kept to match against
+    assert_eq!(map["fingerprint"]["id"], "simulated-1");
+}
@@ -0,0 +1,86 @@
+//! The mapper's end of the protocol in `shared::ipc`.
+//!
+//! Stdout carries protocol lines only, everything meant for people goes to stderr.
+
+use shared::ipc::{HostMessage, MapperMessage, decode, encode};
+use shared::keys::{Key, Rule};
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::mpsc::{Receiver, channel};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(2);
+
+/// When the app's last heartbeat arrived, in Unix milliseconds
+pub static HOST_SEEN_AT: AtomicI64 = AtomicI64::new(0);
+/// Set once stdin is closed, which happens when the app exits or is killed
+pub static HOST_CLOSED: AtomicBool = AtomicBool::new(false);
+
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as i64)
+        .unwrap_or_default()
+}
+
+pub fn send(message: &MapperMessage) {
+    // the lock keeps lines from different threads whole
+    let mut stdout = std::io::stdout().lock();
+    if let Err(err) = writeln!(stdout, "{}", encode(message)).and_then(|_| stdout.flush()) {
+        eprintln!("Could not write to the app: {}", err);
+    }
+}
+
+pub fn suppressed(key: &str, rule: &str) {
+    eprintln!("System key intercepted and suppressed ({}: {})", key, rule);
+    send(&MapperMessage::Suppressed {
+        key: key.to_string(),
+        rule: rule.to_string(),
+        at: now_ms(),
+    });
+}
+
+pub fn suppressed_by(key: Key, rule: &Rule) {
+    suppressed(&key.to_string(), &rule.to_string());
+}
+
+/// Reads the app's messages from stdin. `Configure` is handed to the returned channel,
+/// heartbeats update `HOST_SEEN_AT` and `Shutdown` exits right away.
+pub fn listen() -> Receiver<HostMessage> {
+    let (sender, receiver) = channel();
+    thread::spawn(move || {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match decode::<HostMessage>(&line) {
+                Ok(HostMessage::Shutdown) => {
+                    eprintln!("Shutdown requested by the app");
+                    std::process::exit(0);
+                }
+                Ok(HostMessage::Heartbeat { at }) => HOST_SEEN_AT.store(at, Ordering::SeqCst),
+                Ok(message) => {
+                    if sender.send(message).is_err() {
+                        eprintln!("Configuration received after startup is ignored");
+                    }
+                }
+                Err(message) => send(&MapperMessage::Error { message }),
+            }
+        }
+        HOST_CLOSED.store(true, Ordering::SeqCst);
+    });
+    receiver
+}
+
+pub fn start_heartbeat() {
+    thread::spawn(|| {
+        loop {
+            send(&MapperMessage::Heartbeat { at: now_ms() });
+            thread::sleep(HEARTBEAT_INTERVAL);
+        }
+    });
+}
@@ -1,7 +1,9 @@
 #[cfg(target_os = "windows")]
 //#[allow(unsafe_op_in_unsafe_fn)]
 pub mod key_mapper {
+    use shared::keys::{Decision, Key, Modifiers, RuleSet};
     use std::ptr::null_mut;
+    use std::sync::OnceLock;
     use windows::{
         core::*, Win32::Foundation::*, Win32::UI::Input::KeyboardAndMouse::*,
         Win32::UI::WindowsAndMessaging::*,
@@ -9,45 +11,73 @@ pub mod key_mapper {
 
     static mut HOOK_HANDLE: HHOOK = HHOOK(null_mut());
 
+    static RULES: OnceLock<RuleSet> = OnceLock::new();
+
     fn is_key_pressed(vk: VIRTUAL_KEY) -> bool {
         unsafe { (GetAsyncKeyState(vk.0 as i32) as u32 & 0x8000 as u32) != 0 }
     }
 
+    fn to_key(vk: u32) -> Key {
+        match vk {
+            vk if vk == VK_LWIN.0 as u32 || vk == VK_RWIN.0 as u32 => Key::Super,
+            vk if vk == VK_RMENU.0 as u32 => Key::AltGr,
+            vk if vk == VK_MENU.0 as u32 || vk == VK_LMENU.0 as u32 => Key::Alt,
+            vk if vk == VK_CONTROL.0 as u32
+                || vk == VK_LCONTROL.0 as u32
+                || vk == VK_RCONTROL.0 as u32 =>
+            {
+                Key::Ctrl
+            }
+            vk if vk == VK_SHIFT.0 as u32
+                || vk == VK_LSHIFT.0 as u32
+                || vk == VK_RSHIFT.0 as u32 =>
+            {
+                Key::Shift
+            }
+            vk if vk == VK_TAB.0 as u32 => Key::Tab,
+            vk if vk == VK_ESCAPE.0 as u32 => Key::Escape,
+            vk if vk == VK_DELETE.0 as u32 => Key::Delete,
+            vk if vk == VK_BACK.0 as u32 => Key::Backspace,
+            vk if vk == VK_RETURN.0 as u32 => Key::Enter,
+            vk if vk == VK_SPACE.0 as u32 => Key::Space,
+            vk if (VK_F1.0 as u32..=VK_F24.0 as u32).contains(&vk) => {
+                Key::F((vk - VK_F1.0 as u32 + 1) as u8)
+            }
+            // virtual key codes of digits and letters are their ascii codes
+            0x30..=0x39 | 0x41..=0x5A => Key::Char((vk as u8 as char).to_ascii_lowercase()),
+            other => Key::Other(other),
+        }
+    }
+
+    fn held_modifiers() -> Modifiers {
+        Modifiers {
+            ctrl: is_key_pressed(VK_CONTROL),
+            alt: is_key_pressed(VK_LMENU),
+            altgr: is_key_pressed(VK_RMENU),
+            shift: is_key_pressed(VK_SHIFT),
+            super_key: is_key_pressed(VK_LWIN) || is_key_pressed(VK_RWIN),
+        }
+    }
+
     #[allow(unsafe_op_in_unsafe_fn)]
     unsafe extern "system" fn keyboard_proc(code: i32, w_param: WPARAM, l_param: LPARAM) -> LRESULT {
         if code >= 0 && (w_param.0 == WM_KEYDOWN as usize || w_param.0 == WM_SYSKEYDOWN as usize) {
             let kb: &KBDLLHOOKSTRUCT = unsafe{ &*(l_param.0 as *const KBDLLHOOKSTRUCT) };
-            let vk = kb.vkCode;
-
-            let alt_down = is_key_pressed(VK_MENU);
-            let ctrl_down = is_key_pressed(VK_CONTROL);
-
-            let system_key = match vk {
-                vk if vk == VK_LWIN.0 as u32 || vk == VK_RWIN.0 as u32 => true,
-                vk if vk == VK_TAB.0 as u32 && alt_down => true,
-                vk if vk == VK_ESCAPE.0 as u32 && (alt_down || ctrl_down) => true,
-                vk if vk == VK_F4.0 as u32 && alt_down => true,
-                // Add all modifiers
-                vk if vk == VK_MENU.0 as u32 => true,
-                vk if vk == VK_DELETE.0 as u32 => true,
-                _ => false,
-            };
-
-            if is_key_pressed(VK_MENU) {
-                println!("System key intercepted and suppressed");
-                return LRESULT(1); // Suppressing the key
-            }
+            let key = to_key(kb.vkCode);
 
-            if system_key {
-                println!("System key intercepted and suppressed");
-                return LRESULT(1); // Suppressing the key
+            if let Some(rule) = RULES.get_or_init(RuleSet::default).matching(key, held_modifiers()) {
+                if rule.decision == Decision::Block {
+                    crate::ipc::suppressed_by(key, rule);
+                    return LRESULT(1); // Suppressing the key
+                }
             }
         }
         // pass the key to the system program
         unsafe { CallNextHookEx(Some(HHOOK(null_mut())), code, w_param, l_param) }
     }
 
-    pub fn capture_key() -> Result<()> {
+    pub fn capture_key(rules: RuleSet, ready: &dyn Fn(&str)) -> Result<()> {
+        let _ = RULES.set(rules);
         unsafe {
             HOOK_HANDLE = SetWindowsHookExW(
                 WH_KEYBOARD_LL,
@@ -59,7 +89,8 @@ pub mod key_mapper {
                 panic!("Failed to install hook");
             }
 
-            println!("Keyboard hook installed. Press Ctrl+C to exit.");
+            eprintln!("Keyboard hook installed. Press Ctrl+C to exit.");
+            ready("windows_hook");
 
             // Message loop to keep the hook alive
             let mut msg = MSG::default();
@@ -89,11 +120,24 @@ pub mod key_mapper {
 
 
 }
-#[cfg(not(target_os = "windows"))]
+pub mod ipc;
+pub mod watchdog;
+
+#[cfg(target_os = "linux")]
+pub mod linux;
+
+#[cfg(target_os = "linux")]
+pub mod key_mapper {
+    pub use crate::linux::capture_key;
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
 pub mod key_mapper{
+    use shared::keys::RuleSet;
     use std::fmt::Error;
 
-    pub fn capture_key() -> Result<(), Error>{
+    pub fn capture_key(_rules: RuleSet, ready: &dyn Fn(&str)) -> Result<(), Error>{
+        ready("none");
         Ok(())
     }
 }
@@ -0,0 +1,29 @@
+//! Keyboard capture on linux.
+//!
+//! Grabbing the input devices works under X11, Wayland and the console alike, so it is
+//! tried first. Without access to `/dev/input` the mapper falls back to key grabs on the X
+//! server, which leaves Wayland sessions unprotected.
+
+use shared::keys::RuleSet;
+
+pub mod evdev_grab;
+pub mod x11_grab;
+
+/// Custom rules are only applied by the evdev path, the X11 fallback grabs a fixed set.
+pub fn capture_key(rules: RuleSet, ready: &dyn Fn(&str)) -> Result<(), String> {
+    let evdev_err = match evdev_grab::capture_key(rules, ready) {
+        Ok(()) => return Ok(()),
+        Err(err) => err,
+    };
+    if std::env::var_os("DISPLAY").is_none() {
+        return Err(format!(
+            "could not grab the keyboard devices ({}) and no X display is set",
+            evdev_err
+        ));
+    }
+    eprintln!(
+        "Could not grab the keyboard devices ({}), using X11 key grabs",
+        evdev_err
+    );
+    x11_grab::capture_key(ready).map_err(|err| format!("X11 key grab failed: {}", err))
+}
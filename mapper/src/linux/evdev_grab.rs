@@ -0,0 +1,288 @@
+//! Keyboard capture below the display server.
+//!
+//! Every keyboard under `/dev/input` is grabbed exclusively, so neither X11, a Wayland
+//! compositor nor the console sees it directly. Events are read, checked against the
+//! [`Suppressor`] and whatever is allowed is replayed through a uinput virtual keyboard.
+//! `/dev/input` is watched with inotify, so a keyboard plugged in during the exam is grabbed
+//! as soon as it appears, and one that is unplugged is let go. Needs read access to
+//! `/dev/input` and write access to `/dev/uinput`.
+
+use evdev::uinput::VirtualDevice;
+use evdev::{AttributeSet, Device, EventType, InputEvent, KeyCode};
+use inotify::{Inotify, WatchMask};
+use shared::keys::{Filtered, Key, KeyAction, RuleSet, Suppressor};
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+const VIRTUAL_KEYBOARD_NAME: &str = "secure-browser mapper keyboard";
+const INPUT_DIR: &str = "/dev/input";
+/// Key codes below the mouse and joystick buttons, the virtual keyboard offers all of them
+/// since a keyboard plugged in later may have keys the first ones lack
+const KEYBOARD_CODES: std::ops::Range<u16> = 1..0x100;
+
+/// One suppressor for all keyboards, alt held on one keyboard counts for all of them
+type Output = Arc<Mutex<(Suppressor, VirtualDevice)>>;
+/// Device nodes of the keyboards being read
+type Grabbed = Arc<Mutex<HashSet<PathBuf>>>;
+
+/// Letter and digit keys by their position on a US layout, evdev codes carry no layout.
+const CHARACTER_KEYS: &[(KeyCode, char)] = &[
+    (KeyCode::KEY_A, 'a'),
+    (KeyCode::KEY_B, 'b'),
+    (KeyCode::KEY_C, 'c'),
+    (KeyCode::KEY_D, 'd'),
+    (KeyCode::KEY_E, 'e'),
+    (KeyCode::KEY_F, 'f'),
+    (KeyCode::KEY_G, 'g'),
+    (KeyCode::KEY_H, 'h'),
+    (KeyCode::KEY_I, 'i'),
+    (KeyCode::KEY_J, 'j'),
+    (KeyCode::KEY_K, 'k'),
+    (KeyCode::KEY_L, 'l'),
+    (KeyCode::KEY_M, 'm'),
+    (KeyCode::KEY_N, 'n'),
+    (KeyCode::KEY_O, 'o'),
+    (KeyCode::KEY_P, 'p'),
+    (KeyCode::KEY_Q, 'q'),
+    (KeyCode::KEY_R, 'r'),
+    (KeyCode::KEY_S, 's'),
+    (KeyCode::KEY_T, 't'),
+    (KeyCode::KEY_U, 'u'),
+    (KeyCode::KEY_V, 'v'),
+    (KeyCode::KEY_W, 'w'),
+    (KeyCode::KEY_X, 'x'),
+    (KeyCode::KEY_Y, 'y'),
+    (KeyCode::KEY_Z, 'z'),
+    (KeyCode::KEY_1, '1'),
+    (KeyCode::KEY_2, '2'),
+    (KeyCode::KEY_3, '3'),
+    (KeyCode::KEY_4, '4'),
+    (KeyCode::KEY_5, '5'),
+    (KeyCode::KEY_6, '6'),
+    (KeyCode::KEY_7, '7'),
+    (KeyCode::KEY_8, '8'),
+    (KeyCode::KEY_9, '9'),
+    (KeyCode::KEY_0, '0'),
+];
+
+fn to_key(code: KeyCode) -> Key {
+    match code {
+        KeyCode::KEY_LEFTMETA | KeyCode::KEY_RIGHTMETA => Key::Super,
+        KeyCode::KEY_LEFTALT => Key::Alt,
+        KeyCode::KEY_RIGHTALT => Key::AltGr,
+        KeyCode::KEY_LEFTCTRL | KeyCode::KEY_RIGHTCTRL => Key::Ctrl,
+        KeyCode::KEY_LEFTSHIFT | KeyCode::KEY_RIGHTSHIFT => Key::Shift,
+        KeyCode::KEY_TAB => Key::Tab,
+        KeyCode::KEY_ESC => Key::Escape,
+        KeyCode::KEY_DELETE => Key::Delete,
+        KeyCode::KEY_BACKSPACE => Key::Backspace,
+        KeyCode::KEY_ENTER => Key::Enter,
+        KeyCode::KEY_SPACE => Key::Space,
+        KeyCode::KEY_F1 => Key::F(1),
+        KeyCode::KEY_F2 => Key::F(2),
+        KeyCode::KEY_F3 => Key::F(3),
+        KeyCode::KEY_F4 => Key::F(4),
+        KeyCode::KEY_F5 => Key::F(5),
+        KeyCode::KEY_F6 => Key::F(6),
+        KeyCode::KEY_F7 => Key::F(7),
+        KeyCode::KEY_F8 => Key::F(8),
+        KeyCode::KEY_F9 => Key::F(9),
+        KeyCode::KEY_F10 => Key::F(10),
+        KeyCode::KEY_F11 => Key::F(11),
+        KeyCode::KEY_F12 => Key::F(12),
+        other => match CHARACTER_KEYS.iter().find(|(code, _)| *code == other) {
+            Some((_, c)) => Key::Char(*c),
+            None => Key::Other(other.code() as u32),
+        },
+    }
+}
+
+fn to_action(value: i32) -> KeyAction {
+    match value {
+        0 => KeyAction::Release,
+        2 => KeyAction::Repeat,
+        _ => KeyAction::Press,
+    }
+}
+
+/// Whether a name under `/dev/input` is an event device, the only ones that can be grabbed.
+fn is_event_node(name: &OsStr) -> bool {
+    name.to_str().is_some_and(|name| name.starts_with("event"))
+}
+
+fn is_keyboard(device: &Device) -> bool {
+    device.name() != Some(VIRTUAL_KEYBOARD_NAME)
+        && device
+            .supported_keys()
+            .is_some_and(|keys| keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_ENTER))
+}
+
+/// Grabs every keyboard and blocks forwarding their events, grabbing keyboards that are
+/// plugged in later as well. Fails without doing anything when no keyboard could be opened,
+/// e.g. for lack of permissions.
+pub fn capture_key(rules: RuleSet, ready: &dyn Fn(&str)) -> io::Result<()> {
+    // watching before listing the devices, so none plugged in between is missed
+    let mut inotify = Inotify::init()?;
+    inotify
+        .watches()
+        .add(INPUT_DIR, WatchMask::CREATE | WatchMask::ATTRIB)?;
+    let mut keyboards: Vec<(PathBuf, Device)> = evdev::enumerate()
+        .filter(|(_, device)| is_keyboard(device))
+        .collect();
+    if keyboards.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            "no keyboard under /dev/input could be opened",
+        ));
+    }
+
+    let mut keys = AttributeSet::<KeyCode>::new();
+    for code in KEYBOARD_CODES {
+        keys.insert(KeyCode(code));
+    }
+    for (_, keyboard) in &keyboards {
+        if let Some(supported) = keyboard.supported_keys() {
+            for key in supported.iter() {
+                keys.insert(key);
+            }
+        }
+    }
+    let output = VirtualDevice::builder()?
+        .name(VIRTUAL_KEYBOARD_NAME)
+        .with_keys(&keys)?
+        .build()?;
+
+    for (_, keyboard) in &mut keyboards {
+        keyboard.grab()?;
+    }
+    eprintln!("Keyboard grabbed on {} device(s).", keyboards.len());
+    ready("evdev");
+
+    let shared: Output = Arc::new(Mutex::new((Suppressor::new(rules), output)));
+    let grabbed = Grabbed::default();
+    for (path, keyboard) in keyboards {
+        spawn_reader(path, keyboard, &shared, &grabbed);
+    }
+    watch_hotplug(&mut inotify, &shared, &grabbed)
+}
+
+/// Forwards a grabbed keyboard's events until it is unplugged.
+fn spawn_reader(path: PathBuf, keyboard: Device, shared: &Output, grabbed: &Grabbed) {
+    if let Ok(mut grabbed) = grabbed.lock() {
+        grabbed.insert(path.clone());
+    }
+    let (shared, grabbed) = (shared.clone(), grabbed.clone());
+    thread::spawn(move || {
+        if let Err(err) = forward(keyboard, &shared) {
+            eprintln!("Stopped reading {}: {}", path.display(), err);
+        }
+        if let Ok(mut grabbed) = grabbed.lock() {
+            grabbed.remove(&path);
+        }
+    });
+}
+
+/// Grabs the keyboards that appear under `/dev/input`, for as long as it can be watched.
+fn watch_hotplug(inotify: &mut Inotify, shared: &Output, grabbed: &Grabbed) -> io::Result<()> {
+    let mut buffer = [0u8; 4096];
+    loop {
+        let paths: Vec<PathBuf> = inotify
+            .read_events_blocking(&mut buffer)?
+            .filter_map(|event| event.name)
+            .filter(|name| is_event_node(name))
+            .map(|name| Path::new(INPUT_DIR).join(name))
+            .collect();
+        for path in paths {
+            let known = grabbed
+                .lock()
+                .map_err(|_| io::Error::other("keyboard list poisoned"))?
+                .contains(&path);
+            if known {
+                continue;
+            }
+            // udev may not have given access yet, its ATTRIB event comes once it has
+            let Ok(mut device) = Device::open(&path) else {
+                continue;
+            };
+            if !is_keyboard(&device) {
+                continue;
+            }
+            match device.grab() {
+                Ok(()) => {
+                    eprintln!("Keyboard grabbed on {}.", path.display());
+                    spawn_reader(path, device, shared, grabbed);
+                }
+                Err(err) => eprintln!("Could not grab {}: {}", path.display(), err),
+            }
+        }
+    }
+}
+
+fn forward(mut keyboard: Device, shared: &Mutex<(Suppressor, VirtualDevice)>) -> io::Result<()> {
+    loop {
+        let events: Vec<InputEvent> = keyboard
+            .fetch_events()?
+            .filter(|event| event.event_type() == EventType::KEY)
+            .collect();
+        let mut shared = shared
+            .lock()
+            .map_err(|_| io::Error::other("keyboard state poisoned"))?;
+        let (suppressor, output) = &mut *shared;
+        for event in events {
+            let key = to_key(KeyCode(event.code()));
+            match suppressor.filter(key, to_action(event.value())) {
+                Filtered::Pass => {}
+                Filtered::Blocked(rule) => {
+                    crate::ipc::suppressed_by(key, &rule);
+                    continue;
+                }
+                Filtered::Swallowed => continue,
+            }
+            output.emit(&[event])?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn right_alt_is_altgr() {
+        assert_eq!(to_key(KeyCode::KEY_LEFTALT), Key::Alt);
+        assert_eq!(to_key(KeyCode::KEY_RIGHTALT), Key::AltGr);
+        assert_eq!(to_key(KeyCode::KEY_RIGHTCTRL), Key::Ctrl);
+        assert_eq!(to_key(KeyCode::KEY_RIGHTMETA), Key::Super);
+    }
+
+    #[test]
+    fn keys_map_by_position() {
+        assert_eq!(to_key(KeyCode::KEY_Q), Key::Char('q'));
+        assert_eq!(to_key(KeyCode::KEY_0), Key::Char('0'));
+        assert_eq!(to_key(KeyCode::KEY_F12), Key::F(12));
+        assert_eq!(to_key(KeyCode::KEY_BACKSPACE), Key::Backspace);
+        assert_eq!(
+            to_key(KeyCode::KEY_VOLUMEUP),
+            Key::Other(u32::from(KeyCode::KEY_VOLUMEUP.code()))
+        );
+    }
+
+    #[test]
+    fn values_map_to_actions() {
+        assert_eq!(to_action(0), KeyAction::Release);
+        assert_eq!(to_action(1), KeyAction::Press);
+        assert_eq!(to_action(2), KeyAction::Repeat);
+    }
+
+    #[test]
+    fn only_event_nodes_are_grabbed() {
+        assert!(is_event_node(OsStr::new("event3")));
+        assert!(!is_event_node(OsStr::new("mouse0")));
+        assert!(!is_event_node(OsStr::new("by-id")));
+        assert!(!is_event_node(OsStr::new("js0")));
+    }
+}
@@ -0,0 +1,125 @@
+//! Keyboard capture through the X server, used when the input devices cannot be grabbed.
+//!
+//! Key grabs cannot express a rule set, so this path grabs a fixed set of keys close to
+//! the default rules. The keys that start a system combo get a passive grab on the root
+//! window, so the X server sends them to the mapper instead of the window manager.
+//! Pressing a grabbed alt key turns into a grab of the whole keyboard until it is released,
+//! which blocks everything typed with it. Layouts with an AltGr level report the right alt
+//! key as `ISO_Level3_Shift`, which is not grabbed. The exam window keeps receiving every
+//! other key. Ctrl+Alt+F-keys are handled inside the X server before any grab applies, only
+//! the evdev path or `DontVTSwitch` in the server config stops them.
+
+use std::collections::HashMap;
+use std::error::Error;
+use x11rb::connection::Connection;
+use x11rb::protocol::Event;
+use x11rb::protocol::xproto::{ConnectionExt, GrabMode, Keycode, Keysym, ModMask};
+
+const XK_ESCAPE: Keysym = 0xff1b;
+const XK_DELETE: Keysym = 0xffff;
+const XK_ALT_L: Keysym = 0xffe9;
+const XK_ALT_R: Keysym = 0xffea;
+const XK_SUPER_L: Keysym = 0xffeb;
+const XK_SUPER_R: Keysym = 0xffec;
+
+/// Keys grabbed with any modifier held, with the name they are reported under.
+const ALWAYS: &[(Keysym, &str)] = &[
+    (XK_ALT_L, "alt"),
+    (XK_ALT_R, "alt"),
+    (XK_SUPER_L, "super"),
+    (XK_SUPER_R, "super"),
+    (XK_DELETE, "delete"),
+];
+/// Keys grabbed only together with ctrl.
+const WITH_CTRL: &[(Keysym, &str)] = &[(XK_ESCAPE, "escape")];
+const GRAB_RULE: &str = "x11 key grab";
+
+/// Caps lock and num lock change the modifier state, so every grab is repeated with them.
+fn lock_variants(modifiers: ModMask) -> [ModMask; 4] {
+    [
+        modifiers,
+        modifiers | ModMask::LOCK,
+        modifiers | ModMask::M2,
+        modifiers | ModMask::LOCK | ModMask::M2,
+    ]
+}
+
+/// Every keycode that produces `keysym` in any column of the keyboard mapping.
+fn keycodes_for(
+    keysym: Keysym,
+    min_keycode: Keycode,
+    per_keycode: u8,
+    keysyms: &[Keysym],
+) -> Vec<Keycode> {
+    keysyms
+        .chunks(per_keycode.max(1) as usize)
+        .enumerate()
+        .filter(|(_, syms)| syms.contains(&keysym))
+        .map(|(index, _)| min_keycode.saturating_add(index as u8))
+        .collect()
+}
+
+pub fn capture_key(ready: &dyn Fn(&str)) -> Result<(), Box<dyn Error>> {
+    let (conn, screen) = x11rb::connect(None)?;
+    let setup = conn.setup();
+    let root = setup.roots[screen].root;
+    let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+    let mapping = conn
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)?
+        .reply()?;
+
+    let grabs = ALWAYS
+        .iter()
+        .map(|(keysym, name)| (*keysym, *name, vec![ModMask::ANY]))
+        .chain(
+            WITH_CTRL
+                .iter()
+                .map(|(keysym, name)| (*keysym, *name, lock_variants(ModMask::CONTROL).to_vec())),
+        );
+    let mut grabbed = 0;
+    let mut names = HashMap::new();
+    for (keysym, name, modifier_sets) in grabs {
+        for keycode in keycodes_for(
+            keysym,
+            min_keycode,
+            mapping.keysyms_per_keycode,
+            &mapping.keysyms,
+        ) {
+            names.insert(keycode, name);
+            for modifiers in &modifier_sets {
+                // the window manager may already hold a grab on the same key
+                match conn
+                    .grab_key(
+                        false,
+                        root,
+                        *modifiers,
+                        keycode,
+                        GrabMode::ASYNC,
+                        GrabMode::ASYNC,
+                    )?
+                    .check()
+                {
+                    Ok(()) => grabbed += 1,
+                    Err(err) => eprintln!("Could not grab keycode {}: {:?}", keycode, err),
+                }
+            }
+        }
+    }
+    conn.flush()?;
+    if grabbed == 0 {
+        return Err("no system key could be grabbed".into());
+    }
+    eprintln!("Keyboard grabs installed on the X server.");
+    ready("x11");
+
+    // keys pressed while a grabbed alt is held arrive here too, they have no name of their own
+    loop {
+        if let Event::KeyPress(event) = conn.wait_for_event()? {
+            let key = match names.get(&event.detail) {
+                Some(name) => name.to_string(),
+                None => format!("key {}", event.detail),
+            };
+            crate::ipc::suppressed(&key, GRAB_RULE);
+        }
+    }
+}
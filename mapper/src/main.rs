@@ -1,4 +1,54 @@
+use mapper::ipc;
+use shared::ipc::{HostMessage, MapperMessage};
+use shared::keys::RuleSet;
+use std::time::Duration;
+
+/// How long to wait for the app's configuration before falling back to the built-in rules.
+const CONFIGURE_TIMEOUT: Duration = Duration::from_secs(5);
+
 fn main() {
-    mapper::key_mapper::capture_key()
-        .expect("Failed to capture key");
-}
\ No newline at end of file
+    let messages = ipc::listen();
+    let (rules, mode, watchdog) = match messages.recv_timeout(CONFIGURE_TIMEOUT) {
+        Ok(HostMessage::Configure {
+            rules,
+            mode,
+            watchdog,
+        }) => (rules, mode, watchdog),
+        _ => {
+            eprintln!("No configuration from the app, using the built-in rules");
+            (Vec::new(), "unknown".to_string(), None)
+        }
+    };
+    // later configuration is not applied, see `ipc::listen`
+    drop(messages);
+
+    let rules = if rules.is_empty() {
+        RuleSet::default()
+    } else {
+        RuleSet::parse(&rules.join("\n")).unwrap_or_else(|err| {
+            let message = format!("Invalid key rules: {}", err);
+            eprintln!("{}", message);
+            ipc::send(&MapperMessage::Error { message });
+            std::process::exit(64);
+        })
+    };
+    if let Some(Err(message)) = watchdog.map(mapper::watchdog::start) {
+        eprintln!("{}", message);
+        ipc::send(&MapperMessage::Error { message });
+    }
+    let rule_count = rules.rules().len();
+    let ready = move |backend: &str| {
+        ipc::send(&MapperMessage::Ready {
+            backend: backend.to_string(),
+            rules: rule_count,
+            mode: mode.clone(),
+        });
+        ipc::start_heartbeat();
+    };
+    if let Err(err) = mapper::key_mapper::capture_key(rules, &ready) {
+        let message = format!("Failed to capture key: {:?}", err);
+        eprintln!("{}", message);
+        ipc::send(&MapperMessage::Error { message });
+        std::process::exit(1);
+    }
+}
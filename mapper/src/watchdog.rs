@@ -0,0 +1,92 @@
+//! Watches the app and leaves a signed record when it goes away without a shutdown.
+//!
+//! A shutdown from the app exits the mapper before the watchdog can notice anything, so
+//! only an app that was killed, crashed or hung ends up here. See `shared::watchdog`.
+
+use crate::ipc::{HOST_CLOSED, HOST_SEEN_AT, now_ms};
+use shared::watchdog::{SignedRecord, TerminationRecord, WatchdogConfig};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Duration;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Starts watching the app on its own thread.
+pub fn start(config: WatchdogConfig) -> Result<(), String> {
+    let key = config.key_bytes()?;
+    // the first heartbeat is due one timeout after the configuration
+    HOST_SEEN_AT.store(now_ms(), Ordering::SeqCst);
+    eprintln!("Watching the app, pid {}", config.pid);
+    thread::spawn(move || {
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let last_heartbeat_at = HOST_SEEN_AT.load(Ordering::SeqCst);
+            let reason = if HOST_CLOSED.load(Ordering::SeqCst) {
+                "stdin_closed"
+            } else if now_ms() - last_heartbeat_at > config.timeout_ms as i64 {
+                "heartbeat_timeout"
+            } else {
+                continue;
+            };
+            on_host_lost(&config, &key, reason, last_heartbeat_at);
+            // the grabs go with the process, the relaunched app starts its own mapper
+            std::process::exit(0);
+        }
+    });
+    Ok(())
+}
+
+fn on_host_lost(config: &WatchdogConfig, key: &[u8], reason: &str, last_heartbeat_at: i64) {
+    eprintln!("The app is gone ({}), writing a termination record", reason);
+    let mut record = TerminationRecord {
+        session_id: config.session_id.clone(),
+        pid: config.pid,
+        last_heartbeat_at,
+        detected_at: now_ms(),
+        reason: reason.to_string(),
+        relaunched: !config.relaunch.is_empty(),
+    };
+    // the record goes first, the relaunched app collects the records as it starts
+    let dir = Path::new(&config.record_dir);
+    write_record(dir, &record, key);
+    if record.relaunched && !relaunch(&config.relaunch) {
+        record.relaunched = false;
+        write_record(dir, &record, key);
+    }
+}
+
+fn write_record(dir: &Path, record: &TerminationRecord, key: &[u8]) {
+    let path = dir.join(record.file_name());
+    let written = std::fs::create_dir_all(dir).and_then(|_| {
+        let json = serde_json::to_string(&SignedRecord::sign(record, key))?;
+        std::fs::write(&path, json)
+    });
+    if let Err(err) = written {
+        eprintln!("Could not write {}: {}", path.display(), err);
+    }
+}
+
+fn relaunch(command: &[String]) -> bool {
+    let Some((program, args)) = command.split_first() else {
+        return false;
+    };
+    // the app's end of our pipes is gone, the new instance must not inherit them
+    match Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => {
+            eprintln!("Relaunched the app, pid {}", child.id());
+            true
+        }
+        Err(err) => {
+            eprintln!("Could not relaunch {}: {}", program, err);
+            false
+        }
+    }
+}
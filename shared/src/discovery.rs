@@ -0,0 +1,83 @@
+//! Finding the proctor console on the local network.
+//!
+//! The console advertises [`SERVICE_TYPE`] over mDNS with the heartbeat path in its TXT
+//! record. Anyone on the LAN can advertise that service, so before using a console the app
+//! sends it a random nonce on [`IDENTITY_PATH`] and checks the [`IdentityProof`] against the
+//! ed25519 key pinned in the policy. The key in the TXT record is only a hint for people
+//! looking at the network.
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::net::IpAddr;
+
+pub const SERVICE_TYPE: &str = "_secure-proctor._tcp.local.";
+/// TXT property with the heartbeat path, e.g. `/heartbeat`
+pub const PATH_PROPERTY: &str = "path";
+/// TXT property with the console's public key in hex
+pub const KEY_PROPERTY: &str = "key";
+/// Answers `GET <path>?nonce=<hex>` with an [`IdentityProof`]
+pub const IDENTITY_PATH: &str = "/identity";
+pub const NONCE_LENGTH: usize = 32;
+const IDENTITY_CONTEXT: &[u8] = b"secure-proctor-identity\n";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IdentityProof {
+    /// Hex ed25519 public key of the console
+    pub public_key: String,
+    /// Hex signature over the context string and the nonce
+    pub signature: String,
+}
+
+fn identity_message(nonce: &[u8]) -> Vec<u8> {
+    [IDENTITY_CONTEXT, nonce].concat()
+}
+
+pub fn public_key_hex(key: &SigningKey) -> String {
+    hex::encode(key.verifying_key().to_bytes())
+}
+
+pub fn sign_identity(key: &SigningKey, nonce: &[u8]) -> IdentityProof {
+    IdentityProof {
+        public_key: public_key_hex(key),
+        signature: hex::encode(key.sign(&identity_message(nonce)).to_bytes()),
+    }
+}
+
+/// Parses a hex ed25519 public key, as pinned in the app's policy.
+pub fn parse_public_key(pinned: &str) -> Result<VerifyingKey, String> {
+    let pinned = hex::decode(pinned.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or("pinned console key is not a hex ed25519 public key")?;
+    VerifyingKey::from_bytes(&pinned)
+        .map_err(|err| format!("pinned console key is invalid: {}", err))
+}
+
+/// Checks that the proof is for `nonce` and made with the pinned key.
+pub fn verify_identity(pinned: &str, nonce: &[u8], proof: &IdentityProof) -> Result<(), String> {
+    let key = parse_public_key(pinned)?;
+    if !proof
+        .public_key
+        .trim()
+        .eq_ignore_ascii_case(&hex::encode(key.to_bytes()))
+    {
+        return Err(format!(
+            "console identifies as {}, not the pinned key",
+            proof.public_key
+        ));
+    }
+    let signature = hex::decode(proof.signature.trim())
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("identity signature is malformed")?;
+    key.verify(&identity_message(nonce), &signature)
+        .map_err(|_| "identity signature does not match".to_string())
+}
+
+/// Base URL of a console found at `address`, without a trailing slash.
+pub fn base_url(address: IpAddr, port: u16) -> String {
+    match address {
+        IpAddr::V4(address) => format!("http://{}:{}", address, port),
+        IpAddr::V6(address) => format!("http://[{}]:{}", address, port),
+    }
+}
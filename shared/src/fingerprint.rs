@@ -0,0 +1,40 @@
+//! Machine fingerprints as they travel in heartbeats, and how two of them are compared.
+//!
+//! The app builds the fingerprint, see `src-tauri/src/utils/fingerprint.rs`: one salted hash
+//! per hardware component, so a single changed part still matches and the raw serials never
+//! leave the machine.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Minimum number of components both fingerprints must have for a comparison to mean anything.
+pub const MIN_SHARED_COMPONENTS: usize = 3;
+/// How many shared components may differ before two fingerprints stop matching.
+pub const MAX_CHANGED_COMPONENTS: usize = 1;
+
+/// A salted hash of the machine's hardware identity.
+/// `components` maps each component name to its own hash so a single changed part can be tolerated.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceFingerprint {
+    pub id: String,
+    pub components: BTreeMap<String, String>,
+}
+
+/// Whether two fingerprints belong to the same machine, allowing one component to have changed.
+/// Fingerprints with too few components never match, not even equal ones: machines where
+/// almost nothing can be read would all look alike.
+pub fn is_same_machine(known: &DeviceFingerprint, current: &DeviceFingerprint) -> bool {
+    let shared: Vec<&String> = known
+        .components
+        .keys()
+        .filter(|name| current.components.contains_key(*name))
+        .collect();
+    if shared.len() < MIN_SHARED_COMPONENTS {
+        return false;
+    }
+    let changed = shared
+        .iter()
+        .filter(|name| known.components.get(**name) != current.components.get(**name))
+        .count();
+    changed <= MAX_CHANGED_COMPONENTS
+}
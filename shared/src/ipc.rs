@@ -0,0 +1,93 @@
+//! Messages between the app and the mapper sidecar.
+//!
+//! Both sides write one JSON object per line, the app on the mapper's stdin and the mapper
+//! on its stdout. Every line carries the protocol version in `v` and the message kind in
+//! `type`, e.g. `{"v":1,"type":"heartbeat","at":1718000000000}`. A line with another version
+//! is rejected rather than guessed at. Human readable logs go to stderr.
+
+use crate::watchdog::WatchdogConfig;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// Sent by the app to the mapper.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum HostMessage {
+    /// The rules to enforce, in the `shared::keys` syntax, and the app mode they come from.
+    /// An empty rule list selects the built-in rules. With `watchdog` set the mapper also
+    /// watches the app, see `shared::watchdog`.
+    Configure {
+        rules: Vec<String>,
+        mode: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        watchdog: Option<WatchdogConfig>,
+    },
+    /// The app is alive. `at` is a Unix timestamp in milliseconds.
+    Heartbeat { at: i64 },
+    /// Release the keyboard and exit.
+    Shutdown,
+}
+
+/// Sent by the mapper to the app.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum MapperMessage {
+    /// The keyboard is captured and the rules are applied.
+    Ready {
+        /// How the keyboard is captured, e.g. `windows_hook`, `evdev` or `x11`
+        backend: String,
+        rules: usize,
+        mode: String,
+    },
+    /// A key press was blocked. `at` is a Unix timestamp in milliseconds.
+    Suppressed {
+        key: String,
+        rule: String,
+        at: i64,
+    },
+    Heartbeat {
+        at: i64,
+    },
+    /// Something went wrong that the app should know about, the mapper may still be running.
+    Error {
+        message: String,
+    },
+}
+
+#[derive(Serialize)]
+struct OutgoingLine<'a, T> {
+    v: u32,
+    #[serde(flatten)]
+    message: &'a T,
+}
+
+#[derive(Deserialize)]
+struct Version {
+    v: u32,
+}
+
+/// One protocol line for `message`, without the trailing newline.
+pub fn encode<T: Serialize>(message: &T) -> String {
+    serde_json::to_string(&OutgoingLine {
+        v: PROTOCOL_VERSION,
+        message,
+    })
+    .expect("protocol messages always serialize")
+}
+
+pub fn decode<T: DeserializeOwned>(line: &str) -> Result<T, String> {
+    let line = line.trim();
+    // the version is checked first so a newer peer gets a clear error, not a parse failure
+    let version: Version =
+        serde_json::from_str(line).map_err(|err| format!("invalid protocol line: {}", err))?;
+    if version.v != PROTOCOL_VERSION {
+        return Err(format!(
+            "protocol version {} is not supported, expected {}",
+            version.v, PROTOCOL_VERSION
+        ));
+    }
+    // `v` is not part of any message and is skipped as an unknown field
+    serde_json::from_str(line).map_err(|err| format!("invalid protocol message: {}", err))
+}
@@ -0,0 +1,552 @@
+//! Key suppression rules.
+//!
+//! A rule set is a list of `block <combo>` and `allow <combo>` lines, separated by newlines
+//! or `;`, with `#` starting a comment. A combo is any number of modifiers followed by a key
+//! name or `any`, e.g. `block alt+tab` or `allow altgr+any`. Rules are checked in order and
+//! the first one matching a key press decides, a key no rule matches is allowed.
+//!
+//! The modifiers of a rule only have to be held, others may be held as well, so `block
+//! alt+tab` also blocks alt+shift+tab. `alt` matches either alt key and `altgr` only the
+//! right one, which many keyboard layouts use to type characters.
+
+use std::collections::HashSet;
+use std::fmt;
+
+/// The rules used when the policy sets none. They block what the Windows hook always
+/// blocked and the Linux console switches, and only then let characters typed with AltGr
+/// through. `alt` matches AltGr too, so the blocks above the allow hold for either alt key.
+pub const DEFAULT_RULES: &str = "\
+block super+any
+block alt+tab
+block alt+f4
+block alt+escape
+block alt+space
+block ctrl+escape
+block delete
+block ctrl+alt+backspace
+block ctrl+alt+f1
+block ctrl+alt+f2
+block ctrl+alt+f3
+block ctrl+alt+f4
+block ctrl+alt+f5
+block ctrl+alt+f6
+block ctrl+alt+f7
+block ctrl+alt+f8
+block ctrl+alt+f9
+block ctrl+alt+f10
+block ctrl+alt+f11
+block ctrl+alt+f12
+allow altgr+any
+block alt+any
+";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Key {
+    Super,
+    /// Left alt
+    Alt,
+    /// Right alt
+    AltGr,
+    Ctrl,
+    Shift,
+    Tab,
+    Escape,
+    Delete,
+    Backspace,
+    Enter,
+    Space,
+    /// Function key by number, `F(4)` is F4
+    F(u8),
+    /// Letter or digit key, always lowercase
+    Char(char),
+    /// Any other key, by its native code
+    Other(u32),
+}
+
+impl Key {
+    pub fn parse(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let key = match name.as_str() {
+            "super" | "win" | "meta" | "cmd" => Self::Super,
+            "alt" => Self::Alt,
+            "altgr" => Self::AltGr,
+            "ctrl" | "control" => Self::Ctrl,
+            "shift" => Self::Shift,
+            "tab" => Self::Tab,
+            "escape" | "esc" => Self::Escape,
+            "delete" | "del" => Self::Delete,
+            "backspace" => Self::Backspace,
+            "enter" | "return" => Self::Enter,
+            "space" => Self::Space,
+            _ => {
+                if let Some(number) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    return (1..=24).contains(&number).then_some(Self::F(number));
+                }
+                let mut chars = name.chars();
+                return match (chars.next(), chars.next()) {
+                    (Some(c), None) if c.is_ascii_alphanumeric() => Some(Self::Char(c)),
+                    _ => None,
+                };
+            }
+        };
+        Some(key)
+    }
+
+    fn modifier(self) -> Option<Modifier> {
+        match self {
+            Self::Ctrl => Some(Modifier::Ctrl),
+            Self::Alt => Some(Modifier::Alt),
+            Self::AltGr => Some(Modifier::AltGr),
+            Self::Shift => Some(Modifier::Shift),
+            Self::Super => Some(Modifier::Super),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Super => f.write_str("super"),
+            Self::Alt => f.write_str("alt"),
+            Self::AltGr => f.write_str("altgr"),
+            Self::Ctrl => f.write_str("ctrl"),
+            Self::Shift => f.write_str("shift"),
+            Self::Tab => f.write_str("tab"),
+            Self::Escape => f.write_str("escape"),
+            Self::Delete => f.write_str("delete"),
+            Self::Backspace => f.write_str("backspace"),
+            Self::Enter => f.write_str("enter"),
+            Self::Space => f.write_str("space"),
+            Self::F(number) => write!(f, "f{}", number),
+            Self::Char(c) => write!(f, "{}", c),
+            Self::Other(code) => write!(f, "key {}", code),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Modifier {
+    Ctrl,
+    Alt,
+    AltGr,
+    Shift,
+    Super,
+}
+
+impl Modifier {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Ctrl => "ctrl",
+            Self::Alt => "alt",
+            Self::AltGr => "altgr",
+            Self::Shift => "shift",
+            Self::Super => "super",
+        }
+    }
+}
+
+/// The modifier keys held down.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub ctrl: bool,
+    /// Left alt only, see `altgr`
+    pub alt: bool,
+    pub altgr: bool,
+    pub shift: bool,
+    pub super_key: bool,
+}
+
+impl Modifiers {
+    fn holds(&self, modifier: Modifier) -> bool {
+        match modifier {
+            Modifier::Ctrl => self.ctrl,
+            Modifier::Alt => self.alt || self.altgr,
+            Modifier::AltGr => self.altgr,
+            Modifier::Shift => self.shift,
+            Modifier::Super => self.super_key,
+        }
+    }
+
+    /// Updates the state for `key` going down or up, other keys are ignored.
+    pub fn set(&mut self, key: Key, down: bool) {
+        match key.modifier() {
+            Some(Modifier::Ctrl) => self.ctrl = down,
+            Some(Modifier::Alt) => self.alt = down,
+            Some(Modifier::AltGr) => self.altgr = down,
+            Some(Modifier::Shift) => self.shift = down,
+            Some(Modifier::Super) => self.super_key = down,
+            None => {}
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    Block,
+    Allow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rule {
+    pub decision: Decision,
+    pub modifiers: Vec<Modifier>,
+    /// `None` matches any key
+    pub key: Option<Key>,
+}
+
+impl Rule {
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let decision = match words.next() {
+            Some("block") => Decision::Block,
+            Some("allow") => Decision::Allow,
+            Some(other) => return Err(format!("expected `block` or `allow`, found `{}`", other)),
+            None => return Err("empty rule".into()),
+        };
+        let combo = words.next().ok_or("missing key combination")?;
+        if let Some(extra) = words.next() {
+            return Err(format!("unexpected `{}` after the key combination", extra));
+        }
+
+        let mut parts: Vec<&str> = combo.split('+').collect();
+        let last = parts.pop().unwrap_or_default();
+        let key = if last.eq_ignore_ascii_case("any") {
+            None
+        } else {
+            Some(Key::parse(last).ok_or(format!("unknown key `{}`", last))?)
+        };
+        let modifiers = parts
+            .into_iter()
+            .map(|part| {
+                Key::parse(part)
+                    .and_then(Key::modifier)
+                    .ok_or(format!("`{}` is not a modifier", part))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            decision,
+            modifiers,
+            key,
+        })
+    }
+
+    /// Whether the rule covers `key` pressed while `held` is down.
+    pub fn matches(&self, key: Key, held: Modifiers) -> bool {
+        let key_matches = match self.key {
+            Some(rule_key) => rule_key == key,
+            None => true,
+        };
+        key_matches && self.modifiers.iter().all(|modifier| held.holds(*modifier))
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decision = match self.decision {
+            Decision::Block => "block",
+            Decision::Allow => "allow",
+        };
+        write!(f, "{} ", decision)?;
+        for modifier in &self.modifiers {
+            write!(f, "{}+", modifier.name())?;
+        }
+        match self.key {
+            Some(key) => write!(f, "{}", key),
+            None => f.write_str("any"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuleSet {
+    rules: Vec<Rule>,
+}
+
+impl RuleSet {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        for (index, line) in text.split(['\n', ';']).enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let rule = Rule::parse(line)
+                .map_err(|err| format!("rule {} `{}`: {}", index + 1, line, err))?;
+            rules.push(rule);
+        }
+        Ok(Self { rules })
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// The first rule covering `key`. A modifier key counts as held while it is pressed.
+    pub fn matching(&self, key: Key, held: Modifiers) -> Option<&Rule> {
+        let mut held = held;
+        held.set(key, true);
+        self.rules.iter().find(|rule| rule.matches(key, held))
+    }
+
+    pub fn evaluate(&self, key: Key, held: Modifiers) -> Decision {
+        self.matching(key, held)
+            .map_or(Decision::Allow, |rule| rule.decision)
+    }
+}
+
+impl Default for RuleSet {
+    fn default() -> Self {
+        Self::parse(DEFAULT_RULES).expect("default rules are valid")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Press,
+    Repeat,
+    Release,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Filtered {
+    Pass,
+    /// A press blocked by the rule
+    Blocked(Rule),
+    /// The repeat or release of a blocked press
+    Swallowed,
+}
+
+/// Runs a rule set over a stream of key events for hooks that only see one key at a time.
+/// Tracks the held modifiers itself, and keeps a blocked key's repeats and release from
+/// getting through on their own.
+#[derive(Debug, Default)]
+pub struct Suppressor {
+    rules: RuleSet,
+    held: Modifiers,
+    swallowed: HashSet<Key>,
+}
+
+impl Suppressor {
+    pub fn new(rules: RuleSet) -> Self {
+        Self {
+            rules,
+            held: Modifiers::default(),
+            swallowed: HashSet::new(),
+        }
+    }
+
+    /// What to do with the event. A blocked press returns the rule that blocked it.
+    pub fn filter(&mut self, key: Key, action: KeyAction) -> Filtered {
+        match action {
+            KeyAction::Press => {
+                let blocked_by = self
+                    .rules
+                    .matching(key, self.held)
+                    .filter(|rule| rule.decision == Decision::Block)
+                    .cloned();
+                self.held.set(key, true);
+                match blocked_by {
+                    Some(rule) => {
+                        self.swallowed.insert(key);
+                        Filtered::Blocked(rule)
+                    }
+                    None => Filtered::Pass,
+                }
+            }
+            KeyAction::Repeat if self.swallowed.contains(&key) => Filtered::Swallowed,
+            KeyAction::Repeat => Filtered::Pass,
+            KeyAction::Release => {
+                self.held.set(key, false);
+                if self.swallowed.remove(&key) {
+                    Filtered::Swallowed
+                } else {
+                    Filtered::Pass
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `key` with the modifiers in `combo` held, e.g. `ctrl+altgr+f1`.
+    fn press(combo: &str) -> (Key, Modifiers) {
+        let mut parts: Vec<&str> = combo.split('+').collect();
+        let key = Key::parse(parts.pop().unwrap()).unwrap();
+        let mut held = Modifiers::default();
+        for part in parts {
+            held.set(Key::parse(part).unwrap(), true);
+        }
+        (key, held)
+    }
+
+    fn decide(rules: &RuleSet, combo: &str) -> Decision {
+        let (key, held) = press(combo);
+        rules.evaluate(key, held)
+    }
+
+    #[test]
+    fn default_rules_block_system_combos() {
+        let rules = RuleSet::default();
+        for combo in [
+            "super+l",
+            "super+d",
+            "alt+tab",
+            "alt+shift+tab",
+            "alt+f4",
+            "alt+escape",
+            "alt+space",
+            "ctrl+escape",
+            "delete",
+            "ctrl+alt+delete",
+            "ctrl+alt+backspace",
+            "ctrl+alt+f1",
+            "ctrl+alt+f7",
+            "ctrl+alt+f12",
+            "alt+x",
+        ] {
+            assert_eq!(decide(&rules, combo), Decision::Block, "{}", combo);
+        }
+    }
+
+    #[test]
+    fn default_rules_block_system_combos_with_altgr() {
+        let rules = RuleSet::default();
+        for combo in [
+            "altgr+tab",
+            "altgr+f4",
+            "altgr+escape",
+            "altgr+space",
+            "ctrl+altgr+delete",
+            "ctrl+altgr+backspace",
+            "ctrl+altgr+f1",
+            "ctrl+altgr+f12",
+            "super+altgr+e",
+        ] {
+            assert_eq!(decide(&rules, combo), Decision::Block, "{}", combo);
+        }
+    }
+
+    #[test]
+    fn default_rules_let_typing_through() {
+        let rules = RuleSet::default();
+        for combo in [
+            "a",
+            "shift+a",
+            "ctrl+c",
+            "backspace",
+            "f5",
+            "altgr+e",
+            "altgr+q",
+            "altgr+2",
+            // windows reports altgr as ctrl with the right alt
+            "ctrl+altgr+e",
+            "shift+altgr+7",
+        ] {
+            assert_eq!(decide(&rules, combo), Decision::Allow, "{}", combo);
+        }
+    }
+
+    #[test]
+    fn first_matching_rule_decides() {
+        let rules = RuleSet::parse("allow alt+f4; block alt+any").unwrap();
+        assert_eq!(decide(&rules, "alt+f4"), Decision::Allow);
+        assert_eq!(decide(&rules, "alt+f5"), Decision::Block);
+        assert_eq!(decide(&rules, "f5"), Decision::Allow);
+    }
+
+    #[test]
+    fn rules_parse_and_print() {
+        let rules = RuleSet::parse("block ctrl+Alt+F1 # console\n\nallow altgr+any;").unwrap();
+        let printed: Vec<String> = rules.rules().iter().map(Rule::to_string).collect();
+        assert_eq!(printed, ["block ctrl+alt+f1", "allow altgr+any"]);
+    }
+
+    #[test]
+    fn bad_rules_are_errors() {
+        for (text, error) in [
+            ("deny alt+tab", "expected `block` or `allow`"),
+            ("block", "missing key combination"),
+            ("block alt+tab now", "unexpected `now`"),
+            ("block alt+nope", "unknown key `nope`"),
+            ("block tab+alt", "`tab` is not a modifier"),
+            ("block f25", "unknown key `f25`"),
+        ] {
+            let err = RuleSet::parse(text).unwrap_err();
+            assert!(err.contains(error), "{}: {}", text, err);
+        }
+    }
+
+    #[test]
+    fn suppressor_swallows_a_blocked_key_until_released() {
+        let mut suppressor = Suppressor::default();
+        assert_eq!(
+            suppressor.filter(Key::Ctrl, KeyAction::Press),
+            Filtered::Pass
+        );
+        assert!(matches!(
+            suppressor.filter(Key::Escape, KeyAction::Press),
+            Filtered::Blocked(rule) if rule.to_string() == "block ctrl+escape"
+        ));
+        assert_eq!(
+            suppressor.filter(Key::Escape, KeyAction::Repeat),
+            Filtered::Swallowed
+        );
+        // letting go of ctrl first does not let the held escape through
+        assert_eq!(
+            suppressor.filter(Key::Ctrl, KeyAction::Release),
+            Filtered::Pass
+        );
+        assert_eq!(
+            suppressor.filter(Key::Escape, KeyAction::Repeat),
+            Filtered::Swallowed
+        );
+        assert_eq!(
+            suppressor.filter(Key::Escape, KeyAction::Release),
+            Filtered::Swallowed
+        );
+        assert_eq!(
+            suppressor.filter(Key::Escape, KeyAction::Press),
+            Filtered::Pass
+        );
+        assert_eq!(
+            suppressor.filter(Key::Escape, KeyAction::Release),
+            Filtered::Pass
+        );
+    }
+
+    #[test]
+    fn suppressor_tracks_modifiers_across_events() {
+        let mut suppressor = Suppressor::default();
+        for modifier in [Key::Ctrl, Key::AltGr] {
+            assert_eq!(
+                suppressor.filter(modifier, KeyAction::Press),
+                Filtered::Pass
+            );
+        }
+        assert!(matches!(
+            suppressor.filter(Key::F(2), KeyAction::Press),
+            Filtered::Blocked(_)
+        ));
+        assert_eq!(
+            suppressor.filter(Key::F(2), KeyAction::Release),
+            Filtered::Swallowed
+        );
+        assert_eq!(
+            suppressor.filter(Key::Ctrl, KeyAction::Release),
+            Filtered::Pass
+        );
+        // altgr alone is allowed for typing
+        assert_eq!(
+            suppressor.filter(Key::Char('e'), KeyAction::Press),
+            Filtered::Pass
+        );
+        assert_eq!(
+            suppressor.filter(Key::Char('e'), KeyAction::Repeat),
+            Filtered::Pass
+        );
+    }
+}
@@ -0,0 +1,10 @@
+//! Code used by the app in `src-tauri`, the `mapper` sidecar and the proctor `console`.
+
+pub mod discovery;
+pub mod fingerprint;
+pub mod ipc;
+pub mod keys;
+pub mod proctor;
+pub mod release;
+pub mod unlock;
+pub mod watchdog;
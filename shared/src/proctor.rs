@@ -0,0 +1,263 @@
+//! Heartbeats between the app and the proctoring server.
+//!
+//! The app posts a [`Heartbeat`] as JSON to the proctor endpoint every few seconds and the
+//! server answers with a [`HeartbeatResponse`] carrying any commands for the seat. Heartbeats
+//! are signed with HMAC-SHA256 under the deployment's proctor key. Responses carry the
+//! commands, so they are signed with the server's ed25519 identity key instead and checked
+//! against the key pinned in the app's policy: a seat that knows the proctor key still cannot
+//! forge commands for the others. Both signatures are hex in the [`SIGNATURE_HEADER`].
+//!
+//! A response must echo the heartbeat's `seq`, `session_id` and random `nonce`, so a recorded
+//! response replayed to the app, in the same run or a later one, is rejected along with its
+//! commands.
+
+use crate::discovery::parse_public_key;
+use crate::fingerprint::DeviceFingerprint;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+pub const SIGNATURE_HEADER: &str = "X-Proctor-Signature";
+/// Environment variable holding the proctor key, read by the app and the console
+pub const KEY_ENV: &str = "SECURE_BROWSER_PROCTOR_KEY";
+/// Random bytes in every heartbeat's `nonce`
+pub const NONCE_LENGTH: usize = 16;
+const RESPONSE_CONTEXT: &[u8] = b"secure-proctor-response\n";
+
+/// A violation the server has not acknowledged yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ViolationReport {
+    pub id: u64,
+    /// `ViolationKind` in snake case, e.g. `focus_lost`
+    pub kind: String,
+    pub detail: String,
+    pub occurred_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Counts up from 1 for every heartbeat of a session
+    pub seq: u64,
+    /// Fresh random hex for every heartbeat, echoed by the response
+    pub nonce: String,
+    pub session_id: String,
+    pub seat: Option<String>,
+    /// Salted hashes of the machine's hardware components, `None` without a deployment salt
+    pub fingerprint: Option<DeviceFingerprint>,
+    /// `SessionStatus` in snake case, e.g. `active`
+    pub status: String,
+    pub mode: String,
+    pub violation_count: usize,
+    /// Violations after the last one the server acknowledged
+    pub violations: Vec<ViolationReport>,
+    /// Ids of commands carried out since the previous heartbeat
+    pub acked_commands: Vec<String>,
+    /// Challenge on the lock screen while locked, see `crate::unlock`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlock_challenge: Option<String>,
+    /// Unix timestamp in milliseconds
+    pub sent_at: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CommandAction {
+    /// Block the exam until an unlock
+    Lock {
+        reason: String,
+    },
+    Unlock,
+    /// Add time to the exam, the page keeps the timer
+    ExtendTime {
+        minutes: u32,
+    },
+    /// End the session and close the app
+    Terminate {
+        reason: String,
+    },
+    /// Show a message to the candidate
+    Message {
+        text: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProctorCommand {
+    /// Unique per command, the app carries out each id once
+    pub id: String,
+    #[serde(flatten)]
+    pub action: CommandAction,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeartbeatResponse {
+    /// `seq`, `session_id` and `nonce` of the heartbeat this answers
+    pub seq: u64,
+    pub session_id: String,
+    pub nonce: String,
+    #[serde(default)]
+    pub commands: Vec<ProctorCommand>,
+    /// Highest violation id the server has stored
+    #[serde(default)]
+    pub acked_violation: Option<u64>,
+}
+
+impl HeartbeatResponse {
+    /// An empty response to `heartbeat`.
+    pub fn to(heartbeat: &Heartbeat) -> Self {
+        Self {
+            seq: heartbeat.seq,
+            session_id: heartbeat.session_id.clone(),
+            nonce: heartbeat.nonce.clone(),
+            ..Self::default()
+        }
+    }
+
+    /// Checks that this answers `heartbeat` and not an earlier one.
+    pub fn answers(&self, heartbeat: &Heartbeat) -> Result<(), String> {
+        if self.session_id != heartbeat.session_id {
+            return Err("response is for another session".into());
+        }
+        if self.seq != heartbeat.seq || self.nonce != heartbeat.nonce {
+            return Err(format!(
+                "response is for heartbeat {}, expected {}",
+                self.seq, heartbeat.seq
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn new_mac(key: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("hmac accepts keys of any length")
+}
+
+/// Hex HMAC of a request or response body.
+pub fn sign(key: &[u8], body: &[u8]) -> String {
+    let mut mac = new_mac(key);
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks `signature` over `body` in constant time.
+pub fn verify(key: &[u8], body: &[u8], signature: &str) -> Result<(), String> {
+    let expected =
+        hex::decode(signature.trim()).map_err(|err| format!("signature is not hex: {}", err))?;
+    let mut mac = new_mac(key);
+    mac.update(body);
+    mac.verify_slice(&expected)
+        .map_err(|_| "signature does not match".to_string())
+}
+
+fn response_message(body: &[u8]) -> Vec<u8> {
+    [RESPONSE_CONTEXT, body].concat()
+}
+
+/// Hex ed25519 signature of a response body by the server's identity key.
+pub fn sign_response(identity: &SigningKey, body: &[u8]) -> String {
+    hex::encode(identity.sign(&response_message(body)).to_bytes())
+}
+
+/// Checks a response signature against the hex server key pinned in the policy.
+pub fn verify_response(pinned: &str, body: &[u8], signature: &str) -> Result<(), String> {
+    let key = parse_public_key(pinned)?;
+    let signature = hex::decode(signature.trim())
+        .ok()
+        .and_then(|bytes| Signature::from_slice(&bytes).ok())
+        .ok_or("response signature is malformed")?;
+    key.verify(&response_message(body), &signature)
+        .map_err(|_| "response signature does not match".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::discovery::public_key_hex;
+
+    fn heartbeat() -> Heartbeat {
+        Heartbeat {
+            seq: 7,
+            nonce: "00112233445566778899aabbccddeeff".into(),
+            session_id: "3f2a".into(),
+            seat: Some("S01".into()),
+            fingerprint: None,
+            status: "active".into(),
+            mode: "kiosk".into(),
+            violation_count: 0,
+            violations: Vec::new(),
+            acked_commands: Vec::new(),
+            unlock_challenge: None,
+            sent_at: 1,
+        }
+    }
+
+    #[test]
+    fn heartbeat_signature_round_trips() {
+        let body = serde_json::to_vec(&heartbeat()).unwrap();
+        let signature = sign(b"key", &body);
+        assert_eq!(verify(b"key", &body, &signature), Ok(()));
+    }
+
+    #[test]
+    fn bad_heartbeat_macs_are_rejected() {
+        let body = serde_json::to_vec(&heartbeat()).unwrap();
+        let signature = sign(b"key", &body);
+        assert!(verify(b"other key", &body, &signature).is_err());
+        assert!(verify(b"key", b"{}", &signature).is_err());
+        assert!(verify(b"key", &body, "not hex").is_err());
+        assert!(verify(b"key", &body, "").is_err());
+    }
+
+    #[test]
+    fn response_signature_round_trips() {
+        let identity = SigningKey::from_bytes(&[7; 32]);
+        let body = serde_json::to_vec(&HeartbeatResponse::to(&heartbeat())).unwrap();
+        let signature = sign_response(&identity, &body);
+        assert_eq!(
+            verify_response(&public_key_hex(&identity), &body, &signature),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn responses_from_other_keys_are_rejected() {
+        let identity = SigningKey::from_bytes(&[7; 32]);
+        let other = SigningKey::from_bytes(&[8; 32]);
+        let body = serde_json::to_vec(&HeartbeatResponse::to(&heartbeat())).unwrap();
+        let pinned = public_key_hex(&identity);
+        assert!(verify_response(&pinned, &body, &sign_response(&other, &body)).is_err());
+        assert!(verify_response(&pinned, b"{}", &sign_response(&identity, &body)).is_err());
+        // an hmac under the proctor key is not a server signature
+        assert!(verify_response(&pinned, &body, &sign(b"key", &body)).is_err());
+        assert!(verify_response("not a key", &body, &sign_response(&identity, &body)).is_err());
+    }
+
+    #[test]
+    fn response_must_answer_this_heartbeat() {
+        let heartbeat = heartbeat();
+        assert_eq!(HeartbeatResponse::to(&heartbeat).answers(&heartbeat), Ok(()));
+
+        let mut later = heartbeat.clone();
+        later.seq += 1;
+        assert!(HeartbeatResponse::to(&heartbeat).answers(&later).is_err());
+
+        // same seq after a restart, but a new session and nonce
+        let mut restarted = heartbeat.clone();
+        restarted.session_id = "9c1d".into();
+        restarted.nonce = "ffeeddccbbaa99887766554433221100".into();
+        assert!(HeartbeatResponse::to(&heartbeat).answers(&restarted).is_err());
+
+        let mut same_session = heartbeat.clone();
+        same_session.nonce = "ffeeddccbbaa99887766554433221100".into();
+        assert!(HeartbeatResponse::to(&heartbeat).answers(&same_session).is_err());
+    }
+
+    #[test]
+    fn responses_without_nonce_do_not_parse() {
+        let old = r#"{"seq":7,"commands":[{"id":"c1","type":"unlock"}]}"#;
+        assert!(serde_json::from_str::<HeartbeatResponse>(old).is_err());
+    }
+}
@@ -0,0 +1,122 @@
+//! Releasing an offline exam package at its start time.
+//!
+//! The content key of a package is wrapped under [`release_key`], derived from the
+//! deployment's release secret, the exam id and the start time. Seats never hold the secret:
+//! at the start time they post a [`ReleaseRequest`] to the console on [`RELEASE_PATH`], signed
+//! with the proctor key like a heartbeat, and the console answers with the key once its own
+//! clock has passed `not_before`. The answer is signed with the console's identity key, see
+//! `crate::proctor::sign_response`, and echoes the request's random nonce. Asking for an
+//! earlier `not_before` than the package's gets a key that does not open it.
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Environment variable holding the release secret, read by the console and the packaging tool
+pub const SECRET_ENV: &str = "SECURE_BROWSER_RELEASE_SECRET";
+pub const RELEASE_PATH: &str = "/release";
+/// Random bytes in every request's `nonce`
+pub const NONCE_LENGTH: usize = 16;
+const KEY_CONTEXT: &[u8] = b"secure-browser-release-key\n";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseRequest {
+    pub exam_id: String,
+    /// Start time from the package header, Unix milliseconds
+    pub not_before: i64,
+    /// Fresh random hex for every request, echoed by the response
+    pub nonce: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReleaseResponse {
+    pub exam_id: String,
+    pub not_before: i64,
+    pub nonce: String,
+    /// [`release_key`] in hex
+    pub key: String,
+}
+
+/// Key-encryption key of a package's time release.
+pub fn release_key(secret: &[u8], exam_id: &str, not_before: i64) -> [u8; 32] {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(KEY_CONTEXT);
+    mac.update(&(exam_id.len() as u64).to_be_bytes());
+    mac.update(exam_id.as_bytes());
+    mac.update(&not_before.to_be_bytes());
+    mac.finalize().into_bytes().into()
+}
+
+impl ReleaseResponse {
+    /// The key for `request`, or why it is not released at `now`, Unix milliseconds.
+    pub fn to(request: &ReleaseRequest, secret: &[u8], now: i64) -> Result<Self, String> {
+        if now < request.not_before {
+            return Err("the exam has not started yet".into());
+        }
+        Ok(Self {
+            exam_id: request.exam_id.clone(),
+            not_before: request.not_before,
+            nonce: request.nonce.clone(),
+            key: hex::encode(release_key(secret, &request.exam_id, request.not_before)),
+        })
+    }
+
+    /// The released key, if this answers `request`.
+    pub fn key_for(&self, request: &ReleaseRequest) -> Result<[u8; 32], String> {
+        if self.exam_id != request.exam_id
+            || self.not_before != request.not_before
+            || self.nonce != request.nonce
+        {
+            return Err("response is for another request".into());
+        }
+        hex::decode(&self.key)
+            .ok()
+            .and_then(|key| <[u8; 32]>::try_from(key).ok())
+            .ok_or_else(|| "released key is malformed".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> ReleaseRequest {
+        ReleaseRequest {
+            exam_id: "math-101".into(),
+            not_before: 1_000,
+            nonce: "00112233445566778899aabbccddeeff".into(),
+        }
+    }
+
+    #[test]
+    fn key_depends_on_the_start_time() {
+        let key = release_key(b"secret", "math-101", 1_000);
+        assert_eq!(key, release_key(b"secret", "math-101", 1_000));
+        assert_ne!(key, release_key(b"secret", "math-101", 999));
+        assert_ne!(key, release_key(b"secret", "math-102", 1_000));
+        assert_ne!(key, release_key(b"other", "math-101", 1_000));
+    }
+
+    #[test]
+    fn nothing_is_released_before_the_start_time() {
+        assert!(ReleaseResponse::to(&request(), b"secret", 999).is_err());
+        let response = ReleaseResponse::to(&request(), b"secret", 1_000).unwrap();
+        assert_eq!(
+            response.key_for(&request()),
+            Ok(release_key(b"secret", "math-101", 1_000))
+        );
+    }
+
+    #[test]
+    fn responses_to_other_requests_are_refused() {
+        let response = ReleaseResponse::to(&request(), b"secret", 2_000).unwrap();
+        let mut other = request();
+        other.nonce = "ffeeddccbbaa99887766554433221100".into();
+        assert!(response.key_for(&other).is_err());
+        let mut earlier = request();
+        earlier.not_before = 0;
+        assert!(response.key_for(&earlier).is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Unlock codes for a locked exam session.
+//!
+//! When the session locks, the app makes a short challenge from the session id, the lock
+//! event and a random nonce and shows it on the lock screen. The proctor turns the challenge
+//! into a response with the deployment's unlock secret, offline with the console's
+//! `unlock_code` tool or through the console API, and the candidate types it in. The
+//! response covers the challenge and the proctor's id, so it opens that one lock only and
+//! the audit trail shows who gave it out.
+//!
+//! Codes are [`CODE_LENGTH`] characters of Crockford base32, shown as `XXXX-XXXX`.
+
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Environment variable holding the unlock secret, read by the app and the console
+pub const SECRET_ENV: &str = "SECURE_BROWSER_UNLOCK_SECRET";
+pub const CODE_LENGTH: usize = 8;
+const ALPHABET: &[u8; 32] = b"0123456789ABCDEFGHJKMNPQRSTVWXYZ";
+const CHALLENGE_CONTEXT: &[u8] = b"secure-browser-unlock-challenge\n";
+const RESPONSE_CONTEXT: &[u8] = b"secure-browser-unlock-response\n";
+const MAX_PROCTOR_LENGTH: usize = 64;
+
+/// First [`CODE_LENGTH`] base32 characters of `bytes`.
+fn encode(bytes: &[u8]) -> String {
+    let mut bits = 0u64;
+    for byte in &bytes[..5] {
+        bits = (bits << 8) | u64::from(*byte);
+    }
+    (0..CODE_LENGTH)
+        .rev()
+        .map(|index| ALPHABET[((bits >> (index * 5)) & 0x1f) as usize] as char)
+        .collect()
+}
+
+/// Uppercases a typed code and drops separators, reading `I`/`L` as `1` and `O` as `0`.
+pub fn normalize(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| match c.to_ascii_uppercase() {
+            'I' | 'L' => '1',
+            'O' => '0',
+            c => c,
+        })
+        .collect()
+}
+
+/// `XXXX-XXXX`, for showing a code.
+pub fn format_code(code: &str) -> String {
+    let code = normalize(code);
+    if code.len() == CODE_LENGTH && code.is_ascii() {
+        format!("{}-{}", &code[..4], &code[4..])
+    } else {
+        code
+    }
+}
+
+pub fn validate_code(code: &str) -> Result<String, String> {
+    let code = normalize(code);
+    if code.len() != CODE_LENGTH || !code.bytes().all(|byte| ALPHABET.contains(&byte)) {
+        return Err(format!(
+            "a code is {} letters and digits, e.g. 7K2M-QX9P",
+            CODE_LENGTH
+        ));
+    }
+    Ok(code)
+}
+
+/// Checks the id a proctor gives with an unlock code, it ends up in the audit trail.
+pub fn validate_proctor(proctor: &str) -> Result<String, String> {
+    let proctor = proctor.trim();
+    if proctor.is_empty() || proctor.chars().count() > MAX_PROCTOR_LENGTH {
+        return Err(format!(
+            "proctor id must be between 1 and {} characters",
+            MAX_PROCTOR_LENGTH
+        ));
+    }
+    if proctor.chars().any(char::is_control) {
+        return Err("proctor id may not contain control characters".into());
+    }
+    Ok(proctor.to_string())
+}
+
+/// Challenge for one lock event. `nonce` is fresh for every lock, so no two locks share one.
+pub fn challenge(
+    session_id: &str,
+    lock_id: u64,
+    violation_id: Option<u64>,
+    nonce: &[u8],
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(CHALLENGE_CONTEXT);
+    hasher.update(session_id.as_bytes());
+    hasher.update(lock_id.to_be_bytes());
+    hasher.update(violation_id.unwrap_or_default().to_be_bytes());
+    hasher.update(nonce);
+    encode(&hasher.finalize())
+}
+
+/// The code that opens `challenge` when given by `proctor`.
+pub fn response(secret: &[u8], challenge: &str, proctor: &str) -> Result<String, String> {
+    let challenge = validate_code(challenge)?;
+    let proctor = validate_proctor(proctor)?;
+    let mut mac = HmacSha256::new_from_slice(secret).expect("hmac accepts keys of any length");
+    mac.update(RESPONSE_CONTEXT);
+    mac.update(challenge.as_bytes());
+    mac.update(b"\n");
+    mac.update(proctor.as_bytes());
+    Ok(encode(&mac.finalize().into_bytes()))
+}
+
+/// Checks a typed response in constant time.
+pub fn verify_response(
+    secret: &[u8],
+    challenge: &str,
+    proctor: &str,
+    given: &str,
+) -> Result<(), String> {
+    let given = validate_code(given)?;
+    let expected = response(secret, challenge, proctor)?;
+    let difference = expected
+        .bytes()
+        .zip(given.bytes())
+        .fold(0u8, |difference, (a, b)| difference | (a ^ b));
+    if difference != 0 {
+        return Err("the unlock code does not match".into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECRET: &[u8] = b"unlock-secret";
+
+    fn locked(session_id: &str, lock_id: u64, nonce: u8) -> String {
+        challenge(session_id, lock_id, Some(3), &[nonce; 16])
+    }
+
+    #[test]
+    fn a_response_opens_its_challenge() {
+        let challenge = locked("session", 1, 1);
+        assert!(validate_code(&challenge).is_ok());
+        let code = response(SECRET, &challenge, "Proctor 7").unwrap();
+        assert_eq!(code.len(), CODE_LENGTH);
+        assert_eq!(
+            verify_response(SECRET, &challenge, "Proctor 7", &code),
+            Ok(())
+        );
+        // as typed from the screen, lowercase with the dash and the proctor id padded
+        let typed = format_code(&code).to_lowercase();
+        assert_eq!(
+            verify_response(SECRET, &challenge, " Proctor 7 ", &typed),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn a_response_opens_nothing_else() {
+        let challenge = locked("session", 1, 1);
+        let code = response(SECRET, &challenge, "Proctor 7").unwrap();
+        // the same lock of another session
+        let other_session = locked("other session", 1, 1);
+        assert_ne!(other_session, challenge);
+        assert!(verify_response(SECRET, &other_session, "Proctor 7", &code).is_err());
+        // the next lock of this session
+        assert!(verify_response(SECRET, &locked("session", 2, 1), "Proctor 7", &code).is_err());
+        assert!(verify_response(SECRET, &challenge, "Proctor 8", &code).is_err());
+        assert!(verify_response(b"another secret", &challenge, "Proctor 7", &code).is_err());
+    }
+
+    #[test]
+    fn a_replayed_challenge_is_a_new_challenge() {
+        // locked again with the same ids, only the nonce differs
+        let first = locked("session", 1, 1);
+        let again = locked("session", 1, 2);
+        assert_ne!(first, again);
+        let code = response(SECRET, &first, "Proctor 7").unwrap();
+        assert!(verify_response(SECRET, &again, "Proctor 7", &code).is_err());
+        // the same inputs make the same challenge, the nonce is what keeps them apart
+        assert_eq!(locked("session", 1, 1), first);
+    }
+
+    #[test]
+    fn codes_are_normalized_for_typing() {
+        assert_eq!(normalize(" 7k2m-qx9p "), "7K2MQX9P");
+        assert_eq!(normalize("IL0O-il oo"), "11001100");
+        assert_eq!(format_code("7k2mqx9p"), "7K2M-QX9P");
+        // anything that is not a code is shown as typed
+        assert_eq!(format_code("7k2"), "7K2");
+    }
+
+    #[test]
+    fn only_codes_from_the_alphabet_validate() {
+        assert_eq!(validate_code("7k2m-qx9p"), Ok("7K2MQX9P".to_string()));
+        assert_eq!(validate_code("o1l2-i3o4"), Ok("01121304".to_string()));
+        for code in [
+            "",
+            "7K2M-QX9",
+            "7K2M-QX9PA",
+            "7K2M-QX9U",
+            "7K2M-QX9!",
+            "7K2M-QX9é",
+        ] {
+            assert!(validate_code(code).is_err(), "{:?}", code);
+        }
+        assert!(response(SECRET, "short", "Proctor 7").is_err());
+    }
+
+    #[test]
+    fn proctor_ids_are_checked() {
+        assert_eq!(
+            validate_proctor("  Proctor 7 "),
+            Ok("Proctor 7".to_string())
+        );
+        assert!(validate_proctor("  ").is_err());
+        assert!(validate_proctor(&"p".repeat(65)).is_err());
+        assert!(validate_proctor(&"p".repeat(64)).is_ok());
+        assert!(validate_proctor("Proctor\n7").is_err());
+    }
+}
@@ -0,0 +1,89 @@
+//! Records of the app being killed, written by the mapper acting as its watchdog.
+//!
+//! The app sends a heartbeat down the mapper's stdin every second. When the heartbeats stop,
+//! or stdin closes because the app died, the mapper writes a [`TerminationRecord`] into the
+//! record directory, signed with HMAC-SHA256 under the key the app handed over, and can start
+//! the app again in resume mode. The app picks the records up on its next start.
+
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Records are named `abnormal_termination-<detected_at>.json`.
+pub const RECORD_PREFIX: &str = "abnormal_termination-";
+
+/// Sent with `Configure` when the app wants to be watched.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WatchdogConfig {
+    pub session_id: String,
+    /// Process id of the app
+    pub pid: u32,
+    /// Hex HMAC key the records are signed with
+    pub key: String,
+    /// Directory the records are written to
+    pub record_dir: String,
+    /// The app counts as gone after this long without a heartbeat
+    pub timeout_ms: u64,
+    /// Program and arguments that start the app again, empty to not relaunch
+    #[serde(default)]
+    pub relaunch: Vec<String>,
+}
+
+impl WatchdogConfig {
+    pub fn key_bytes(&self) -> Result<Vec<u8>, String> {
+        hex::decode(&self.key).map_err(|err| format!("watchdog key is not hex: {}", err))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TerminationRecord {
+    pub session_id: String,
+    pub pid: u32,
+    /// Unix timestamps in milliseconds
+    pub last_heartbeat_at: i64,
+    pub detected_at: i64,
+    /// `stdin_closed` or `heartbeat_timeout`
+    pub reason: String,
+    pub relaunched: bool,
+}
+
+impl TerminationRecord {
+    pub fn file_name(&self) -> String {
+        format!("{}{}.json", RECORD_PREFIX, self.detected_at)
+    }
+}
+
+/// The record as stored on disk. The MAC covers the record's JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedRecord {
+    pub record: String,
+    pub mac: String,
+}
+
+fn new_mac(key: &[u8]) -> HmacSha256 {
+    HmacSha256::new_from_slice(key).expect("hmac accepts keys of any length")
+}
+
+impl SignedRecord {
+    pub fn sign(record: &TerminationRecord, key: &[u8]) -> Self {
+        let record = serde_json::to_string(record).expect("records always serialize");
+        let mut mac = new_mac(key);
+        mac.update(record.as_bytes());
+        Self {
+            record,
+            mac: hex::encode(mac.finalize().into_bytes()),
+        }
+    }
+
+    pub fn verify(&self, key: &[u8]) -> Result<TerminationRecord, String> {
+        let expected =
+            hex::decode(&self.mac).map_err(|err| format!("record mac is not hex: {}", err))?;
+        let mut mac = new_mac(key);
+        mac.update(self.record.as_bytes());
+        mac.verify_slice(&expected)
+            .map_err(|_| "record mac does not match".to_string())?;
+        serde_json::from_str(&self.record).map_err(|err| format!("invalid record: {}", err))
+    }
+}
@@ -0,0 +1,75 @@
+// This file is generated by `cargo run --bin event_types`. Do not edit it by hand.
+
+export type ViolationKind = "disallowed_device" | "remote_application" | "multiple_displays" | "display_hot_plug" | "focus_lost" | "clipboard" | "keyboard_guard_lost" | "integrity_failure";
+
+export type Violation = { id: number, kind: ViolationKind, detail: string, 
+/**
+ * Unix timestamp in milliseconds
+ */
+occurred_at: number, };
+
+export type SessionStatus = "starting" | "active" | "locked" | "ended";
+
+export type AppMode = "kiosk" | "practice";
+
+export type SessionState = { 
+/**
+ * Random id of this exam session, keys the answer store and the audit trail
+ */
+id: string, status: SessionStatus, mode: AppMode, seat: string | null, 
+/**
+ * Unix timestamp in milliseconds
+ */
+started_at: number, violation_count: number, 
+/**
+ * Continued with `--resume` after the app was killed, see `utils::watchdog`
+ */
+resumed: boolean, 
+/**
+ * Time the proctor added to the exam
+ */
+extra_minutes: number, };
+
+export type LockNotice = { 
+/**
+ * Counts the locks of the session, an unlock code only opens the lock it was made for
+ */
+lock_id: number, reason: string, violation_id: number | null, 
+/**
+ * Read out to the proctor for an unlock code, missing when codes are not set up
+ */
+challenge: string | null, };
+
+export type TimeExtension = { minutes: number, 
+/**
+ * All time added during the session
+ */
+total_minutes: number, };
+
+export type ProctorNotice = { text: string, 
+/**
+ * Unix timestamp in milliseconds
+ */
+received_at: number, };
+
+export type SavedAnswer = { seq: number, idempotency_key: string, saved_at: number, };
+
+export type SyncStatus = { endpoint_configured: boolean, pending: number, synced: number, 
+/**
+ * Failed attempts since the last successful submission
+ */
+failed_attempts: number, last_synced_at: number | null, last_error: string | null, next_attempt_at: number | null, };
+
+export interface AppEventMap {
+  "show-password-prompt": null;
+  "show-ctrl-alt-delete-prompt": null;
+  "violation-recorded": Violation;
+  "session-state-changed": SessionState;
+  "session-locked": LockNotice;
+  "session-unlocked": null;
+  "time-extended": TimeExtension;
+  "proctor-message": ProctorNotice;
+  "exit-requested": null;
+}
+
+export type AppEventName = keyof AppEventMap;
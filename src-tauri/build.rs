@@ -1,14 +1,28 @@
+// App commands exposed to the front-end. Each one gets an `allow-<name>` permission
+// that has to be granted in `utils::capability` before the webview can call it.
+const COMMANDS: &[&str] = &[
+    "get_host_info",
+    "get_detector_status",
+    "get_session_state",
+    "get_violations",
+    "unlock_exam_package",
+    "autosave_answer",
+    "get_sync_status",
+    "report_blocked_paste",
+    "get_lock_notice",
+    "unlock_session",
+];
+
 fn main() {
-    #[cfg(not(target_os = "windows"))]
-    {
-        tauri_build::build()
-    }
+    let attributes = tauri_build::Attributes::new()
+        .app_manifest(tauri_build::AppManifest::new().commands(COMMANDS));
 
     #[cfg(target_os = "windows")]
-    {
+    let attributes = {
         let mut windows = tauri_build::WindowsAttributes::new();
         windows = windows.app_manifest(include_str!("app.manifest"));
-        tauri_build::try_build(tauri_build::Attributes::new().windows_attributes(windows))
-            .expect("failed to run build script");
-    }
+        attributes.windows_attributes(windows)
+    };
+
+    tauri_build::try_build(attributes).expect("failed to run build script");
 }
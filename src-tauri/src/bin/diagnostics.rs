@@ -0,0 +1,132 @@
+//! Headless diagnostics for support staff.
+//!
+//! Runs the same detectors as the secure browser without opening the kiosk window.
+//! The exit code is the worst finding seen: 0 ok, 1 warning, 2 critical, 64 bad usage.
+//!
+//! Usage: diagnostics [--json] [--watch <seconds> [--iterations <n>]]
+
+use app_lib::utils::diagnostics::{render_human, run_checks};
+use app_lib::utils::types::Severity;
+use std::process;
+use std::thread::sleep;
+use std::time::Duration;
+
+const USAGE: &str = "usage: diagnostics [--json] [--watch <seconds> [--iterations <n>]]";
+
+struct Options {
+    json: bool,
+    watch: Option<u64>,
+    iterations: Option<u64>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        json: false,
+        watch: None,
+        iterations: None,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "--json" => options.json = true,
+            "--watch" => {
+                let value = iter.next().ok_or("--watch needs a number of seconds")?;
+                let seconds = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid --watch value `{}`", value))?;
+                if seconds == 0 {
+                    return Err("--watch must be at least 1 second".into());
+                }
+                options.watch = Some(seconds);
+            }
+            "--iterations" => {
+                let value = iter.next().ok_or("--iterations needs a number")?;
+                let count = value
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid --iterations value `{}`", value))?;
+                if count == 0 {
+                    return Err("--iterations must be at least 1".into());
+                }
+                options.iterations = Some(count);
+            }
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            other => return Err(format!("unknown argument `{}`", other)),
+        }
+    }
+    if options.iterations.is_some() && options.watch.is_none() {
+        return Err("--iterations only applies with --watch".into());
+    }
+    Ok(options)
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(64);
+        }
+    };
+
+    let mut worst = Severity::Ok;
+    let mut runs = 0;
+    loop {
+        let report = run_checks();
+        worst = worst.max(report.worst());
+        if options.json {
+            // one report per line so watch mode can be piped into other tools
+            match serde_json::to_string(&report) {
+                Ok(json) => println!("{}", json),
+                Err(err) => eprintln!("could not serialize report: {}", err),
+            }
+        } else {
+            println!("{}", render_human(&report));
+        }
+
+        runs += 1;
+        let Some(seconds) = options.watch else { break };
+        if options.iterations.is_some_and(|limit| runs >= limit) {
+            break;
+        }
+        sleep(Duration::from_secs(seconds));
+    }
+
+    process::exit(worst.exit_code());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args)
+    }
+
+    #[test]
+    fn iterations_limit_watch_mode() {
+        let options = parse(&["--watch", "5", "--iterations", "3"]).unwrap();
+        assert_eq!(options.watch, Some(5));
+        assert_eq!(options.iterations, Some(3));
+        let options = parse(&["--iterations", "3", "--watch", "5"]).unwrap();
+        assert_eq!(options.iterations, Some(3));
+    }
+
+    #[test]
+    fn iterations_without_watch_are_refused() {
+        assert_eq!(
+            parse(&["--iterations", "3"]).err().as_deref(),
+            Some("--iterations only applies with --watch")
+        );
+        assert_eq!(
+            parse(&["--watch", "5", "--iterations", "0"])
+                .err()
+                .as_deref(),
+            Some("--iterations must be at least 1")
+        );
+    }
+}
@@ -0,0 +1,89 @@
+//! Stands in for the mapper in the sidecar supervision tests.
+//!
+//! Speaks the `shared::ipc` protocol without touching the keyboard: it answers the
+//! configuration with `ready` and then sends heartbeats until told to shut down. With
+//! `--crash <code>` it exits with `code` right after `ready`, with `--silent` it stays
+//! running but sends nothing more.
+//!
+//! Usage: dummy_sidecar [--crash <code> | --silent]
+
+use shared::ipc::{decode, encode, HostMessage, MapperMessage};
+use std::io::{BufRead, Write};
+use std::process;
+use std::thread;
+use std::time::Duration;
+
+const USAGE: &str = "usage: dummy_sidecar [--crash <code> | --silent]";
+const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(200);
+
+enum Behavior {
+    Healthy,
+    Crash(i32),
+    Silent,
+}
+
+fn parse_args(args: &[String]) -> Result<Behavior, String> {
+    match args {
+        [] => Ok(Behavior::Healthy),
+        [flag, code] if flag == "--crash" => code
+            .parse()
+            .map(Behavior::Crash)
+            .map_err(|_| format!("`{}` is not an exit code", code)),
+        [flag] if flag == "--silent" => Ok(Behavior::Silent),
+        _ => Err("unexpected arguments".into()),
+    }
+}
+
+fn send(message: &MapperMessage) {
+    let mut stdout = std::io::stdout().lock();
+    let _ = writeln!(stdout, "{}", encode(message));
+    let _ = stdout.flush();
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let behavior = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(64);
+    });
+
+    let mut lines = std::io::stdin().lock().lines();
+    let Some(Ok(line)) = lines.next() else {
+        process::exit(1);
+    };
+    let (rules, mode) = match decode::<HostMessage>(&line) {
+        Ok(HostMessage::Configure { rules, mode, .. }) => (rules.len(), mode),
+        other => {
+            eprintln!("expected a configuration, got {:?}", other);
+            process::exit(1);
+        }
+    };
+    send(&MapperMessage::Ready {
+        backend: "dummy".into(),
+        rules,
+        mode,
+    });
+
+    match behavior {
+        Behavior::Crash(code) => process::exit(code),
+        Behavior::Silent => {}
+        Behavior::Healthy => {
+            thread::spawn(|| loop {
+                send(&MapperMessage::Heartbeat { at: now_ms() });
+                thread::sleep(HEARTBEAT_INTERVAL);
+            });
+        }
+    }
+    for line in lines {
+        let Ok(line) = line else {
+            break;
+        };
+        if let Ok(HostMessage::Shutdown) = decode::<HostMessage>(&line) {
+            break;
+        }
+    }
+}
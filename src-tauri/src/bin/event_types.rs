@@ -0,0 +1,28 @@
+//! Writes the TypeScript definitions for the webview events.
+//!
+//! Usage: event_types [output path, defaults to bindings/events.d.ts]
+
+use app_lib::utils::events::typescript_definitions;
+use std::path::PathBuf;
+use std::process;
+
+fn main() {
+    let path = std::env::args()
+        .nth(1)
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("bindings/events.d.ts"));
+
+    if let Some(parent) = path.parent() {
+        if let Err(err) = std::fs::create_dir_all(parent) {
+            eprintln!("could not create {}: {}", parent.display(), err);
+            process::exit(1);
+        }
+    }
+    match std::fs::write(&path, typescript_definitions()) {
+        Ok(_) => println!("wrote {}", path.display()),
+        Err(err) => {
+            eprintln!("could not write {}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
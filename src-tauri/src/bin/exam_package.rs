@@ -0,0 +1,150 @@
+//! Builds a signed, encrypted offline exam package from a directory of exam files.
+//!
+//! The signing key file holds the hex ed25519 seed, its public key goes in the policy as
+//! `offline.public_key`. With `--not-before` the content key is also wrapped for release at
+//! that time, using the release secret from SECURE_BROWSER_RELEASE_SECRET. The secret stays
+//! with the packaging tool and the console, which gives out the key at the start time.
+//!
+//! Usage: exam_package --dir <dir> --out <file> --key <file> --exam-id <id>
+//!        [--entry <page>] [--not-before <rfc3339>] [--code <proctor code>]
+
+use app_lib::utils::exam_package::{seal, ExamContent, SealOptions};
+use ed25519_dalek::SigningKey;
+use shared::release::SECRET_ENV;
+use std::path::{Path, PathBuf};
+use std::process;
+
+const USAGE: &str = "usage: exam_package --dir <dir> --out <file> --key <file> --exam-id <id> \
+                     [--entry <page>] [--not-before <rfc3339>] [--code <proctor code>]";
+
+struct Options {
+    dir: PathBuf,
+    out: PathBuf,
+    key: PathBuf,
+    exam_id: String,
+    entry: String,
+    not_before: Option<i64>,
+    code: Option<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let (mut dir, mut out, mut key, mut exam_id) = (None, None, None, None);
+    let mut entry = "index.html".to_string();
+    let mut not_before = None;
+    let mut code = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--dir" => dir = Some(PathBuf::from(value()?)),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            "--key" => key = Some(PathBuf::from(value()?)),
+            "--exam-id" => exam_id = Some(value()?),
+            "--entry" => entry = value()?,
+            "--not-before" => {
+                let value = value()?;
+                let at = chrono::DateTime::parse_from_rfc3339(&value)
+                    .map_err(|err| format!("invalid --not-before `{}`: {}", value, err))?;
+                not_before = Some(at.timestamp_millis());
+            }
+            "--code" => code = Some(value()?),
+            other => return Err(format!("unknown argument `{}`", other)),
+        }
+    }
+    Ok(Options {
+        dir: dir.ok_or("--dir is required")?,
+        out: out.ok_or("--out is required")?,
+        key: key.ok_or("--key is required")?,
+        exam_id: exam_id.ok_or("--exam-id is required")?,
+        entry,
+        not_before,
+        code,
+    })
+}
+
+fn read_dir_into(root: &Path, dir: &Path, content: &mut ExamContent) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            read_dir_into(root, &path, content)?;
+        } else {
+            let relative = path
+                .strip_prefix(root)
+                .expect("walked paths are under the root")
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            content.files.insert(relative, std::fs::read(&path)?);
+        }
+    }
+    Ok(())
+}
+
+fn build(options: Options) -> Result<(), String> {
+    let seed: [u8; 32] = std::fs::read_to_string(&options.key)
+        .map_err(|err| format!("could not read {}: {}", options.key.display(), err))
+        .and_then(|hex_seed| {
+            hex::decode(hex_seed.trim()).map_err(|err| format!("key is not hex: {}", err))
+        })?
+        .try_into()
+        .map_err(|_| "key must be a 32 byte seed".to_string())?;
+    let signing_key = SigningKey::from_bytes(&seed);
+
+    let mut content = ExamContent::default();
+    read_dir_into(&options.dir, &options.dir, &mut content)
+        .map_err(|err| format!("could not read {}: {}", options.dir.display(), err))?;
+    if !content.files.contains_key(&options.entry) {
+        return Err(format!(
+            "entry page `{}` is not in the package",
+            options.entry
+        ));
+    }
+
+    let secret = match options.not_before {
+        Some(_) => Some(
+            std::env::var(SECRET_ENV)
+                .ok()
+                .filter(|secret| !secret.is_empty())
+                .ok_or(format!("--not-before needs {}", SECRET_ENV))?
+                .into_bytes(),
+        ),
+        None => None,
+    };
+    let package = seal(
+        &content,
+        SealOptions {
+            exam_id: options.exam_id,
+            entry: options.entry,
+            not_before: options.not_before.unwrap_or(i64::MAX),
+            release_secret: secret.as_deref(),
+            proctor_code: options.code.as_deref(),
+        },
+        &signing_key,
+    )?;
+    let json = serde_json::to_string(&package).map_err(|err| err.to_string())?;
+    std::fs::write(&options.out, json)
+        .map_err(|err| format!("could not write {}: {}", options.out.display(), err))?;
+    println!(
+        "wrote {} with {} files, public key {}",
+        options.out.display(),
+        content.files.len(),
+        hex::encode(signing_key.verifying_key().to_bytes())
+    );
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(64);
+        }
+    };
+    if let Err(err) = build(options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,104 @@
+//! Signs the integrity manifest the app checks itself against at startup.
+//!
+//! Run it on the bundled app, after any code signing, since both change the binaries. The
+//! executable and sidecars are hashed as given and listed by file name, so pass the copies
+//! the installer ships. The frontend assets are the ones embedded in this build. The signing
+//! key file holds the hex ed25519 seed, its public key has to be set in
+//! SECURE_BROWSER_INTEGRITY_KEY when the app is built.
+//!
+//! Usage: integrity_manifest --exe <file> --sidecar <file>... --key <file> --out <file>
+
+use app_lib::utils::integrity::{hash_assets, hash_file, Manifest, ManifestFile};
+use ed25519_dalek::SigningKey;
+use std::path::{Path, PathBuf};
+use std::process;
+use tauri::Assets;
+
+const USAGE: &str = "usage: integrity_manifest --exe <file> --sidecar <file>... --key <file> \
+                     --out <file>";
+
+struct Options {
+    exe: PathBuf,
+    sidecars: Vec<PathBuf>,
+    key: PathBuf,
+    out: PathBuf,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let (mut exe, mut key, mut out) = (None, None, None);
+    let mut sidecars = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--exe" => exe = Some(PathBuf::from(value()?)),
+            "--sidecar" => sidecars.push(PathBuf::from(value()?)),
+            "--key" => key = Some(PathBuf::from(value()?)),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown argument `{}`", other)),
+        }
+    }
+    Ok(Options {
+        exe: exe.ok_or("--exe is required")?,
+        sidecars,
+        key: key.ok_or("--key is required")?,
+        out: out.ok_or("--out is required")?,
+    })
+}
+
+fn file_name(path: &Path) -> Result<String, String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or(format!("{} is not a file", path.display()))
+}
+
+fn build(options: Options) -> Result<(), String> {
+    let seed: [u8; 32] = std::fs::read_to_string(&options.key)
+        .map_err(|err| format!("could not read {}: {}", options.key.display(), err))
+        .and_then(|hex_seed| {
+            hex::decode(hex_seed.trim()).map_err(|err| format!("key is not hex: {}", err))
+        })?
+        .try_into()
+        .map_err(|_| "key must be a 32 byte seed".to_string())?;
+    let signing_key = SigningKey::from_bytes(&seed);
+
+    let context: tauri::Context<tauri::Wry> = tauri::generate_context!();
+    let mut manifest = Manifest {
+        version: context.package_info().version.to_string(),
+        assets: hash_assets(context.assets().iter()),
+        ..Manifest::default()
+    };
+    for path in std::iter::once(&options.exe).chain(&options.sidecars) {
+        let hash =
+            hash_file(path).map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+        manifest.files.insert(file_name(path)?, hash);
+    }
+
+    let file = ManifestFile::sign(&manifest, &signing_key)?;
+    let json = serde_json::to_string(&file).map_err(|err| err.to_string())?;
+    std::fs::write(&options.out, json)
+        .map_err(|err| format!("could not write {}: {}", options.out.display(), err))?;
+    println!(
+        "wrote {} with {} files and {} assets, public key {}",
+        options.out.display(),
+        manifest.files.len(),
+        manifest.assets.len(),
+        hex::encode(signing_key.verifying_key().to_bytes())
+    );
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(64);
+        }
+    };
+    if let Err(err) = build(options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,283 @@
+//! A stand-in proctoring server for trying out the heartbeat client.
+//!
+//! Prints every heartbeat it receives and answers with the commands typed on stdin, one per
+//! line: `lock <reason>`, `unlock`, `extend <minutes>`, `terminate <reason>` or
+//! `message <text>`. A command goes out with every answer until a heartbeat acknowledges
+//! it. Heartbeats are checked with the same key as the app's, from SECURE_BROWSER_PROCTOR_KEY,
+//! and answers are signed with the ed25519 seed in `--identity`, or a new key for this run.
+//! Point the policy's `proctor.endpoint` at `http://<listen address>/heartbeat` and set
+//! `proctor.console_key` to the public key printed on start.
+//!
+//! Usage: mock_proctor [--listen <addr>, defaults to 127.0.0.1:8787] [--identity <file>]
+
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use app_lib::utils::mode::AppMode;
+use app_lib::utils::proctor::proctor_key;
+use app_lib::utils::secrets;
+use ed25519_dalek::SigningKey;
+use shared::discovery::public_key_hex;
+use shared::proctor::{
+    sign_response, verify, CommandAction, Heartbeat, HeartbeatResponse, ProctorCommand,
+    SIGNATURE_HEADER,
+};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const USAGE: &str = "usage: mock_proctor [--listen <addr>] [--identity <file>]";
+const MAX_REQUEST: usize = 1024 * 1024;
+
+struct Server {
+    key: Vec<u8>,
+    identity: SigningKey,
+    queued: Mutex<Vec<ProctorCommand>>,
+    acked_violation: Mutex<u64>,
+}
+
+fn parse_command(line: &str, id: usize) -> Result<ProctorCommand, String> {
+    let (verb, rest) = line.trim().split_once(' ').unwrap_or((line.trim(), ""));
+    let rest = rest.trim().to_string();
+    let action = match verb {
+        "lock" => CommandAction::Lock { reason: rest },
+        "unlock" => CommandAction::Unlock,
+        "extend" => CommandAction::ExtendTime {
+            minutes: rest
+                .parse()
+                .map_err(|_| format!("`{}` is not a number of minutes", rest))?,
+        },
+        "terminate" => CommandAction::Terminate { reason: rest },
+        "message" => CommandAction::Message { text: rest },
+        other => return Err(format!("unknown command `{}`", other)),
+    };
+    Ok(ProctorCommand {
+        id: format!("cmd-{}", id),
+        action,
+    })
+}
+
+/// Reads one request and returns the signature header and the body.
+async fn read_request(stream: &mut TcpStream) -> Result<(Option<String>, Vec<u8>), String> {
+    let mut buffer = Vec::new();
+    let mut chunk = [0u8; 4096];
+    let head_end = loop {
+        let read = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|err| err.to_string())?;
+        if read == 0 {
+            return Err("connection closed".into());
+        }
+        buffer.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buffer.windows(4).position(|window| window == b"\r\n\r\n") {
+            break end + 4;
+        }
+        if buffer.len() > MAX_REQUEST {
+            return Err("request too large".into());
+        }
+    };
+    let head = String::from_utf8_lossy(&buffer[..head_end]).to_string();
+    let header = |name: &str| {
+        head.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim()
+                .eq_ignore_ascii_case(name)
+                .then(|| value.trim().to_string())
+        })
+    };
+    let length: usize = header("content-length")
+        .and_then(|length| length.parse().ok())
+        .unwrap_or(0)
+        .min(MAX_REQUEST);
+    let mut body = buffer[head_end..].to_vec();
+    while body.len() < length {
+        let read = stream
+            .read(&mut chunk)
+            .await
+            .map_err(|err| err.to_string())?;
+        if read == 0 {
+            break;
+        }
+        body.extend_from_slice(&chunk[..read]);
+    }
+    Ok((header(SIGNATURE_HEADER), body))
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, signature: Option<&str>, body: &[u8]) {
+    let mut head = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    if let Some(signature) = signature {
+        head.push_str(&format!("{}: {}\r\n", SIGNATURE_HEADER, signature));
+    }
+    head.push_str("\r\n");
+    let written = async {
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(body).await?;
+        stream.flush().await
+    };
+    if let Err(err) = written.await {
+        eprintln!("could not answer: {}", err);
+    }
+}
+
+fn answer(server: &Server, heartbeat: &Heartbeat) -> HeartbeatResponse {
+    let mut queued = server.queued.lock().expect("queue lock");
+    queued.retain(|command| !heartbeat.acked_commands.contains(&command.id));
+    let mut acked_violation = server.acked_violation.lock().expect("violation lock");
+    for violation in &heartbeat.violations {
+        println!(
+            "  violation {} {}: {}",
+            violation.id, violation.kind, violation.detail
+        );
+        *acked_violation = (*acked_violation).max(violation.id);
+    }
+    HeartbeatResponse {
+        commands: queued.clone(),
+        acked_violation: Some(*acked_violation),
+        ..HeartbeatResponse::to(heartbeat)
+    }
+}
+
+async fn handle(server: Arc<Server>, mut stream: TcpStream) {
+    let (signature, body) = match read_request(&mut stream).await {
+        Ok(request) => request,
+        Err(err) => {
+            eprintln!("bad request: {}", err);
+            return;
+        }
+    };
+    let checked = signature
+        .ok_or("heartbeat is not signed".to_string())
+        .and_then(|signature| verify(&server.key, &body, &signature))
+        .and_then(|_| serde_json::from_slice::<Heartbeat>(&body).map_err(|err| err.to_string()));
+    let heartbeat = match checked {
+        Ok(heartbeat) => heartbeat,
+        Err(err) => {
+            eprintln!("rejected heartbeat: {}", err);
+            respond(&mut stream, "401 Unauthorized", None, b"{}").await;
+            return;
+        }
+    };
+    println!(
+        "heartbeat {} from seat {} session {}: {} {}, {} violations, acked {:?}",
+        heartbeat.seq,
+        heartbeat.seat.as_deref().unwrap_or("-"),
+        heartbeat.session_id,
+        heartbeat.mode,
+        heartbeat.status,
+        heartbeat.violation_count,
+        heartbeat.acked_commands
+    );
+    let reply = serde_json::to_vec(&answer(&server, &heartbeat)).expect("responses serialize");
+    let signature = sign_response(&server.identity, &reply);
+    respond(&mut stream, "200 OK", Some(&signature), &reply).await;
+}
+
+struct Options {
+    listen: String,
+    identity: Option<PathBuf>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut options = Options {
+        listen: "127.0.0.1:8787".to_string(),
+        identity: None,
+    };
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--listen" => options.listen = value()?,
+            "--identity" => options.identity = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown argument `{}`", other)),
+        }
+    }
+    Ok(options)
+}
+
+fn load_identity(path: Option<&Path>) -> Result<SigningKey, String> {
+    let seed: [u8; 32] = match path {
+        Some(path) => std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read {}: {}", path.display(), err))
+            .and_then(|hex_seed| {
+                hex::decode(hex_seed.trim()).map_err(|err| format!("seed is not hex: {}", err))
+            })?
+            .try_into()
+            .map_err(|_| "identity must be a 32 byte seed".to_string())?,
+        None => {
+            let mut seed = [0u8; 32];
+            OsRng.fill_bytes(&mut seed);
+            seed
+        }
+    };
+    Ok(SigningKey::from_bytes(&seed))
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = parse_args(&args).unwrap_or_else(|err| {
+        eprintln!("{}\n{}", err, USAGE);
+        process::exit(64);
+    });
+    // a development tool, the key may come from the environment
+    secrets::allow_environment(AppMode::Practice);
+    let loaded = proctor_key()
+        .and_then(|key| load_identity(options.identity.as_deref()).map(|identity| (key, identity)));
+    let (key, identity) = loaded.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    println!("console key: {}", public_key_hex(&identity));
+    let listen = options.listen;
+    let server = Arc::new(Server {
+        key,
+        identity,
+        queued: Mutex::default(),
+        acked_violation: Mutex::default(),
+    });
+
+    std::thread::spawn({
+        let server = server.clone();
+        move || {
+            let mut next_id = 1;
+            for line in std::io::stdin().lock().lines().map_while(Result::ok) {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                match parse_command(&line, next_id) {
+                    Ok(command) => {
+                        println!("queued {}: {:?}", command.id, command.action);
+                        server.queued.lock().expect("queue lock").push(command);
+                        next_id += 1;
+                    }
+                    Err(err) => eprintln!("{}", err),
+                }
+            }
+        }
+    });
+
+    tauri::async_runtime::block_on(async move {
+        let listener = match TcpListener::bind(&listen).await {
+            Ok(listener) => listener,
+            Err(err) => {
+                eprintln!("could not listen on {}: {}", listen, err);
+                process::exit(1);
+            }
+        };
+        println!("mock proctor listening on {}", listen);
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tauri::async_runtime::spawn(handle(server.clone(), stream));
+                }
+                Err(err) => eprintln!("accept failed: {}", err),
+            }
+        }
+    });
+}
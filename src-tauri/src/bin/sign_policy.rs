@@ -0,0 +1,79 @@
+//! Signs the policy a kiosk build is installed with.
+//!
+//! The policy is signed as written, install the output as `policy.json` next to the
+//! executable. The signing key file holds the hex ed25519 seed, its public key has to be set
+//! in SECURE_BROWSER_POLICY_KEY when the app is built.
+//!
+//! Usage: sign_policy --policy <file> --key <file> --out <file>
+
+use app_lib::utils::policy::PolicyFile;
+use ed25519_dalek::SigningKey;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: sign_policy --policy <file> --key <file> --out <file>";
+
+struct Options {
+    policy: PathBuf,
+    key: PathBuf,
+    out: PathBuf,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let (mut policy, mut key, mut out) = (None, None, None);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        let mut value = || iter.next().cloned().ok_or(format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--policy" => policy = Some(PathBuf::from(value()?)),
+            "--key" => key = Some(PathBuf::from(value()?)),
+            "--out" => out = Some(PathBuf::from(value()?)),
+            other => return Err(format!("unknown argument `{}`", other)),
+        }
+    }
+    Ok(Options {
+        policy: policy.ok_or("--policy is required")?,
+        key: key.ok_or("--key is required")?,
+        out: out.ok_or("--out is required")?,
+    })
+}
+
+fn build(options: Options) -> Result<(), String> {
+    let seed: [u8; 32] = std::fs::read_to_string(&options.key)
+        .map_err(|err| format!("could not read {}: {}", options.key.display(), err))
+        .and_then(|hex_seed| {
+            hex::decode(hex_seed.trim()).map_err(|err| format!("key is not hex: {}", err))
+        })?
+        .try_into()
+        .map_err(|_| "key must be a 32 byte seed".to_string())?;
+    let signing_key = SigningKey::from_bytes(&seed);
+
+    let policy = std::fs::read_to_string(&options.policy)
+        .map_err(|err| format!("could not read {}: {}", options.policy.display(), err))?;
+    let file = PolicyFile::sign(&policy, &signing_key)
+        .map_err(|err| format!("{}: {}", options.policy.display(), err))?;
+    let json = serde_json::to_string(&file).map_err(|err| err.to_string())?;
+    std::fs::write(&options.out, json)
+        .map_err(|err| format!("could not write {}: {}", options.out.display(), err))?;
+    println!(
+        "wrote {}, public key {}",
+        options.out.display(),
+        hex::encode(signing_key.verifying_key().to_bytes())
+    );
+    Ok(())
+}
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n{}", err, USAGE);
+            process::exit(64);
+        }
+    };
+    if let Err(err) = build(options) {
+        eprintln!("{}", err);
+        process::exit(1);
+    }
+}
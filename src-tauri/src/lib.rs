@@ -1,50 +1,322 @@
 #![allow(unused_imports)]
 pub mod utils;
 
-use crate::utils::types::Triggers;
+use crate::utils::audit::{AuditLog, AuditState, AuditVerification, AUDIT_FILE_NAME};
+use crate::utils::autosave::{AnswerState, AnswerStore};
+use crate::utils::cli::Cli;
+use crate::utils::clipboard::{ClipboardWatcher, PASTE_BLOCK_SCRIPT};
+use crate::utils::content::{ContentProtections, INTERACTION_LOCK_SCRIPT};
+use crate::utils::display::{DisplayChange, DisplayWatcher};
+use crate::utils::focus::{FocusState, FocusTracker};
+use crate::utils::integrity::IntegrityCheck;
+use crate::utils::mode::{watermark_script, AppMode};
+use crate::utils::navigation::NavigationAllowlist;
+use crate::utils::offline::{OfflineExam, OfflineState};
+use crate::utils::policy::Policy;
+use crate::utils::session::{DetectorState, Session, SessionStore};
+use crate::utils::sidecar::SidecarState;
+use crate::utils::sync::{SyncState, SyncStatus};
+use crate::utils::types::{DetectorStatus, SessionStatus, Triggers, ViolationKind};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
 use std::process;
 use std::sync::mpsc::channel;
 use std::sync::{Arc, Mutex};
 use std::thread::sleep;
 use std::time::Duration;
 use tauri::menu::MenuBuilder;
-use tauri::{Emitter, Manager};
+use tauri::webview::NewWindowResponse;
+use tauri::{Emitter, Manager, WebviewWindowBuilder};
 use tauri_plugin_global_shortcut::{Code, GlobalShortcutExt, Modifiers, Shortcut};
+use tauri_plugin_log::{Target, TargetKind};
 use tauri_plugin_notification::NotificationExt;
-use tauri_plugin_shell::process::{CommandChild, CommandEvent};
-use tauri_plugin_shell::ShellExt;
 use tokio_task_scheduler::{Scheduler, TaskBuilder};
 
 // collapse these 2 schdeulers into 1 struct and manage it
 pub struct SchedulerState(pub Mutex<Option<Scheduler>>);
 pub struct RemoteChecker(pub Mutex<Option<Scheduler>>);
-
-struct AppState {
-    child_process: Arc<Mutex<Option<CommandChild>>>,
-}
+pub struct DisplayChecker(pub Mutex<Option<Scheduler>>);
+pub struct ClipboardChecker(pub Mutex<Option<Scheduler>>);
+pub struct IntegrityChecker(pub Mutex<Option<Scheduler>>);
 
 #[cfg_attr(mobile, tauri::mobile_entry_point)]
 pub fn run() {
-    let args: Vec<String> = std::env::args().collect();
-    log::info!("arguments: {:?}", args);
-    let is_kiosk = args.iter().any(|arg| arg == "kiosk");
-    log::info!("Running app in kiosk mode set to : {}", is_kiosk);
-    let child_process: Arc<Mutex<Option<CommandChild>>> = Arc::new(Mutex::default());
-    let app_state = AppState { child_process };
+    let cli = Cli::parse();
+    if let Some(path) = &cli.verify_audit {
+        // checking a copied log is not an exam run, outside kiosk builds the key may be set
+        if !utils::policy::is_kiosk_build() {
+            utils::secrets::allow_environment(AppMode::Practice);
+        }
+        let key = utils::audit::audit_key().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(2);
+        });
+        let code = match utils::audit::verify_file(path, &key) {
+            Ok(AuditVerification::Valid { records }) => {
+                println!("{}: chain intact, {} records", path.display(), records);
+                0
+            }
+            Ok(AuditVerification::Broken { line, reason }) => {
+                println!(
+                    "{}: chain broken at line {}: {}",
+                    path.display(),
+                    line,
+                    reason
+                );
+                1
+            }
+            Err(err) => {
+                eprintln!("could not read {}: {}", path.display(), err);
+                2
+            }
+        };
+        process::exit(code);
+    }
+    // a broken policy or a forbidden override must stop the launch, not fall back to defaults
+    let policy = cli
+        .load_policy()
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit());
+    let launch = cli.resolve(&policy).unwrap_or_else(|err| {
+        Cli::command()
+            .error(ErrorKind::ArgumentConflict, err)
+            .exit()
+    });
+    let allowlist = NavigationAllowlist::new(&policy.navigation, launch.exam_url.as_ref())
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit());
+    let mode = launch.mode;
+    utils::secrets::allow_environment(mode);
+    // kiosk mode does not start on keys anyone could know
+    if let Err(err) = utils::secrets::check(mode) {
+        Cli::command().error(ErrorKind::InvalidValue, err).exit();
+    }
+    // nor unverified, only practice runs and debug builds go without the manifest key
+    if let Err(err) = utils::integrity::check_key(mode) {
+        Cli::command().error(ErrorKind::InvalidValue, err).exit();
+    }
+    let protections = ContentProtections::new(mode, &policy.content, allowlist.origins());
+    if cli.preflight_only {
+        let mut report = utils::diagnostics::run_checks();
+        report
+            .findings
+            .extend(utils::diagnostics::content_findings(&protections));
+        println!("{}", utils::diagnostics::render_human(&report));
+        process::exit(report.worst().exit_code());
+    }
+    let mut proxy_hosts = policy.proxy.allowed_hosts.clone();
+    proxy_hosts.extend(allowlist.hosts());
+    let proxy_enabled = policy.proxy.enabled;
+    let offline_package = policy
+        .offline
+        .load_package()
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit());
+    let offline_entry = offline_package
+        .as_ref()
+        .map(|package| package.header.entry.clone());
+    let offline_always = policy.offline.always;
+    let offline_release = policy
+        .offline
+        .release(&policy.proctor)
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit());
+    let submission = policy.submission.clone();
+    let clipboard_policy = policy.clipboard.clone();
+    // a typo in the key rules must not leave the keyboard unguarded
+    if let Err(err) = policy.keyboard.rule_set() {
+        Cli::command().error(ErrorKind::InvalidValue, err).exit();
+    }
+    let key_rules = policy.keyboard.rules.clone();
+    let sidecar_policy = policy.sidecar.clone();
+    let integrity_policy = policy.integrity.clone();
+    let watchdog_policy = policy.watchdog.clone();
+    let proctor_policy = policy.proctor.clone();
+    let proctor_credentials = proctor_policy
+        .credentials()
+        .unwrap_or_else(|err| Cli::command().error(ErrorKind::InvalidValue, err).exit());
+    let session = match &launch.resume {
+        Some(id) => Session::resume(mode, launch.seat.clone(), id.clone()),
+        None => Session::new(mode, launch.seat.clone()),
+    };
+    let session_id = session.state.id.clone();
+    let watermark = mode.lockdown().watermark;
+    log::info!("Running app in {:?} mode", mode);
 
     tauri::Builder::default()
+        .on_page_load(move |webview, payload| {
+            if let (Some(text), tauri::webview::PageLoadEvent::Finished) =
+                (watermark, payload.event())
+            {
+                if let Err(e) = webview.eval(&watermark_script(text)) {
+                    log::error!("Could not add watermark: {:?}", e);
+                }
+            }
+        })
         .plugin(tauri_plugin_notification::init())
         .plugin(tauri_plugin_process::init())
+        .plugin(tauri_plugin_clipboard_manager::init())
         .plugin(tauri_plugin_updater::Builder::new().build())
-        .manage(app_state)
+        .manage(SidecarState(Mutex::default()))
         .manage(SchedulerState(Mutex::default()))
         .manage(RemoteChecker(Mutex::default()))
-        .setup(|app| {
-            app.handle().plugin(
-                tauri_plugin_log::Builder::default()
-                    .level(log::LevelFilter::Info)
-                    .build(),
-            )?;
+        .manage(DisplayChecker(Mutex::default()))
+        .manage(ClipboardChecker(Mutex::default()))
+        .manage(IntegrityChecker(Mutex::default()))
+        .manage(DetectorState(Mutex::new(DetectorStatus::default())))
+        .manage(SessionStore(Mutex::new(session)))
+        .manage(SyncState(Mutex::new(SyncStatus::default())))
+        .manage(policy)
+        .manage(mode)
+        .manage(FocusState(Mutex::new(FocusTracker::default())))
+        .manage(OfflineExam(Mutex::new(OfflineState::new(offline_package))))
+        .register_uri_scheme_protocol(utils::offline::SCHEME, |ctx, request| {
+            utils::offline::handle_request(ctx.app_handle(), &request)
+        })
+        .invoke_handler(tauri::generate_handler![
+            utils::commands::get_host_info,
+            utils::commands::get_detector_status,
+            utils::commands::get_session_state,
+            utils::commands::get_violations,
+            utils::commands::unlock_exam_package,
+            utils::commands::autosave_answer,
+            utils::commands::get_sync_status,
+            utils::commands::report_blocked_paste,
+            utils::commands::get_lock_notice,
+            utils::commands::unlock_session,
+        ])
+        .setup(move |app| {
+            let lockdown = mode.lockdown();
+            let mut log_builder = tauri_plugin_log::Builder::default().level(log::LevelFilter::Info);
+            if let Some(dir) = &launch.log_dir {
+                log_builder = log_builder
+                    .clear_targets()
+                    .target(Target::new(TargetKind::Stdout))
+                    .target(Target::new(TargetKind::Folder {
+                        path: dir.clone(),
+                        file_name: None,
+                    }));
+            }
+            app.handle().plugin(log_builder.build())?;
+
+            let audit_path = match &launch.log_dir {
+                Some(dir) => Some(dir.join(AUDIT_FILE_NAME)),
+                None => app.path().app_log_dir().ok().map(|dir| dir.join(AUDIT_FILE_NAME)),
+            };
+            let audit_log = audit_path.and_then(|path| {
+                let key = utils::audit::audit_key()
+                    .map_err(|err| log::error!("Audit log disabled: {}", err))
+                    .ok()?;
+                AuditLog::open(&path, key)
+                    .map_err(|err| log::error!("Could not open audit log {}: {}", path.display(), err))
+                    .ok()
+            });
+            app.manage(AuditState(audit_log));
+            // a resumed session takes back its lock and violations, see `Session::attach`
+            let attached = app
+                .path()
+                .app_data_dir()
+                .map_err(|err| err.to_string())
+                .and_then(|dir| {
+                    let key = utils::audit::audit_key()?;
+                    let path = utils::session::saved_path(&dir, &session_id);
+                    let store = app.state::<SessionStore>();
+                    let mut session = store.0.lock().map_err(|err| err.to_string())?;
+                    session.attach(&path, key)
+                });
+            if let Err(err) = attached {
+                if launch.resume.is_some() {
+                    log::error!("Could not resume session {}: {}", session_id, err);
+                    utils::audit::audit(
+                        app.handle(),
+                        "session_resume_refused",
+                        serde_json::json!({ "session_id": session_id, "error": err }),
+                    );
+                    app.handle().exit(1);
+                    return Ok(());
+                }
+                log::error!("The session is not saved, a relaunch cannot resume it: {}", err);
+            }
+            utils::audit::audit(
+                app.handle(),
+                "session_started",
+                serde_json::json!({
+                    "session_id": session_id,
+                    "mode": mode,
+                    "seat": launch.seat,
+                    "resumed": launch.resume.is_some(),
+                }),
+            );
+            // left behind by the mapper if the previous run was killed
+            let terminations = utils::watchdog::collect_records(app.handle());
+            if terminations > 0 {
+                log::info!("{} abnormal termination(s) recorded since the last run", terminations);
+            }
+
+            // a swapped sidecar or patched executable must not get to run the exam in kiosk mode
+            let integrity = IntegrityCheck::load(&integrity_policy, mode)
+                .and_then(|check| match check {
+                    Some(check) => {
+                        let problems = check.verify(app.handle());
+                        if problems.is_empty() {
+                            Ok(Some(check))
+                        } else {
+                            Err(problems.join(", "))
+                        }
+                    }
+                    None => Ok(None),
+                });
+            let integrity = match integrity {
+                Ok(Some(check)) => {
+                    utils::audit::audit(
+                        app.handle(),
+                        "integrity_verified",
+                        serde_json::json!({
+                            "version": check.manifest().version,
+                            "files": check.manifest().files.len(),
+                            "assets": check.manifest().assets.len(),
+                        }),
+                    );
+                    Some(check)
+                }
+                Ok(None) => {
+                    log::warn!("No integrity key in this build, files are not verified");
+                    None
+                }
+                Err(err) => {
+                    log::error!("Integrity check failed: {}", err);
+                    utils::audit::audit(
+                        app.handle(),
+                        "integrity_failed",
+                        serde_json::json!({ "at": "startup", "error": err }),
+                    );
+                    if lockdown.enforce_violations {
+                        app.handle().exit(1);
+                        return Ok(());
+                    }
+                    None
+                }
+            };
+
+            let answer_store = app
+                .path()
+                .app_data_dir()
+                .map_err(|err| err.to_string())
+                .and_then(|dir| {
+                    let key = utils::autosave::store_key()?;
+                    let path = dir.join("answers").join(format!("{}.jsonl", session_id));
+                    AnswerStore::open(&path, &session_id, &key)
+                        .map_err(|err| format!("{}: {}", path.display(), err))
+                })
+                .map_err(|err| log::error!("Could not open the answer store {}", err))
+                .ok();
+            app.manage(AnswerState(Mutex::new(answer_store)));
+            tauri::async_runtime::spawn(utils::sync::run_queue(app.handle().clone(), submission));
+            tauri::async_runtime::spawn(utils::proctor::run(app.handle().clone(), proctor_policy, proctor_credentials));
+            if let Some(release) = offline_release {
+                tauri::async_runtime::spawn(utils::offline::run_release(app.handle().clone(), release));
+            }
+            let mut clipboard_watcher = ClipboardWatcher::default();
+            if clipboard_policy.clear && utils::clipboard::clear(app.handle()) {
+                clipboard_watcher.cleared();
+                utils::audit::audit(app.handle(), "clipboard_cleared", serde_json::json!({ "at": "session_start" }));
+            }
             // request notification access from user
             match app.notification().request_permission() {
                 Ok(_) => log::info!("Permission Requested for Application"),
@@ -52,74 +324,40 @@ pub fn run() {
             }
             // Check if running in a guest machine on windows
             if utils::is_virtual_machine() || utils::is_running_in_rdp() {
-                log::info!("Running in a guest machine, exiting...");
-                app.handle().exit(0);
+                if lockdown.enforce_violations {
+                    log::info!("Running in a guest machine, exiting...");
+                    app.handle().exit(0);
+                } else {
+                    log::info!("Running in a guest machine, allowed in practice mode");
+                }
             }
 
             #[cfg(target_os = "windows")]
-            {
+            if lockdown.block_system_keys {
                 log::info!("Disabling CAD commands");
                 utils::disable_cad_actions(true).expect("could not disable cad command");
-
             }
             // get host info
             let host_info = utils::get_host_info();
             log::info!("Host Info: {:?}", host_info);
 
-            if cfg!(target_os = "windows") {
-                log::info!("Dectected Windows Environment: Running side car");
+            // the mapper grabs system keys on windows and linux, it is a no-op elsewhere
+            if cfg!(any(target_os = "windows", target_os = "linux")) && lockdown.block_system_keys
+            {
+                log::info!("Running key mapper side car");
                 app.handle()
                     .plugin(tauri_plugin_shell::init())
-                    .expect("Failed to initialize shell plugin for Windows");
-
-                let sidecar = app
-                    .shell()
-                    .sidecar("mapper")
-                    .expect("Failed to get sidecar");
-                let process = sidecar.spawn();
-
-                match process {
-                    Ok((mut rx, child)) => {
-                        // Save the child handle
-                        let app_state = app.state::<AppState>();
-                        let mut child_lock = app_state.child_process.lock().unwrap();
-                        *child_lock = Some(child);
-                        drop(child_lock);
-                        tauri::async_runtime::spawn(async move {
-                            while let Some(event) = rx.recv().await {
-                                match event {
-                                    CommandEvent::Stdout(line) => {
-                                        log::info!(
-                                            "[Sidecar stdout] {:?}",
-                                            String::from_utf8_lossy(&line)
-                                        );
-                                    }
-                                    CommandEvent::Stderr(line) => {
-                                        log::error!(
-                                            "[Sidecar stderr] {:?}",
-                                            String::from_utf8_lossy(&line)
-                                        );
-                                    }
-                                    CommandEvent::Error(err) => {
-                                        let error_message = format!("[Sidecar error] {}", err);
-                                        log::error!("{}", &error_message);
+                    .expect("Failed to initialize shell plugin for the key mapper");
 
-                                        process::exit(1); // Exit on error
-                                    }
-                                    CommandEvent::Terminated(_) => {
-                                        log::error!("[Sidecar] Terminated.");
-                                        //process::exit(1); // Exit on error
-                                    }
-                                    _ => {}
-                                }
-                            }
-                        });
-                    }
-                    Err(err) => {
-                        log::error!("Failed to spawn sidecar process: {}", err);
-                        app.handle().exit(1);
-                    }
-                }
+                let watchdog =
+                    utils::watchdog::config(app.handle(), &session_id, &watchdog_policy);
+                tauri::async_runtime::spawn(utils::sidecar::supervise(
+                    app.handle().clone(),
+                    key_rules,
+                    mode,
+                    watchdog,
+                    sidecar_policy,
+                ));
             }
 
             let kill_binding = Shortcut::new(Some(Modifiers::CONTROL), Code::KeyK);
@@ -148,17 +386,94 @@ pub fn run() {
                 app.global_shortcut().register(minimized_shortcut)?;
             }
 
-            let window = app.get_webview_window("main").unwrap();
-            window.set_fullscreen(true)?;
-            window.set_decorations(false)?;
-            window.set_always_on_top(true)?;
-            window.set_resizable(false)?;
-            let menu = MenuBuilder::new(app.handle()).build()?;
-            window.set_menu(menu)?;
-            window.set_skip_taskbar(true)?;
-            window.set_visible_on_all_workspaces(true)?;
+            // the exam pages may call the app's commands wherever the allowlist lets them load
+            app.add_capability(utils::capability::exam(&allowlist))?;
+
+            // the main window is built here rather than from the config so navigation is guarded
+            // from its very first page load
+            let window_config = app
+                .config()
+                .app
+                .windows
+                .iter()
+                .find(|config| config.label == "main")
+                .cloned()
+                .expect("main window missing from tauri.conf.json");
+            let mut window_builder =
+                WebviewWindowBuilder::from_config(app.handle(), &window_config)?
+                    .initialization_script(utils::content::csp_script(&protections.csp))
+                    .devtools(lockdown.devtools);
+            if lockdown.block_page_interaction {
+                window_builder = window_builder.initialization_script(INTERACTION_LOCK_SCRIPT);
+            }
+            if clipboard_policy.block_paste {
+                window_builder = window_builder.initialization_script(PASTE_BLOCK_SCRIPT);
+            }
+            if proxy_enabled {
+                match utils::proxy::start(app.handle(), proxy_hosts) {
+                    Ok(addr) => {
+                        log::info!("Filtering proxy listening on {}", addr);
+                        window_builder =
+                            window_builder.proxy_url(tauri::Url::parse(&format!("http://{}", addr))?);
+                    }
+                    Err(err) => {
+                        log::error!("Could not start the filtering proxy: {}", err);
+                        if lockdown.enforce_violations {
+                            app.handle().exit(1);
+                        }
+                    }
+                }
+            }
+            let window = window_builder
+                .on_navigation({
+                    let app_handle = app.handle().clone();
+                    move |url| utils::navigation::guard_navigation(&app_handle, &allowlist, url)
+                })
+                .on_new_window({
+                    let app_handle = app.handle().clone();
+                    move |url, _features| {
+                        utils::navigation::block_new_window(&app_handle, &url);
+                        NewWindowResponse::Deny
+                    }
+                })
+                .build()?;
+            window.set_fullscreen(lockdown.fullscreen)?;
+            window.set_decorations(!lockdown.fullscreen)?;
+            window.set_always_on_top(lockdown.always_on_top)?;
+            window.set_resizable(!lockdown.fullscreen)?;
+            if mode.is_kiosk() {
+                let menu = MenuBuilder::new(app.handle()).build()?;
+                window.set_menu(menu)?;
+                window.set_visible_on_all_workspaces(true)?;
+            }
+            window.set_skip_taskbar(lockdown.hide_from_taskbar)?;
             // prevent app from screen sharing
-            window.set_content_protected(true)?;
+            window.set_content_protected(lockdown.content_protected)?;
+            let hosted_url = launch
+                .exam_url
+                .clone()
+                .or_else(|| match &window_config.url {
+                    tauri::WebviewUrl::External(url) => Some(url.clone()),
+                    _ => None,
+                });
+            let offline = offline_entry.as_ref().filter(|_| {
+                offline_always
+                    || !hosted_url
+                        .as_ref()
+                        .is_some_and(utils::offline::is_reachable)
+            });
+            if let Some(entry) = offline {
+                log::info!("Loading exam from the offline package");
+                utils::audit::audit(
+                    app.handle(),
+                    "offline_exam_loaded",
+                    serde_json::json!({ "hosted_url": hosted_url.as_ref().map(|url| url.as_str()) }),
+                );
+                window.navigate(utils::offline::entry_url(entry))?;
+            } else if let Some(url) = &launch.exam_url {
+                log::info!("Loading exam from {}", url);
+                window.navigate(url.clone())?;
+            }
 
             // create a channel for listeners
             let (sender, rx) = channel::<Triggers>();
@@ -176,8 +491,13 @@ pub fn run() {
             // It runs every 30 seconds.
             let remote_checker_task = TaskBuilder::new("remote_checker", {
                 let input_checker_sender = sender.clone();
+                let app_handle = app_handle.clone();
                 move || {
                     let report = utils::is_web_rtc_running();
+                    if let Ok(mut status) = app_handle.state::<DetectorState>().0.lock() {
+                        status.web_rtc = Some(report.clone());
+                        status.web_rtc_checked_at = Some(chrono::Utc::now().timestamp_millis());
+                    }
                     if report.is_running() {
                         match input_checker_sender
                             .clone()
@@ -221,8 +541,13 @@ pub fn run() {
             // It runs every 10 seconds.
             let task = TaskBuilder::new("input_checker", {
                 let input_checker_sender = sender.clone();
+                let app_handle = app_handle.clone();
                 move || {
                     let response = utils::is_disallowed_device_connected();
+                    if let Ok(mut status) = app_handle.state::<DetectorState>().0.lock() {
+                        status.disallowed_devices = response.clone();
+                        status.devices_checked_at = Some(chrono::Utc::now().timestamp_millis());
+                    }
                     if response.len() > 0 {
                         match input_checker_sender
                             .clone()
@@ -254,6 +579,178 @@ pub fn run() {
                 }
             });
 
+            //////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ////////////////////////////////////                    SCHEDULE TASK FOR DISPLAY TOPOLOGY                           //////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+            let display_scheduler = Scheduler::new();
+            let display_watcher = Arc::new(Mutex::new(DisplayWatcher::default()));
+
+            // It runs every 5 seconds, a hot-plugged monitor should be caught quickly.
+            let display_task = TaskBuilder::new("display_checker", {
+                let display_sender = sender.clone();
+                let app_handle = app_handle.clone();
+                move || {
+                    let topology = utils::display::read_topology(&app_handle);
+                    if let Ok(mut status) = app_handle.state::<DetectorState>().0.lock() {
+                        status.displays = Some(topology.clone());
+                    }
+                    let policy = app_handle.state::<Policy>();
+                    let change = match display_watcher.lock() {
+                        Ok(mut watcher) => watcher.check(&topology, &policy.displays),
+                        Err(e) => {
+                            log::error!("could not lock display watcher: {:?}", e);
+                            return Ok(());
+                        }
+                    };
+                    let trigger = match change {
+                        DisplayChange::TooMany => Triggers::TooManyDisplays(topology),
+                        DisplayChange::HotPlugged => Triggers::DisplayHotPlugged(topology),
+                        DisplayChange::Unchanged => {
+                            log::info!("Task executed: Display topology unchanged!");
+                            return Ok(());
+                        }
+                    };
+                    match display_sender.send(trigger) {
+                        Ok(_) => log::info!("send was successful"),
+                        Err(e) => log::error!("send failed: {:?}", e),
+                    }
+                    Ok(())
+                }
+            })
+            .every_seconds(5)
+            .build();
+
+            tauri::async_runtime::spawn({
+                let app_handle = app_handle.clone();
+                async move {
+                    match display_scheduler.add_task(display_task).await {
+                        Ok(_) => log::info!("Task: Display Checker added successfully."),
+                        Err(e) => log::error!("Error adding task: {:?}", e),
+                    }
+                    display_scheduler.start().await;
+                    let process = &app_handle.state::<DisplayChecker>().0;
+                    let mut lock = process.lock().expect("could not lock scheduler");
+                    *lock = Some(display_scheduler);
+                    drop(lock);
+                }
+            });
+            //////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ////////////////////////////////////                    SCHEDULE TASK FOR CLIPBOARD CHANGES                          //////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+            if clipboard_policy.poll_secs > 0 {
+                let clipboard_scheduler = Scheduler::new();
+                let clipboard_watcher = Arc::new(Mutex::new(clipboard_watcher));
+
+                let clipboard_task = TaskBuilder::new("clipboard_checker", {
+                    let clipboard_sender = sender.clone();
+                    let app_handle = app_handle.clone();
+                    move || {
+                        let content = utils::clipboard::read_content(&app_handle);
+                        let changed = match clipboard_watcher.lock() {
+                            Ok(mut watcher) => {
+                                let changed = watcher.check(&content);
+                                // an emptied clipboard cannot be pasted from
+                                if changed
+                                    && lockdown.enforce_violations
+                                    && utils::clipboard::clear(&app_handle)
+                                {
+                                    watcher.cleared();
+                                }
+                                changed
+                            }
+                            Err(e) => {
+                                log::error!("could not lock clipboard watcher: {:?}", e);
+                                return Ok(());
+                            }
+                        };
+                        if changed {
+                            match clipboard_sender.send(Triggers::ClipboardChanged(content.len())) {
+                                Ok(_) => log::info!("send was successful"),
+                                Err(e) => log::error!("send failed: {:?}", e),
+                            }
+                        }
+                        Ok(())
+                    }
+                })
+                .every_seconds(clipboard_policy.poll_secs)
+                .build();
+
+                tauri::async_runtime::spawn({
+                    let app_handle = app_handle.clone();
+                    async move {
+                        match clipboard_scheduler.add_task(clipboard_task).await {
+                            Ok(_) => log::info!("Task: Clipboard Checker added successfully."),
+                            Err(e) => log::error!("Error adding task: {:?}", e),
+                        }
+                        clipboard_scheduler.start().await;
+                        let process = &app_handle.state::<ClipboardChecker>().0;
+                        let mut lock = process.lock().expect("could not lock scheduler");
+                        *lock = Some(clipboard_scheduler);
+                        drop(lock);
+                    }
+                });
+            }
+
+            //////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ////////////////////////////////////                    SCHEDULE TASK FOR SELF INTEGRITY                             //////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+            ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
+
+            if let Some(integrity) = integrity.filter(|_| integrity_policy.interval_secs > 0) {
+                let integrity_scheduler = Scheduler::new();
+                // the same differences are reported once, not on every run
+                let reported = Arc::new(Mutex::new(Vec::<String>::new()));
+
+                let integrity_task = TaskBuilder::new("integrity_checker", {
+                    let integrity_sender = sender.clone();
+                    let app_handle = app_handle.clone();
+                    move || {
+                        let problems = integrity.verify(&app_handle);
+                        let mut reported = match reported.lock() {
+                            Ok(reported) => reported,
+                            Err(e) => {
+                                log::error!("could not lock integrity report: {:?}", e);
+                                return Ok(());
+                            }
+                        };
+                        if problems.is_empty() || *reported == problems {
+                            log::info!("Task executed: Integrity unchanged!");
+                            return Ok(());
+                        }
+                        *reported = problems.clone();
+                        match integrity_sender.send(Triggers::IntegrityFailed(problems)) {
+                            Ok(_) => log::info!("send was successful"),
+                            Err(e) => log::error!("send failed: {:?}", e),
+                        }
+                        Ok(())
+                    }
+                })
+                .every_seconds(integrity_policy.interval_secs)
+                .build();
+
+                tauri::async_runtime::spawn({
+                    let app_handle = app_handle.clone();
+                    async move {
+                        match integrity_scheduler.add_task(integrity_task).await {
+                            Ok(_) => log::info!("Task: Integrity Checker added successfully."),
+                            Err(e) => log::error!("Error adding task: {:?}", e),
+                        }
+                        integrity_scheduler.start().await;
+                        let process = &app_handle.state::<IntegrityChecker>().0;
+                        let mut lock = process.lock().expect("could not lock scheduler");
+                        *lock = Some(integrity_scheduler);
+                        drop(lock);
+                    }
+                });
+            }
+
             ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
             ///////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////////
             ////////////////////////////////////                    RECIEVE TASK REPORTS OVER A CHANNEL                           /////////////////////////////////////////
@@ -267,31 +764,123 @@ pub fn run() {
                     while let Ok(event) = rx.recv() {
                         match event {
                             Triggers::DisAllowedInputDectected(device) => {
+                                let description =
+                                    device[0].description.clone().unwrap_or("unnamed".into());
+                                utils::session::record_violation(
+                                    &app_handle,
+                                    ViolationKind::DisallowedDevice,
+                                    format!("Disallowed device connected: {}", description),
+                                );
+                                if !lockdown.enforce_violations {
+                                    log::info!(
+                                        "Disallowed input detected with description: `{}`, reported only",
+                                        description
+                                    );
+                                    continue;
+                                }
                                 log::info!(
                                     "Disallowed input detected with description: `{}`, exiting app",
-                                    device[0].description.clone().unwrap_or("unnamed".into())
+                                    description
                                 );
-                                app_handle
+                                utils::session::set_status(&app_handle, SessionStatus::Ended);
+                                if let Err(err) = app_handle
                                     .notification()
                                     .builder()
                                     .title("Device Compromised")
                                     .body("An external device has been attached to your device")
                                     .show()
-                                    .unwrap();
+                                {
+                                    log::error!("Could not show the device notification: {}", err);
+                                }
                                 sleep(Duration::from_secs(5));
                                 app_handle.exit(0);
                             }
+                            Triggers::RemoteApplicationDectected(report) => {
+                                utils::session::record_violation(
+                                    &app_handle,
+                                    ViolationKind::RemoteApplication,
+                                    format!(
+                                        "{} remote application(s) running with {} udp port(s) in use",
+                                        report.processes.len(),
+                                        report.ports.len()
+                                    ),
+                                );
+                            }
+                            Triggers::TooManyDisplays(topology) => {
+                                utils::session::record_violation(
+                                    &app_handle,
+                                    ViolationKind::MultipleDisplays,
+                                    format!(
+                                        "{} displays active, {} allowed",
+                                        topology.active_count(),
+                                        app_handle.state::<Policy>().displays.max_displays
+                                    ),
+                                );
+                                if lockdown.enforce_violations
+                                    && app_handle.state::<Policy>().displays.blackout_secondary
+                                {
+                                    if let Err(e) =
+                                        utils::display::blackout_secondary_monitors(&app_handle)
+                                    {
+                                        log::error!("Could not black out monitors: {:?}", e);
+                                    }
+                                }
+                            }
+                            Triggers::DisplayHotPlugged(topology) => {
+                                utils::session::record_violation(
+                                    &app_handle,
+                                    ViolationKind::DisplayHotPlug,
+                                    format!(
+                                        "Display topology changed during the exam: {}",
+                                        topology.signature().join(", ")
+                                    ),
+                                );
+                            }
+                            Triggers::ClipboardChanged(bytes) => {
+                                utils::session::record_violation(
+                                    &app_handle,
+                                    ViolationKind::Clipboard,
+                                    format!(
+                                        "Clipboard changed from outside the exam ({} bytes)",
+                                        bytes
+                                    ),
+                                );
+                            }
+                            Triggers::IntegrityFailed(problems) => {
+                                let detail = problems.join(", ");
+                                utils::audit::audit(
+                                    &app_handle,
+                                    "integrity_failed",
+                                    serde_json::json!({ "at": "runtime", "error": detail }),
+                                );
+                                utils::session::record_violation(
+                                    &app_handle,
+                                    ViolationKind::IntegrityFailure,
+                                    format!("App files changed during the exam: {}", detail),
+                                );
+                                if !lockdown.enforce_violations {
+                                    continue;
+                                }
+                                log::info!("App files changed during the exam, exiting app");
+                                utils::session::set_status(&app_handle, SessionStatus::Ended);
+                                sleep(Duration::from_secs(5));
+                                app_handle.exit(1);
+                            }
                             _ => {}
                         }
                     }
                 }
             });
+
+            utils::session::begin(app.handle());
             Ok(())
         })
         .on_window_event({
-            move |_window, event| {
+            move |window, event| {
                 if let tauri::WindowEvent::CloseRequested { api, .. } = event {
                     api.prevent_close();
+                } else {
+                    utils::focus::handle_window_event(window, event);
                 }
             }
         })
@@ -301,22 +890,9 @@ pub fn run() {
             // if user exits the app, kill the thread running in the background
             if let tauri::RunEvent::ExitRequested { .. } = event {
                 log::info!("🚨 Exit requested!");
+                utils::events::emit(app_handle, utils::events::AppEvent::ExitRequested);
 
-                #[cfg(target_os = "windows")]
-                {
-                    let child_process = app_handle.state::<AppState>().child_process.clone();
-                    let mut lock = child_process.lock().unwrap();
-                    if let Some(child) = lock.take() {
-                        let _ = child.kill();
-                        log::info!("🛑 Sidecar killed on exit.");
-                    }
-                }
-                let child_process = app_handle.state::<AppState>().child_process.clone();
-                let mut lock = child_process.lock().unwrap();
-                if let Some(child) = lock.take() {
-                    let _ = child.kill();
-                    println!("🛑 Sidecar killed on restart.");
-                }
+                utils::sidecar::stop(app_handle);
                 let process = &app_handle.state::<SchedulerState>().0;
                 let mut lock = process.lock().unwrap();
                 if let Some(child) = lock.take() {
@@ -331,6 +907,39 @@ pub fn run() {
                         log::info!("🛑 Remote Scheduler killed on exit");
                     }
                 }
+                {
+                    let process = &app_handle.state::<DisplayChecker>().0;
+                    let mut lock = process.lock().unwrap();
+                    if let Some(child) = lock.take() {
+                        let _ = child.stop();
+                        log::info!("🛑 Display Scheduler killed on exit");
+                    }
+                }
+                {
+                    let process = &app_handle.state::<ClipboardChecker>().0;
+                    let mut lock = process.lock().unwrap();
+                    if let Some(child) = lock.take() {
+                        let _ = child.stop();
+                        log::info!("🛑 Clipboard Scheduler killed on exit");
+                    }
+                }
+                {
+                    let process = &app_handle.state::<IntegrityChecker>().0;
+                    let mut lock = process.lock().unwrap();
+                    if let Some(child) = lock.take() {
+                        let _ = child.stop();
+                        log::info!("🛑 Integrity Scheduler killed on exit");
+                    }
+                }
+                if app_handle.state::<Policy>().clipboard.clear
+                    && utils::clipboard::clear(app_handle)
+                {
+                    utils::audit::audit(
+                        app_handle,
+                        "clipboard_cleared",
+                        serde_json::json!({ "at": "session_end" }),
+                    );
+                }
             }
         });
 }
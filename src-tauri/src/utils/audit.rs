@@ -0,0 +1,337 @@
+//! Tamper-evident audit log.
+//!
+//! Each record is one JSON line carrying the MAC of the previous record, so removing,
+//! reordering or editing any line breaks the chain from that point on. The MAC key is set
+//! per deployment, see `utils::secrets`.
+
+use crate::utils::secrets::AUDIT_KEY;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tauri::{AppHandle, Manager};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// `prev` of the first record in a file
+const GENESIS: &str = "genesis";
+pub const AUDIT_FILE_NAME: &str = "audit.jsonl";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub seq: u64,
+    /// Unix timestamp in milliseconds
+    pub at: i64,
+    pub kind: String,
+    pub detail: Value,
+    pub prev: String,
+    pub mac: String,
+}
+
+pub fn audit_key() -> Result<Vec<u8>, String> {
+    AUDIT_KEY.load()
+}
+
+fn compute_mac(key: &[u8], seq: u64, at: i64, kind: &str, detail: &Value, prev: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(prev.as_bytes());
+    mac.update(&seq.to_be_bytes());
+    mac.update(&at.to_be_bytes());
+    mac.update(kind.as_bytes());
+    mac.update(detail.to_string().as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+struct ChainWriter {
+    file: File,
+    seq: u64,
+    last_mac: String,
+}
+
+/// Appends records to an audit file, continuing the chain of an existing file.
+pub struct AuditLog {
+    path: PathBuf,
+    key: Vec<u8>,
+    writer: Mutex<ChainWriter>,
+}
+
+impl AuditLog {
+    pub fn open(path: &Path, key: Vec<u8>) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let (seq, last_mac) = match File::open(path) {
+            Ok(file) => BufReader::new(file)
+                .lines()
+                .map_while(Result::ok)
+                .filter_map(|line| serde_json::from_str::<AuditRecord>(&line).ok())
+                .last()
+                .map(|record| (record.seq + 1, record.mac))
+                .unwrap_or((0, GENESIS.to_string())),
+            Err(_) => (0, GENESIS.to_string()),
+        };
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            key,
+            writer: Mutex::new(ChainWriter {
+                file,
+                seq,
+                last_mac,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn append(&self, kind: &str, detail: Value) -> std::io::Result<AuditRecord> {
+        let mut writer = self
+            .writer
+            .lock()
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+        let at = chrono::Utc::now().timestamp_millis();
+        let record = AuditRecord {
+            seq: writer.seq,
+            at,
+            kind: kind.to_string(),
+            mac: compute_mac(&self.key, writer.seq, at, kind, &detail, &writer.last_mac),
+            detail,
+            prev: writer.last_mac.clone(),
+        };
+        let line = serde_json::to_string(&record)?;
+        writeln!(writer.file, "{}", line)?;
+        writer.file.flush()?;
+        writer.seq += 1;
+        writer.last_mac = record.mac.clone();
+        Ok(record)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuditVerification {
+    Valid {
+        records: u64,
+    },
+    /// The chain is broken at this 1-based line number
+    Broken {
+        line: usize,
+        reason: String,
+    },
+}
+
+/// Walks the whole chain of an audit file.
+pub fn verify_file(path: &Path, key: &[u8]) -> std::io::Result<AuditVerification> {
+    let reader = BufReader::new(File::open(path)?);
+    let mut expected_prev = GENESIS.to_string();
+    let mut expected_seq = 0;
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let broken = |reason: String| AuditVerification::Broken {
+            line: index + 1,
+            reason,
+        };
+        let record: AuditRecord = match serde_json::from_str(&line) {
+            Ok(record) => record,
+            Err(err) => return Ok(broken(format!("not an audit record: {}", err))),
+        };
+        if record.seq != expected_seq {
+            return Ok(broken(format!(
+                "expected sequence {} but found {}",
+                expected_seq, record.seq
+            )));
+        }
+        if record.prev != expected_prev {
+            return Ok(broken("does not follow the previous record".into()));
+        }
+        let mac = compute_mac(
+            key,
+            record.seq,
+            record.at,
+            &record.kind,
+            &record.detail,
+            &record.prev,
+        );
+        if mac != record.mac {
+            return Ok(broken("record was modified".into()));
+        }
+        expected_prev = record.mac;
+        expected_seq += 1;
+    }
+    Ok(AuditVerification::Valid {
+        records: expected_seq,
+    })
+}
+
+/// Managed audit log, `None` when the log file could not be opened.
+pub struct AuditState(pub Option<AuditLog>);
+
+/// Appends a record to the managed audit log. Failures are logged, never fatal.
+pub fn audit(app: &AppHandle, kind: &str, detail: Value) {
+    let state = app.state::<AuditState>();
+    let Some(log) = &state.0 else {
+        log::warn!("Audit log unavailable, dropping `{}` record", kind);
+        return;
+    };
+    if let Err(err) = log.append(kind, detail) {
+        log::error!(
+            "Could not write `{}` to {}: {}",
+            kind,
+            log.path().display(),
+            err
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const KEY: &[u8] = b"audit-test-key";
+
+    /// An audit file under the temp dir, removed again when dropped.
+    struct TempLog(PathBuf);
+
+    impl TempLog {
+        fn new(name: &str) -> Self {
+            Self(std::env::temp_dir().join(format!("audit-{}-{}.jsonl", name, std::process::id())))
+        }
+
+        fn write(&self, records: &[(&str, Value)]) {
+            let log = AuditLog::open(&self.0, KEY.to_vec()).unwrap();
+            for (kind, detail) in records {
+                log.append(kind, detail.clone()).unwrap();
+            }
+        }
+
+        fn lines(&self) -> Vec<String> {
+            std::fs::read_to_string(&self.0)
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect()
+        }
+
+        fn rewrite(&self, lines: &[String]) {
+            std::fs::write(&self.0, lines.join("\n") + "\n").unwrap();
+        }
+
+        fn verify(&self) -> AuditVerification {
+            verify_file(&self.0, KEY).unwrap()
+        }
+    }
+
+    impl Drop for TempLog {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn records() -> Vec<(&'static str, Value)> {
+        vec![
+            ("session_started", json!({ "mode": "kiosk" })),
+            ("violation", json!({ "kind": "focus_lost" })),
+            ("session_ended", json!({})),
+        ]
+    }
+
+    fn broken_at(line: usize, reason: &str) -> AuditVerification {
+        AuditVerification::Broken {
+            line,
+            reason: reason.into(),
+        }
+    }
+
+    #[test]
+    fn an_untouched_chain_verifies() {
+        let log = TempLog::new("intact");
+        log.write(&records());
+        assert_eq!(log.verify(), AuditVerification::Valid { records: 3 });
+
+        // a relaunch carries on the chain of the same file
+        log.write(&records());
+        assert_eq!(log.verify(), AuditVerification::Valid { records: 6 });
+        assert!(verify_file(&log.0, b"another key")
+            .is_ok_and(|result| result == broken_at(1, "record was modified")));
+    }
+
+    #[test]
+    fn an_edited_record_breaks_the_chain() {
+        let log = TempLog::new("edited");
+        log.write(&records());
+        let mut lines = log.lines();
+        lines[1] = lines[1].replace("focus_lost", "nothing");
+        log.rewrite(&lines);
+        assert_eq!(log.verify(), broken_at(2, "record was modified"));
+    }
+
+    #[test]
+    fn a_removed_or_moved_record_breaks_the_chain() {
+        let log = TempLog::new("removed");
+        log.write(&records());
+        let mut lines = log.lines();
+        lines.remove(1);
+        log.rewrite(&lines);
+        assert_eq!(
+            log.verify(),
+            broken_at(2, "expected sequence 1 but found 2")
+        );
+
+        let log = TempLog::new("moved");
+        log.write(&records());
+        let mut lines = log.lines();
+        lines.swap(1, 2);
+        log.rewrite(&lines);
+        assert_eq!(
+            log.verify(),
+            broken_at(2, "expected sequence 1 but found 2")
+        );
+
+        // renumbered to hide the gap, the mac chain still shows it
+        let log = TempLog::new("renumbered");
+        log.write(&records());
+        let mut lines = log.lines();
+        lines.remove(1);
+        lines[1] = lines[1].replace("\"seq\":2", "\"seq\":1");
+        log.rewrite(&lines);
+        assert_eq!(
+            log.verify(),
+            broken_at(2, "does not follow the previous record")
+        );
+    }
+
+    #[test]
+    fn the_first_record_must_start_the_chain() {
+        let log = TempLog::new("truncated");
+        log.write(&records());
+        let lines = log.lines();
+        log.rewrite(&lines[1..]);
+        assert_eq!(
+            log.verify(),
+            broken_at(1, "expected sequence 0 but found 1")
+        );
+
+        let log = TempLog::new("garbage");
+        log.write(&records());
+        let mut lines = log.lines();
+        lines.push("not a record".into());
+        log.rewrite(&lines);
+        let AuditVerification::Broken { line, reason } = log.verify() else {
+            panic!("a line that is not a record is not valid");
+        };
+        assert_eq!(line, 4);
+        assert!(reason.starts_with("not an audit record"), "{}", reason);
+    }
+
+    #[test]
+    fn a_missing_file_is_an_error() {
+        let log = TempLog::new("missing");
+        assert!(verify_file(&log.0, KEY).is_err());
+    }
+}
@@ -0,0 +1,199 @@
+//! Encrypted, append-only answer store.
+//!
+//! Every autosave appends one line to a per-session file, encrypted with AES-256-GCM under a
+//! key derived from the deployment store key, see `utils::secrets`, and the session id.
+//! Lines are never rewritten: when the sync queue has delivered an answer, a `synced` line is
+//! appended for it instead, and opening the store replays the file to find what is still
+//! pending.
+
+use crate::utils::secrets::STORE_KEY;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use ts_rs::TS;
+
+/// One autosaved answer, as queued for submission.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnswerRecord {
+    pub seq: u64,
+    pub question_id: String,
+    pub answer: Value,
+    /// Unix timestamp in milliseconds
+    pub saved_at: i64,
+    /// Sent with the submission so a retried request is only applied once
+    pub idempotency_key: String,
+}
+
+/// Returned to the front-end once an answer is on disk.
+#[derive(Debug, Clone, Serialize, TS)]
+pub struct SavedAnswer {
+    #[ts(type = "number")]
+    pub seq: u64,
+    pub idempotency_key: String,
+    #[ts(type = "number")]
+    pub saved_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum StoreEntry {
+    Answer(AnswerRecord),
+    Synced { seq: u64 },
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedLine {
+    nonce: String,
+    ciphertext: String,
+}
+
+pub fn store_key() -> Result<Vec<u8>, String> {
+    STORE_KEY.load()
+}
+
+/// Managed answer store, `None` when it could not be opened.
+pub struct AnswerState(pub Mutex<Option<AnswerStore>>);
+
+pub struct AnswerStore {
+    path: PathBuf,
+    session_id: String,
+    cipher: Aes256Gcm,
+    file: File,
+    next_seq: u64,
+    pending: Vec<AnswerRecord>,
+    synced: usize,
+}
+
+impl AnswerStore {
+    /// Opens the store of `session_id`, replaying whatever an earlier run left in it.
+    pub fn open(path: &Path, session_id: &str, key: &[u8]) -> std::io::Result<Self> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut mac =
+            <Hmac<Sha256> as Mac>::new_from_slice(key).expect("hmac accepts keys of any length");
+        mac.update(session_id.as_bytes());
+        let session_key: [u8; 32] = mac.finalize().into_bytes().into();
+        let mut store = Self {
+            path: path.to_path_buf(),
+            session_id: session_id.to_string(),
+            cipher: Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(&session_key)),
+            file: OpenOptions::new().create(true).append(true).open(path)?,
+            next_seq: 0,
+            pending: Vec::new(),
+            synced: 0,
+        };
+        let reader = BufReader::new(File::open(path)?);
+        for (index, line) in reader.lines().enumerate() {
+            match store.decrypt(&line?) {
+                Ok(StoreEntry::Answer(record)) => {
+                    store.next_seq = store.next_seq.max(record.seq + 1);
+                    store.pending.push(record);
+                }
+                Ok(StoreEntry::Synced { seq }) => {
+                    store.pending.retain(|record| record.seq != seq);
+                    store.synced += 1;
+                }
+                Err(err) => log::error!(
+                    "Skipping unreadable line {} of {}: {}",
+                    index + 1,
+                    path.display(),
+                    err
+                ),
+            }
+        }
+        Ok(store)
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    /// Answers not yet acknowledged by the submission endpoint, oldest first.
+    pub fn pending(&self) -> &[AnswerRecord] {
+        &self.pending
+    }
+
+    pub fn synced_count(&self) -> usize {
+        self.synced
+    }
+
+    fn decrypt(&self, line: &str) -> Result<StoreEntry, String> {
+        let line: EncryptedLine = serde_json::from_str(line).map_err(|err| err.to_string())?;
+        let nonce = BASE64.decode(&line.nonce).map_err(|err| err.to_string())?;
+        if nonce.len() != 12 {
+            return Err("nonce has the wrong length".into());
+        }
+        let ciphertext = BASE64
+            .decode(&line.ciphertext)
+            .map_err(|err| err.to_string())?;
+        let plaintext = self
+            .cipher
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: self.session_id.as_bytes(),
+                },
+            )
+            .map_err(|_| "could not decrypt".to_string())?;
+        serde_json::from_slice(&plaintext).map_err(|err| err.to_string())
+    }
+
+    fn append(&mut self, entry: &StoreEntry) -> std::io::Result<()> {
+        let plaintext = serde_json::to_vec(entry)?;
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: self.session_id.as_bytes(),
+                },
+            )
+            .map_err(|_| std::io::Error::other("could not encrypt answer"))?;
+        let line = serde_json::to_string(&EncryptedLine {
+            nonce: BASE64.encode(nonce),
+            ciphertext: BASE64.encode(ciphertext),
+        })?;
+        writeln!(self.file, "{}", line)?;
+        // an answer only counts as saved once it is on disk
+        self.file.sync_data()
+    }
+
+    pub fn save(&mut self, question_id: &str, answer: Value) -> std::io::Result<AnswerRecord> {
+        let seq = self.next_seq;
+        let record = AnswerRecord {
+            seq,
+            question_id: question_id.to_string(),
+            answer,
+            saved_at: chrono::Utc::now().timestamp_millis(),
+            idempotency_key: format!("{}-{}", self.session_id, seq),
+        };
+        self.append(&StoreEntry::Answer(record.clone()))?;
+        self.next_seq += 1;
+        self.pending.push(record.clone());
+        Ok(record)
+    }
+
+    pub fn mark_synced(&mut self, seq: u64) -> std::io::Result<()> {
+        self.append(&StoreEntry::Synced { seq })?;
+        self.pending.retain(|record| record.seq != seq);
+        self.synced += 1;
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
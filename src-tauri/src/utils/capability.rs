@@ -0,0 +1,94 @@
+//! The `exam` capability, which lets the exam pages call the commands in `utils::commands`.
+//!
+//! It is added at startup instead of shipping in `capabilities/`, so it covers exactly the
+//! pages the main window may load: the origins of the [`NavigationAllowlist`], the launch's
+//! `exam_url` among them, and the app's own pages, which include the offline exam on `exam://`.
+
+use crate::utils::navigation::NavigationAllowlist;
+use tauri::ipc::CapabilityBuilder;
+
+pub const IDENTIFIER: &str = "exam";
+
+/// One per entry of `COMMANDS` in `build.rs`.
+const PERMISSIONS: &[&str] = &[
+    "allow-get-host-info",
+    "allow-get-detector-status",
+    "allow-get-session-state",
+    "allow-get-violations",
+    "allow-unlock-exam-package",
+    "allow-autosave-answer",
+    "allow-get-sync-status",
+    "allow-report-blocked-paste",
+    "allow-get-lock-notice",
+    "allow-unlock-session",
+];
+
+/// Builds the capability for the main window, pass it to `Manager::add_capability`.
+pub fn exam(allowlist: &NavigationAllowlist) -> CapabilityBuilder {
+    let capability = CapabilityBuilder::new(IDENTIFIER)
+        .window("main")
+        // pages served by the app itself, `exam://` is a registered scheme
+        .local(true);
+    let capability = allowlist
+        .origins()
+        .iter()
+        .fold(capability, |capability, origin| {
+            capability.remote(origin.clone())
+        });
+    PERMISSIONS
+        .iter()
+        .fold(capability, |capability, permission| {
+            capability.permission(*permission)
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::navigation::NavigationPolicy;
+    use tauri::ipc::RuntimeCapability;
+    use tauri::utils::acl::capability::CapabilityFile;
+    use tauri::Url;
+
+    #[test]
+    fn every_allowed_origin_gets_the_commands() {
+        let policy = NavigationPolicy {
+            allowed_origins: vec![
+                "https://exam.example.com/start".into(),
+                "http://10.0.0.5:8080".into(),
+            ],
+            allowed_paths: vec!["/exam/*".into()],
+        };
+        let exam_url = Url::parse("https://other.example.org/paper/1").unwrap();
+        let allowlist = NavigationAllowlist::new(&policy, Some(&exam_url)).unwrap();
+
+        let CapabilityFile::Capability(capability) = exam(&allowlist).build() else {
+            panic!("expected a single capability");
+        };
+        assert_eq!(capability.identifier, IDENTIFIER);
+        assert_eq!(capability.windows, ["main"]);
+        assert!(capability.local);
+        assert_eq!(
+            capability.remote.expect("remote urls").urls,
+            [
+                "https://exam.example.com",
+                "http://10.0.0.5:8080",
+                "https://other.example.org"
+            ]
+        );
+        assert_eq!(capability.permissions.len(), PERMISSIONS.len());
+    }
+
+    #[test]
+    fn permissions_match_the_generated_commands() {
+        let build = include_str!("../../build.rs");
+        let start = build.find("const COMMANDS").unwrap();
+        let end = start + build[start..].find("];").unwrap();
+        let commands: Vec<String> = build[start..end]
+            .lines()
+            .filter_map(|line| line.trim().strip_prefix('"')?.strip_suffix("\","))
+            .map(|command| format!("allow-{}", command.replace('_', "-")))
+            .collect();
+        assert_eq!(commands, PERMISSIONS);
+    }
+}
@@ -0,0 +1,354 @@
+//! Command line interface of the secure browser.
+
+use crate::utils::mode::{resolve_mode, AppMode};
+use crate::utils::policy::{self, Policy, POLICY_ENV};
+use crate::utils::session::validate_session_id;
+use clap::Parser;
+use ed25519_dalek::VerifyingKey;
+use std::ffi::OsString;
+use std::path::PathBuf;
+use tauri::Url;
+
+const MAX_SEAT_LENGTH: usize = 32;
+
+#[derive(Debug, Parser)]
+#[command(name = "secure-browser", version, about = "Locked-down exam browser")]
+pub struct Cli {
+    /// Policy file, overrides the SECURE_BROWSER_POLICY environment variable.
+    /// Kiosk builds refuse both and run their installed policy
+    #[arg(long, value_name = "PATH")]
+    pub policy: Option<PathBuf>,
+
+    /// Seat number assigned to this machine
+    #[arg(long)]
+    pub seat: Option<String>,
+
+    /// Exam page to load instead of the configured one
+    #[arg(long, value_name = "URL")]
+    pub exam_url: Option<String>,
+
+    #[arg(long, value_enum)]
+    pub mode: Option<AppMode>,
+
+    /// Directory for the application and audit logs
+    #[arg(long, value_name = "DIR")]
+    pub log_dir: Option<PathBuf>,
+
+    /// Run the preflight checks, print the report and exit
+    #[arg(long)]
+    pub preflight_only: bool,
+
+    /// Verify the chain of an audit log file and exit
+    #[arg(long, value_name = "FILE")]
+    pub verify_audit: Option<PathBuf>,
+
+    /// Continue an exam session after the app was killed, used by the watchdog relaunch.
+    /// The session must have been saved and not be in use by a running app
+    #[arg(long, value_name = "SESSION")]
+    pub resume: Option<String>,
+
+    /// Bare `kiosk` or `practice`, as passed by older launch scripts
+    #[arg(value_enum, hide = true)]
+    pub legacy_mode: Option<AppMode>,
+}
+
+/// Settings for this launch after the command line and the policy are merged.
+#[derive(Debug, Clone)]
+pub struct LaunchConfig {
+    pub mode: AppMode,
+    pub seat: Option<String>,
+    pub exam_url: Option<Url>,
+    pub log_dir: Option<PathBuf>,
+    /// Session to continue instead of starting a new one
+    pub resume: Option<String>,
+}
+
+pub fn validate_seat(seat: &str) -> Result<String, String> {
+    let seat = seat.trim();
+    if seat.is_empty() || seat.len() > MAX_SEAT_LENGTH {
+        return Err(format!(
+            "seat must be between 1 and {} characters",
+            MAX_SEAT_LENGTH
+        ));
+    }
+    if !seat
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+    {
+        return Err(format!(
+            "seat `{}` may only contain letters, digits, `-` and `_`",
+            seat
+        ));
+    }
+    Ok(seat.to_string())
+}
+
+pub fn validate_exam_url(url: &str) -> Result<Url, String> {
+    let parsed = Url::parse(url).map_err(|err| format!("invalid exam url `{}`: {}", url, err))?;
+    match parsed.scheme() {
+        "https" => Ok(parsed),
+        "http" if matches!(parsed.host_str(), Some("localhost" | "127.0.0.1")) => Ok(parsed),
+        scheme => Err(format!(
+            "exam url must use https, `{}` is not allowed",
+            scheme
+        )),
+    }
+}
+
+impl Cli {
+    /// The policy named on the command line, else the one from the environment.
+    /// Kiosk builds only load the signed policy they were installed with.
+    pub fn load_policy(&self) -> Result<Policy, String> {
+        if let Some(key) = policy::public_key() {
+            return self.load_installed_policy(&key?, std::env::var_os(POLICY_ENV), || {
+                policy::installed_path()
+            });
+        }
+        match &self.policy {
+            Some(path) => Policy::load(path),
+            None => Policy::from_env(),
+        }
+    }
+
+    /// The kiosk build's policy: the one at `installed`, signed with `key`. Neither `--policy`
+    /// nor `env_policy`, the value of SECURE_BROWSER_POLICY, may name another.
+    fn load_installed_policy(
+        &self,
+        key: &VerifyingKey,
+        env_policy: Option<OsString>,
+        installed: impl FnOnce() -> Result<PathBuf, String>,
+    ) -> Result<Policy, String> {
+        if self.policy.is_some() {
+            return Err(
+                "kiosk builds only run their installed policy, --policy is not accepted".into(),
+            );
+        }
+        if env_policy.is_some_and(|path| !path.is_empty()) {
+            return Err(format!(
+                "kiosk builds only run their installed policy, unset {}",
+                POLICY_ENV
+            ));
+        }
+        Policy::load_signed(&installed()?, key)
+    }
+
+    /// Merges the command line over the policy.
+    /// In kiosk mode the fields the policy lists in `locked` cannot be overridden, and a
+    /// locked `mode` cannot be overridden at all, so a candidate cannot launch their way out.
+    /// A policy without a `mode`, or no policy at all, means kiosk with the mode locked, and
+    /// kiosk builds run nothing else.
+    pub fn resolve(&self, policy: &Policy) -> Result<LaunchConfig, String> {
+        let mode = resolve_mode(
+            self.mode.or(self.legacy_mode),
+            policy.mode,
+            policy.is_locked("mode"),
+            policy::is_kiosk_build(),
+        )?;
+
+        let check_override = |field: &str, overridden: bool| {
+            if overridden && mode.is_kiosk() && policy.is_locked(field) {
+                Err(format!(
+                    "`{}` is locked by the policy and cannot be overridden in kiosk mode",
+                    field
+                ))
+            } else {
+                Ok(())
+            }
+        };
+        check_override("seat", self.seat.is_some())?;
+        check_override("exam_url", self.exam_url.is_some())?;
+        check_override("log_dir", self.log_dir.is_some())?;
+
+        let seat = match self.seat.as_ref().or(policy.seat.as_ref()) {
+            Some(seat) => Some(validate_seat(seat)?),
+            None => None,
+        };
+        let exam_url = match self.exam_url.as_ref().or(policy.exam_url.as_ref()) {
+            Some(url) => Some(validate_exam_url(url)?),
+            None => None,
+        };
+        let resume = match &self.resume {
+            Some(id) => Some(validate_session_id(id)?),
+            None => None,
+        };
+        Ok(LaunchConfig {
+            mode,
+            seat,
+            exam_url,
+            log_dir: self.log_dir.clone().or(policy.log_dir.clone()),
+            resume,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::policy::PolicyFile;
+    use ed25519_dalek::SigningKey;
+
+    const SESSION: &str = "0123456789abcdef0123456789abcdef";
+
+    fn cli(args: &[&str]) -> Cli {
+        Cli::try_parse_from(std::iter::once("secure-browser").chain(args.iter().copied())).unwrap()
+    }
+
+    fn policy(mode: AppMode, locked: &[&str]) -> Policy {
+        Policy {
+            mode: Some(mode),
+            seat: Some("A1".into()),
+            exam_url: Some("https://exam.example.com/start".into()),
+            locked: locked.iter().map(|field| field.to_string()).collect(),
+            ..Policy::default()
+        }
+    }
+
+    #[test]
+    fn the_policy_fills_in_what_the_command_line_leaves_out() {
+        let launch = cli(&[])
+            .resolve(&policy(AppMode::Kiosk, &["seat"]))
+            .unwrap();
+        assert_eq!(launch.mode, AppMode::Kiosk);
+        assert_eq!(launch.seat.as_deref(), Some("A1"));
+        assert_eq!(
+            launch.exam_url.unwrap().as_str(),
+            "https://exam.example.com/start"
+        );
+        assert_eq!(launch.resume, None);
+    }
+
+    #[test]
+    fn locked_fields_cannot_be_overridden_in_kiosk_mode() {
+        let locked = policy(AppMode::Kiosk, &["seat", "exam_url", "log_dir"]);
+        for args in [
+            &["--seat", "B2"][..],
+            &["--exam-url", "https://evil.example.com"],
+            &["--log-dir", "/tmp/elsewhere"],
+        ] {
+            let err = cli(args).resolve(&locked).unwrap_err();
+            assert!(
+                err.contains("is locked by the policy"),
+                "{:?}: {}",
+                args,
+                err
+            );
+        }
+        // a field the policy does not lock can be set
+        let launch = cli(&["--seat", "B2"])
+            .resolve(&policy(AppMode::Kiosk, &["exam_url"]))
+            .unwrap();
+        assert_eq!(launch.seat.as_deref(), Some("B2"));
+        // nor can a locked mode, or a policy without one, be left for practice
+        assert!(cli(&["--mode", "practice"])
+            .resolve(&policy(AppMode::Kiosk, &["mode"]))
+            .is_err());
+        assert!(cli(&["practice"]).resolve(&Policy::default()).is_err());
+    }
+
+    #[test]
+    fn locked_fields_can_be_overridden_in_practice_mode() {
+        let launch = cli(&["--seat", "B2", "--exam-url", "http://localhost:8080/"])
+            .resolve(&policy(AppMode::Practice, &["seat", "exam_url"]))
+            .unwrap();
+        assert_eq!(launch.mode, AppMode::Practice);
+        assert_eq!(launch.seat.as_deref(), Some("B2"));
+        assert_eq!(launch.exam_url.unwrap().as_str(), "http://localhost:8080/");
+    }
+
+    #[test]
+    fn values_from_either_side_are_validated() {
+        assert!(cli(&["--seat", "B 2"])
+            .resolve(&policy(AppMode::Kiosk, &[]))
+            .is_err());
+        let mut bad_policy = policy(AppMode::Kiosk, &[]);
+        bad_policy.exam_url = Some("http://exam.example.com".into());
+        assert!(cli(&[]).resolve(&bad_policy).is_err());
+
+        let launch = cli(&["--resume", &SESSION.to_uppercase()])
+            .resolve(&policy(AppMode::Kiosk, &[]))
+            .unwrap();
+        assert_eq!(launch.resume.as_deref(), Some(SESSION));
+        assert!(cli(&["--resume", "../../etc/passwd"])
+            .resolve(&policy(AppMode::Kiosk, &[]))
+            .is_err());
+    }
+
+    #[test]
+    fn seats_are_short_names() {
+        assert_eq!(validate_seat(" A-01_b "), Ok("A-01_b".to_string()));
+        assert_eq!(validate_seat(&"7".repeat(32)), Ok("7".repeat(32)));
+        for seat in ["", "   ", "A 1", "A/1", "../A1", "Ä1", &"7".repeat(33)] {
+            assert!(validate_seat(seat).is_err(), "{:?}", seat);
+        }
+    }
+
+    #[test]
+    fn exam_urls_use_https_or_a_local_server() {
+        for url in [
+            "https://exam.example.com/start",
+            "http://localhost:8080/",
+            "http://127.0.0.1/exam",
+        ] {
+            assert!(validate_exam_url(url).is_ok(), "{}", url);
+        }
+        for url in [
+            "http://exam.example.com/",
+            "http://localhost.evil.com/",
+            "http://10.0.0.5/",
+            "file:///etc/passwd",
+            "javascript:alert(1)",
+            "exam.example.com",
+        ] {
+            assert!(validate_exam_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn verify_audit_takes_a_file() {
+        let cli = cli(&["--verify-audit", "/var/log/exam/audit.jsonl"]);
+        assert_eq!(
+            cli.verify_audit.as_deref(),
+            Some(std::path::Path::new("/var/log/exam/audit.jsonl"))
+        );
+        assert!(Cli::try_parse_from(["secure-browser", "--verify-audit"]).is_err());
+    }
+
+    #[test]
+    fn kiosk_builds_only_run_the_signed_installed_policy() {
+        let signing_key = SigningKey::from_bytes(&[4; 32]);
+        let key = signing_key.verifying_key();
+        let installed =
+            std::env::temp_dir().join(format!("installed-policy-{}.json", std::process::id()));
+        let write = |file: &PolicyFile| {
+            std::fs::write(&installed, serde_json::to_string(file).unwrap()).unwrap()
+        };
+        write(&PolicyFile::sign(r#"{"seat": "K9"}"#, &signing_key).unwrap());
+        let path = || Ok(installed.clone());
+
+        let policy = cli(&[]).load_installed_policy(&key, None, path).unwrap();
+        assert_eq!(policy.seat.as_deref(), Some("K9"));
+        // an empty variable is no policy
+        assert!(cli(&[])
+            .load_installed_policy(&key, Some(OsString::new()), path)
+            .is_ok());
+
+        let err = cli(&["--policy", "/tmp/open.json"])
+            .load_installed_policy(&key, None, path)
+            .unwrap_err();
+        assert!(err.contains("--policy is not accepted"), "{}", err);
+        let err = cli(&[])
+            .load_installed_policy(&key, Some("/tmp/open.json".into()), path)
+            .unwrap_err();
+        assert!(err.contains(POLICY_ENV), "{}", err);
+
+        // signed by someone else
+        let other = SigningKey::from_bytes(&[5; 32]);
+        write(&PolicyFile::sign(r#"{"seat": "K9"}"#, &other).unwrap());
+        assert!(cli(&[]).load_installed_policy(&key, None, path).is_err());
+        // a plain policy in its place
+        std::fs::write(&installed, r#"{"seat": "K9"}"#).unwrap();
+        assert!(cli(&[]).load_installed_policy(&key, None, path).is_err());
+        std::fs::remove_file(&installed).unwrap();
+        assert!(cli(&[]).load_installed_policy(&key, None, path).is_err());
+    }
+}
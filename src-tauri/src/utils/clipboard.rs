@@ -0,0 +1,109 @@
+//! Clipboard guard for the exam session.
+//!
+//! The clipboard is emptied when the session starts and when it ends, so nothing copied
+//! before the exam can be pasted in and nothing from the exam is left behind. While the exam
+//! runs the clipboard is polled and any change is treated as coming from outside the app,
+//! since copying out of the exam page is blocked in kiosk mode. Only a hash of the content is
+//! kept, never the content itself.
+
+use crate::utils::session::record_violation;
+use crate::utils::types::ViolationKind;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tauri::AppHandle;
+use tauri_plugin_clipboard_manager::ClipboardExt;
+
+/// Cancels paste and drop-in text on every page of the exam window. Each blocked attempt is
+/// reported back so it ends up in the violation list.
+pub const PASTE_BLOCK_SCRIPT: &str = r#"
+(function () {
+    function block(event) {
+        event.preventDefault();
+        event.stopImmediatePropagation();
+        if (window.__TAURI_INTERNALS__) {
+            window.__TAURI_INTERNALS__.invoke("report_blocked_paste").catch(function () {});
+        }
+    }
+    document.addEventListener("paste", block, true);
+    document.addEventListener("beforeinput", function (event) {
+        if (event.inputType === "insertFromPaste" || event.inputType === "insertFromDrop") {
+            block(event);
+        }
+    }, true);
+})();
+"#;
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ClipboardPolicy {
+    /// Empty the clipboard at session start and end
+    pub clear: bool,
+    /// Cancel every paste into the exam window
+    pub block_paste: bool,
+    /// Seconds between clipboard checks, 0 turns monitoring off
+    pub poll_secs: u64,
+}
+
+impl Default for ClipboardPolicy {
+    fn default() -> Self {
+        Self {
+            clear: true,
+            block_paste: true,
+            poll_secs: 2,
+        }
+    }
+}
+
+/// Remembers the hash of the last clipboard content seen.
+#[derive(Debug, Default)]
+pub struct ClipboardWatcher {
+    last: Option<[u8; 32]>,
+}
+
+impl ClipboardWatcher {
+    /// Records `content` and returns whether it differs from the previous check.
+    /// The first check only sets the baseline.
+    pub fn check(&mut self, content: &[u8]) -> bool {
+        let hash: [u8; 32] = Sha256::digest(content).into();
+        let changed = self.last.is_some_and(|last| last != hash);
+        self.last = Some(hash);
+        changed
+    }
+
+    /// Sets the baseline to an empty clipboard, after the app cleared it.
+    pub fn cleared(&mut self) {
+        self.last = Some(Sha256::digest([]).into());
+    }
+}
+
+/// Current clipboard content as bytes, text first and image pixels otherwise.
+/// An empty or unreadable clipboard reads as no bytes.
+pub fn read_content(app: &AppHandle) -> Vec<u8> {
+    let clipboard = app.clipboard();
+    if let Ok(text) = clipboard.read_text() {
+        return text.into_bytes();
+    }
+    match clipboard.read_image() {
+        Ok(image) => image.rgba().to_vec(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub fn clear(app: &AppHandle) -> bool {
+    match app.clipboard().clear() {
+        Ok(()) => true,
+        Err(err) => {
+            log::error!("Could not clear the clipboard: {}", err);
+            false
+        }
+    }
+}
+
+/// Called by [`PASTE_BLOCK_SCRIPT`] whenever a paste was cancelled.
+pub fn paste_blocked(app: &AppHandle) {
+    record_violation(
+        app,
+        ViolationKind::Clipboard,
+        "Paste into the exam window was blocked",
+    );
+}
@@ -0,0 +1,93 @@
+//! Commands the exam front-end can invoke.
+//! Every command here must also be listed in `build.rs` so a permission is generated for it,
+//! and granted in `utils::capability`.
+
+use crate::utils::autosave::{AnswerState, SavedAnswer};
+use crate::utils::events::LockNotice;
+use crate::utils::focus::FocusState;
+use crate::utils::session::{DetectorState, SessionStore};
+use crate::utils::sync::SyncStatus;
+use crate::utils::types::{DetectorStatus, HostInfo, SessionState, Violation};
+use serde_json::Value;
+use std::time::Instant;
+use tauri::{AppHandle, State};
+
+#[tauri::command]
+pub fn get_host_info() -> HostInfo {
+    crate::utils::get_host_info()
+}
+
+#[tauri::command]
+pub fn get_detector_status(
+    detectors: State<'_, DetectorState>,
+    focus: State<'_, FocusState>,
+) -> Result<DetectorStatus, String> {
+    let mut status = detectors.0.lock().map_err(|err| err.to_string())?.clone();
+    status.focus = focus
+        .0
+        .lock()
+        .map_err(|err| err.to_string())?
+        .summary(Instant::now());
+    Ok(status)
+}
+
+#[tauri::command]
+pub fn get_session_state(session: State<'_, SessionStore>) -> Result<SessionState, String> {
+    let session = session.0.lock().map_err(|err| err.to_string())?;
+    Ok(session.state.clone())
+}
+
+#[tauri::command]
+pub fn get_violations(session: State<'_, SessionStore>) -> Result<Vec<Violation>, String> {
+    let session = session.0.lock().map_err(|err| err.to_string())?;
+    Ok(session.violations.clone())
+}
+
+/// Opens the offline exam package with the code read out by the proctor.
+#[tauri::command]
+pub async fn unlock_exam_package(app: AppHandle, code: String) -> Result<(), String> {
+    crate::utils::offline::unlock_with_code(&app, code).await
+}
+
+/// The current lock with its unlock challenge, for a lock screen that was reloaded.
+#[tauri::command]
+pub fn get_lock_notice(session: State<'_, SessionStore>) -> Result<Option<LockNotice>, String> {
+    let session = session.0.lock().map_err(|err| err.to_string())?;
+    Ok(session.lock.as_ref().map(|event| event.notice.clone()))
+}
+
+/// Lifts the lock with the code a proctor made for its challenge.
+#[tauri::command]
+pub fn unlock_session(app: AppHandle, proctor: String, code: String) -> Result<(), String> {
+    crate::utils::unlock::unlock_with_code(&app, &proctor, &code)
+}
+
+/// Writes an answer to the encrypted local store, the sync queue submits it from there.
+#[tauri::command]
+pub fn autosave_answer(
+    answers: State<'_, AnswerState>,
+    question_id: String,
+    answer: Value,
+) -> Result<SavedAnswer, String> {
+    let mut store = answers.0.lock().map_err(|err| err.to_string())?;
+    let store = store.as_mut().ok_or("the answer store is not available")?;
+    let record = store
+        .save(&question_id, answer)
+        .map_err(|err| format!("could not save the answer: {}", err))?;
+    Ok(SavedAnswer {
+        seq: record.seq,
+        idempotency_key: record.idempotency_key,
+        saved_at: record.saved_at,
+    })
+}
+
+#[tauri::command]
+pub fn get_sync_status(app: AppHandle) -> Result<SyncStatus, String> {
+    crate::utils::sync::current_status(&app)
+}
+
+/// Reported by the paste blocking script each time it cancels a paste.
+#[tauri::command]
+pub fn report_blocked_paste(app: AppHandle) {
+    crate::utils::clipboard::paste_blocked(&app);
+}
@@ -0,0 +1,274 @@
+//! Content-Security-Policy and page protections for the exam webview.
+//!
+//! The exam is served remotely, so its response headers are out of our hands. The CSP is
+//! injected as a `<meta>` tag before any page script runs instead, and popups are disabled
+//! from the same script. In kiosk mode devtools, the context menu, copying and dragging
+//! content out of the page are switched off as well.
+//!
+//! Browsers ignore `frame-ancestors`, `sandbox` and `report-uri` in a `<meta>` CSP, so the
+//! generated policy leaves them out. Whatever the policy, `connect-src` keeps the sources the
+//! Tauri IPC bridge uses, otherwise the page could no longer reach the app.
+
+use crate::utils::mode::AppMode;
+use serde::{Deserialize, Serialize};
+
+/// Sources the Tauri IPC bridge connects to, `ipc:` on most platforms and `http://ipc.localhost` on Windows.
+const IPC_SOURCES: &str = "ipc: http://ipc.localhost";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ContentPolicy {
+    /// Replaces the generated CSP entirely when set
+    pub csp: Option<String>,
+    /// Extra sources the exam may fetch from or connect to, e.g. its API host
+    pub connect_sources: Vec<String>,
+    /// Let the exam embed frames from the allowed origins
+    pub allow_frames: bool,
+}
+
+impl ContentPolicy {
+    /// The CSP for pages served from `origins`.
+    pub fn effective_csp(&self, origins: &[String]) -> String {
+        if let Some(csp) = &self.csp {
+            return with_ipc_sources(csp);
+        }
+        let origins = origins.join(" ");
+        let connect = self.connect_sources.join(" ");
+        let frames = if self.allow_frames {
+            format!("'self' {}", origins)
+        } else {
+            "'none'".into()
+        };
+        format!(
+            "default-src 'self' {origins}; \
+             script-src 'self' 'unsafe-inline' {origins}; \
+             style-src 'self' 'unsafe-inline' {origins}; \
+             img-src 'self' data: blob: {origins}; \
+             font-src 'self' data: {origins}; \
+             connect-src 'self' {IPC_SOURCES} {origins} {connect}; \
+             frame-src {frames}; \
+             child-src {frames}; \
+             object-src 'none'; \
+             base-uri 'self'; \
+             form-action 'self' {origins}"
+        )
+    }
+}
+
+/// Adds the IPC sources to a configured CSP. They join `connect-src`, or a `connect-src` copied
+/// from `default-src` when connections fall back to it. A policy with neither allows any
+/// connection already. `'none'` cannot be combined with other sources, so it is dropped.
+fn with_ipc_sources(csp: &str) -> String {
+    let mut directives: Vec<Vec<&str>> = csp
+        .split(';')
+        .map(|directive| directive.split_whitespace().collect::<Vec<_>>())
+        .filter(|directive| !directive.is_empty())
+        .collect();
+    let named = |directives: &[Vec<&str>], name: &str| {
+        directives
+            .iter()
+            .position(|directive| directive[0].eq_ignore_ascii_case(name))
+    };
+    let index = match (
+        named(&directives, "connect-src"),
+        named(&directives, "default-src"),
+    ) {
+        (Some(index), _) => index,
+        (None, Some(fallback)) => {
+            let mut connect = directives[fallback].clone();
+            connect[0] = "connect-src";
+            directives.push(connect);
+            directives.len() - 1
+        }
+        (None, None) => return csp.to_string(),
+    };
+    directives[index].retain(|source| *source != "'none'");
+    directives[index].extend(IPC_SOURCES.split_whitespace());
+    directives
+        .iter()
+        .map(|directive| directive.join(" "))
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// The protections actually applied to the exam webview, for the preflight report.
+#[derive(Debug, Clone, Serialize)]
+pub struct ContentProtections {
+    pub csp: String,
+    pub frames_blocked: bool,
+    pub popups_blocked: bool,
+    pub devtools_disabled: bool,
+    pub context_menu_blocked: bool,
+    pub copy_blocked: bool,
+    pub drag_out_blocked: bool,
+}
+
+impl ContentProtections {
+    pub fn new(mode: AppMode, policy: &ContentPolicy, origins: &[String]) -> Self {
+        let lockdown = mode.lockdown();
+        Self {
+            csp: policy.effective_csp(origins),
+            frames_blocked: !policy.allow_frames && policy.csp.is_none(),
+            popups_blocked: true,
+            devtools_disabled: !lockdown.devtools,
+            context_menu_blocked: lockdown.block_page_interaction,
+            copy_blocked: lockdown.block_page_interaction,
+            drag_out_blocked: lockdown.block_page_interaction,
+        }
+    }
+}
+
+/// Injected before any page script: adds the CSP `<meta>` tag and disables `window.open`.
+pub fn csp_script(csp: &str) -> String {
+    format!(
+        r#"(function () {{
+    var csp = {csp};
+    function apply() {{
+        var head = document.head || document.documentElement;
+        if (!head) return false;
+        var meta = document.createElement("meta");
+        meta.httpEquiv = "Content-Security-Policy";
+        meta.content = csp;
+        head.insertBefore(meta, head.firstChild);
+        return true;
+    }}
+    if (!apply()) {{
+        var observer = new MutationObserver(function () {{
+            if (apply()) observer.disconnect();
+        }});
+        observer.observe(document, {{ childList: true, subtree: true }});
+    }}
+    window.open = function () {{ return null; }};
+}})();"#,
+        csp = serde_json::to_string(csp).unwrap_or_else(|_| "\"\"".into())
+    )
+}
+
+/// Injected in kiosk mode: no context menu, no copying page text, no dragging content out.
+pub const INTERACTION_LOCK_SCRIPT: &str = r#"(function () {
+    ["contextmenu", "copy", "cut", "dragstart", "drop"].forEach(function (type) {
+        document.addEventListener(type, function (event) {
+            event.preventDefault();
+        }, true);
+    });
+})();"#;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn directive<'a>(csp: &'a str, name: &str) -> Option<&'a str> {
+        csp.split(';')
+            .map(str::trim)
+            .find(|directive| directive.split_whitespace().next() == Some(name))
+    }
+
+    fn origins() -> Vec<String> {
+        vec!["https://exam.example.org".into()]
+    }
+
+    #[test]
+    fn generated_csp_lets_the_page_reach_the_app() {
+        let csp = ContentPolicy::default().effective_csp(&origins());
+        let connect = directive(&csp, "connect-src").unwrap();
+        assert!(connect.contains(" ipc: "), "{}", connect);
+        assert!(connect.contains(" http://ipc.localhost "), "{}", connect);
+    }
+
+    #[test]
+    fn generated_csp_merges_origins_and_connect_sources() {
+        let policy = ContentPolicy {
+            connect_sources: vec!["wss://live.example.org".into()],
+            ..Default::default()
+        };
+        let csp = policy.effective_csp(&origins());
+        for name in [
+            "default-src",
+            "script-src",
+            "style-src",
+            "connect-src",
+            "form-action",
+        ] {
+            let sources = directive(&csp, name).unwrap();
+            assert!(sources.contains("https://exam.example.org"), "{}", sources);
+        }
+        assert!(directive(&csp, "connect-src")
+            .unwrap()
+            .ends_with("wss://live.example.org"));
+        assert!(!directive(&csp, "default-src")
+            .unwrap()
+            .contains("wss://live.example.org"));
+    }
+
+    #[test]
+    fn generated_csp_only_allows_frames_when_asked() {
+        let blocked = ContentPolicy::default().effective_csp(&origins());
+        assert_eq!(directive(&blocked, "frame-src"), Some("frame-src 'none'"));
+        assert_eq!(directive(&blocked, "child-src"), Some("child-src 'none'"));
+
+        let policy = ContentPolicy {
+            allow_frames: true,
+            ..Default::default()
+        };
+        let allowed = policy.effective_csp(&origins());
+        assert_eq!(
+            directive(&allowed, "frame-src"),
+            Some("frame-src 'self' https://exam.example.org")
+        );
+    }
+
+    #[test]
+    fn generated_csp_leaves_out_directives_a_meta_tag_ignores() {
+        let csp = ContentPolicy::default().effective_csp(&origins());
+        for name in ["frame-ancestors", "sandbox", "report-uri"] {
+            assert_eq!(directive(&csp, name), None);
+        }
+    }
+
+    #[test]
+    fn configured_csp_keeps_its_directives_and_gains_ipc_sources() {
+        let policy = ContentPolicy {
+            csp: Some("default-src 'self'; connect-src https://api.example.org;".into()),
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.effective_csp(&origins()),
+            "default-src 'self'; connect-src https://api.example.org ipc: http://ipc.localhost"
+        );
+    }
+
+    #[test]
+    fn configured_csp_without_connect_src_copies_the_default() {
+        assert_eq!(
+            with_ipc_sources("default-src 'self' https://exam.example.org"),
+            "default-src 'self' https://exam.example.org; \
+             connect-src 'self' https://exam.example.org ipc: http://ipc.localhost"
+        );
+        assert_eq!(
+            with_ipc_sources("default-src 'none'; script-src 'self'"),
+            "default-src 'none'; script-src 'self'; connect-src ipc: http://ipc.localhost"
+        );
+    }
+
+    #[test]
+    fn configured_csp_drops_none_from_connect_src() {
+        assert_eq!(
+            with_ipc_sources("CONNECT-SRC 'none'"),
+            "CONNECT-SRC ipc: http://ipc.localhost"
+        );
+    }
+
+    #[test]
+    fn configured_csp_without_a_connection_fallback_is_unchanged() {
+        assert_eq!(with_ipc_sources("img-src 'self'"), "img-src 'self'");
+    }
+
+    #[test]
+    fn csp_script_injects_the_policy_as_a_meta_tag() {
+        let script = csp_script("default-src 'self'; script-src \"x\"</script>");
+        assert!(script.contains(r#"var csp = "default-src 'self'; script-src \"x\"</script>";"#));
+        assert!(script.contains(r#"meta.httpEquiv = "Content-Security-Policy";"#));
+        assert!(script.contains("head.insertBefore(meta, head.firstChild);"));
+        assert!(script.contains("window.open = function () { return null; };"));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::utils::content::ContentProtections;
+use crate::utils::types::{DiagnosticReport, Finding, Severity};
+use crate::utils::{
+    get_host_info, is_disallowed_device_connected, is_running_in_rdp, is_virtual_machine,
+    is_web_rtc_running,
+};
+
+/// Runs every detector once and collects the results into a report.
+/// This does not need a running tauri app, so it can be used by the headless diagnostics binary.
+pub fn run_checks() -> DiagnosticReport {
+    let mut findings = vec![];
+    let host = get_host_info();
+
+    findings.push(if host.unreadable.is_empty() {
+        Finding {
+            check: "hardware_identity".into(),
+            severity: Severity::Ok,
+            detail: "All hardware identity fields readable".into(),
+        }
+    } else {
+        Finding {
+            check: "hardware_identity".into(),
+            severity: Severity::Warning,
+            detail: format!("Permission denied reading: {}", host.unreadable.join(", ")),
+        }
+    });
+
+    findings.push(if is_virtual_machine() {
+        Finding {
+            check: "virtual_machine".into(),
+            severity: Severity::Critical,
+            detail: "Running inside a virtual machine".into(),
+        }
+    } else {
+        Finding {
+            check: "virtual_machine".into(),
+            severity: Severity::Ok,
+            detail: "No hypervisor detected".into(),
+        }
+    });
+
+    findings.push(if is_running_in_rdp() {
+        Finding {
+            check: "remote_desktop".into(),
+            severity: Severity::Critical,
+            detail: "Running inside a remote desktop session".into(),
+        }
+    } else {
+        Finding {
+            check: "remote_desktop".into(),
+            severity: Severity::Ok,
+            detail: "Not a remote desktop session".into(),
+        }
+    });
+
+    let devices = is_disallowed_device_connected();
+    findings.push(if devices.is_empty() {
+        Finding {
+            check: "usb_devices".into(),
+            severity: Severity::Ok,
+            detail: "No disallowed usb devices connected".into(),
+        }
+    } else {
+        let names: Vec<String> = devices
+            .iter()
+            .map(|device| device.description.clone().unwrap_or("unnamed".into()))
+            .collect();
+        Finding {
+            check: "usb_devices".into(),
+            severity: Severity::Critical,
+            detail: format!("Disallowed usb devices connected: {}", names.join(", ")),
+        }
+    });
+
+    let report = is_web_rtc_running();
+    findings.push(if report.is_running() {
+        Finding {
+            check: "web_rtc".into(),
+            severity: Severity::Critical,
+            detail: format!(
+                "{} known remote application(s) running with {} udp port(s) in use",
+                report.processes.len(),
+                report.ports.len()
+            ),
+        }
+    } else if !report.processes.is_empty() {
+        Finding {
+            check: "web_rtc".into(),
+            severity: Severity::Warning,
+            detail: format!(
+                "{} known remote application(s) running but no udp ports in use",
+                report.processes.len()
+            ),
+        }
+    } else if !report.ports.is_empty() {
+        Finding {
+            check: "web_rtc".into(),
+            severity: Severity::Warning,
+            detail: format!(
+                "{} udp port(s) in use by an unknown application",
+                report.ports.len()
+            ),
+        }
+    } else {
+        Finding {
+            check: "web_rtc".into(),
+            severity: Severity::Ok,
+            detail: "No WebRTC connection found".into(),
+        }
+    });
+
+    DiagnosticReport {
+        generated_at: chrono::Utc::now().to_rfc3339(),
+        host,
+        findings,
+    }
+}
+
+/// Findings describing the protections applied to the exam webview, for the preflight report.
+pub fn content_findings(protections: &ContentProtections) -> Vec<Finding> {
+    let finding = |check: &str, enforced: bool, detail: &str| Finding {
+        check: check.into(),
+        severity: Severity::Ok,
+        detail: format!("{}: {}", if enforced { "enforced" } else { "off" }, detail),
+    };
+    vec![
+        Finding {
+            check: "csp".into(),
+            severity: if protections.frames_blocked {
+                Severity::Ok
+            } else {
+                Severity::Warning
+            },
+            detail: protections.csp.clone(),
+        },
+        finding(
+            "popups",
+            protections.popups_blocked,
+            "window.open and new windows",
+        ),
+        finding(
+            "devtools",
+            protections.devtools_disabled,
+            "web inspector disabled",
+        ),
+        finding(
+            "context_menu",
+            protections.context_menu_blocked,
+            "right click menu blocked",
+        ),
+        finding(
+            "copy",
+            protections.copy_blocked,
+            "copying page text blocked",
+        ),
+        finding(
+            "drag_out",
+            protections.drag_out_blocked,
+            "dragging content out of the page blocked",
+        ),
+    ]
+}
+
+/// Formats a report for support staff reading it in a terminal.
+pub fn render_human(report: &DiagnosticReport) -> String {
+    let mut out = String::new();
+    out.push_str(&format!(
+        "Secure Browser diagnostics ({})\n",
+        report.generated_at
+    ));
+    out.push_str(&format!(
+        "Host: {} / {}, fingerprint: {}\n\n",
+        report.host.os,
+        report.host.arch,
+        report
+            .host
+            .fingerprint
+            .as_ref()
+            .map_or("none", |fingerprint| fingerprint.id.as_str()),
+    ));
+    for finding in &report.findings {
+        let label = match finding.severity {
+            Severity::Ok => "OK",
+            Severity::Warning => "WARN",
+            Severity::Critical => "FAIL",
+        };
+        out.push_str(&format!(
+            "[{:<4}] {:<16} {}\n",
+            label, finding.check, finding.detail
+        ));
+    }
+    out.push_str(&format!("\nWorst finding: {:?}\n", report.worst()));
+    out
+}
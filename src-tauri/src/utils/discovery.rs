@@ -0,0 +1,216 @@
+//! Finding the proctor console on the LAN over mDNS, see `shared::discovery`.
+//!
+//! Every console found is asked to sign a fresh nonce and only one holding the key pinned in
+//! the policy as `proctor.console_key` is used. Without a verified console the heartbeats go
+//! to `proctor.endpoint`.
+//! For a console on the same machine, run it with `--mdns-loopback` and set `loopback`.
+
+use crate::utils::audit::audit;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use mdns_sd::{IfKind, ServiceDaemon, ServiceEvent};
+use serde::Deserialize;
+use shared::discovery::{
+    base_url, verify_identity, IdentityProof, IDENTITY_PATH, NONCE_LENGTH, PATH_PROPERTY,
+    SERVICE_TYPE,
+};
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+use tauri::AppHandle;
+
+const DEFAULT_PATH: &str = "/heartbeat";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DiscoveryPolicy {
+    pub enabled: bool,
+    /// How long to listen for consoles
+    pub timeout_secs: u64,
+    /// Also browse on 127.0.0.1
+    pub loopback: bool,
+}
+
+impl Default for DiscoveryPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            timeout_secs: 3,
+            loopback: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    /// Full service name, e.g. `proctor-console._secure-proctor._tcp.local.`
+    pub instance: String,
+    pub base_url: String,
+    pub path: String,
+}
+
+/// Lists the consoles that answer within `timeout`. Blocks, so call it off the async workers.
+pub fn browse(timeout: Duration, loopback: bool) -> Result<Vec<Candidate>, String> {
+    let daemon = ServiceDaemon::new().map_err(|err| err.to_string())?;
+    if loopback {
+        daemon
+            .enable_interface(IfKind::LoopbackV4)
+            .map_err(|err| err.to_string())?;
+    }
+    let events = daemon.browse(SERVICE_TYPE).map_err(|err| err.to_string())?;
+    let deadline = Instant::now() + timeout;
+    let mut found: Vec<Candidate> = Vec::new();
+    while let Some(left) = deadline.checked_duration_since(Instant::now()) {
+        let Ok(event) = events.recv_timeout(left) else {
+            break;
+        };
+        let ServiceEvent::ServiceResolved(info) = event else {
+            continue;
+        };
+        let path = info
+            .get_property_val_str(PATH_PROPERTY)
+            .filter(|path| path.starts_with('/'))
+            .unwrap_or(DEFAULT_PATH);
+        // link-local IPv6 needs a zone that does not fit in a URL
+        let mut addresses: Vec<IpAddr> = info
+            .get_addresses()
+            .iter()
+            .copied()
+            .filter(|address| match address {
+                IpAddr::V4(_) => true,
+                IpAddr::V6(address) => address.segments()[0] & 0xffc0 != 0xfe80,
+            })
+            .collect();
+        addresses.sort_by_key(IpAddr::is_ipv6);
+        for address in addresses {
+            let candidate = Candidate {
+                instance: info.get_fullname().to_string(),
+                base_url: base_url(address, info.get_port()),
+                path: path.to_string(),
+            };
+            if !found.contains(&candidate) {
+                found.push(candidate);
+            }
+        }
+    }
+    if let Err(err) = daemon.shutdown() {
+        log::warn!("Could not stop the mDNS browser: {}", err);
+    }
+    Ok(found)
+}
+
+async fn check_identity(
+    client: &reqwest::Client,
+    candidate: &Candidate,
+    pinned: &str,
+) -> Result<(), String> {
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    let url = format!(
+        "{}{}?nonce={}",
+        candidate.base_url,
+        IDENTITY_PATH,
+        hex::encode(nonce)
+    );
+    let proof: IdentityProof = client
+        .get(url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| err.to_string())?
+        .json()
+        .await
+        .map_err(|err| format!("invalid identity: {}", err))?;
+    verify_identity(pinned, &nonce, &proof)
+}
+
+/// The consoles found, checked against the pinned key.
+#[derive(Debug, Default)]
+pub struct Selection {
+    /// The first console that proved the pinned key, with its heartbeat URL
+    pub chosen: Option<(Candidate, String)>,
+    /// Consoles that did not, with the reason
+    pub rejected: Vec<(Candidate, String)>,
+}
+
+/// Asks each candidate in turn to sign a fresh nonce and stops at the first one holding
+/// the pinned key. Kept apart from [`browse`] so any list of candidates can be checked.
+pub async fn select(
+    client: &reqwest::Client,
+    candidates: Vec<Candidate>,
+    pinned: &str,
+) -> Selection {
+    let mut selection = Selection::default();
+    for candidate in candidates {
+        match check_identity(client, &candidate, pinned).await {
+            Ok(()) => {
+                let endpoint = format!("{}{}", candidate.base_url, candidate.path);
+                selection.chosen = Some((candidate, endpoint));
+                break;
+            }
+            Err(err) => selection.rejected.push((candidate, err)),
+        }
+    }
+    selection
+}
+
+/// Returns the heartbeat URL of a console holding the pinned key, if one is on the network.
+pub async fn discover(
+    app: &AppHandle,
+    client: &reqwest::Client,
+    policy: &DiscoveryPolicy,
+    pinned: &str,
+) -> Option<String> {
+    let timeout = Duration::from_secs(policy.timeout_secs.max(1));
+    let loopback = policy.loopback;
+    let candidates =
+        match tauri::async_runtime::spawn_blocking(move || browse(timeout, loopback)).await {
+            Ok(Ok(candidates)) => candidates,
+            Ok(Err(err)) => {
+                log::error!("Could not browse for proctor consoles: {}", err);
+                return None;
+            }
+            Err(err) => {
+                log::error!("Proctor discovery stopped: {}", err);
+                return None;
+            }
+        };
+
+    let selection = select(client, candidates, pinned).await;
+    let rejected: Vec<serde_json::Value> = selection
+        .rejected
+        .iter()
+        .map(|(candidate, err)| {
+            log::warn!(
+                "Ignoring proctor console {} at {}: {}",
+                candidate.instance,
+                candidate.base_url,
+                err
+            );
+            serde_json::json!({
+                "instance": candidate.instance,
+                "url": candidate.base_url,
+                "error": err,
+            })
+        })
+        .collect();
+    if !rejected.is_empty() {
+        // someone else may be advertising the service
+        audit(
+            app,
+            "proctor_discovery_rejected",
+            serde_json::json!({ "rejected": rejected }),
+        );
+    }
+    let (candidate, endpoint) = selection.chosen?;
+    log::info!(
+        "Found proctor console {} at {}",
+        candidate.instance,
+        endpoint
+    );
+    audit(
+        app,
+        "proctor_discovered",
+        serde_json::json!({ "instance": candidate.instance, "endpoint": endpoint }),
+    );
+    Some(endpoint)
+}
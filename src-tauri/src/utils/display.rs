@@ -0,0 +1,213 @@
+//! Display topology detection.
+//!
+//! A second monitor lets someone else watch the exam, or keeps notes visible next to the
+//! fullscreen window. Displays are counted through tauri's monitor API and, on linux,
+//! through the DRM connector status in sysfs, which also sees mirrored outputs.
+
+use crate::utils::types::{DisplayInfo, DisplayTopology};
+use serde::Deserialize;
+use std::path::Path;
+use tauri::{AppHandle, Manager, WebviewUrl, WebviewWindowBuilder};
+
+pub const LINUX_DRM_ROOT: &str = "/sys/class/drm";
+const BLACKOUT_LABEL_PREFIX: &str = "blackout-";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct DisplayPolicy {
+    /// Number of displays that may be active during the exam
+    pub max_displays: usize,
+    /// Cover every monitor but the primary one with a black window
+    pub blackout_secondary: bool,
+}
+
+impl Default for DisplayPolicy {
+    fn default() -> Self {
+        Self {
+            max_displays: 1,
+            blackout_secondary: true,
+        }
+    }
+}
+
+/// Lists the connectors reported as `connected` under a DRM sysfs directory.
+/// Connector entries look like `card0-HDMI-A-1`, the bare `card0` entries are skipped.
+pub fn read_drm_connectors(root: &Path) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut connected: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if !name.starts_with("card") || !name.contains('-') {
+                return None;
+            }
+            let status = std::fs::read_to_string(entry.path().join("status")).ok()?;
+            (status.trim() == "connected").then_some(name)
+        })
+        .collect();
+    connected.sort();
+    connected
+}
+
+pub fn read_topology(app: &AppHandle) -> DisplayTopology {
+    let monitors = match app.available_monitors() {
+        Ok(monitors) => monitors
+            .iter()
+            .map(|monitor| DisplayInfo {
+                name: monitor.name().cloned(),
+                width: monitor.size().width,
+                height: monitor.size().height,
+                x: monitor.position().x,
+                y: monitor.position().y,
+                scale_factor: monitor.scale_factor(),
+            })
+            .collect(),
+        Err(err) => {
+            log::error!("Could not enumerate monitors: {}", err);
+            vec![]
+        }
+    };
+    let drm_connectors = if cfg!(target_os = "linux") {
+        read_drm_connectors(Path::new(LINUX_DRM_ROOT))
+    } else {
+        vec![]
+    };
+    DisplayTopology {
+        monitors,
+        drm_connectors,
+    }
+}
+
+/// Remembers the topology seen at exam start so a hot-plug can be told apart from a
+/// machine that simply has the allowed number of displays, and the topology last reported
+/// so a disallowed one is reported once rather than on every poll.
+#[derive(Debug, Default)]
+pub struct DisplayWatcher {
+    baseline: Option<Vec<String>>,
+    reported: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DisplayChange {
+    Unchanged,
+    TooMany,
+    HotPlugged,
+}
+
+impl DisplayWatcher {
+    pub fn check(&mut self, topology: &DisplayTopology, policy: &DisplayPolicy) -> DisplayChange {
+        let signature = topology.signature();
+        let hot_plugged = match &self.baseline {
+            Some(baseline) => *baseline != signature,
+            None => false,
+        };
+        self.baseline = Some(signature.clone());
+
+        if self.reported.as_ref() == Some(&signature) {
+            return DisplayChange::Unchanged;
+        }
+        let change = if topology.active_count() > policy.max_displays {
+            DisplayChange::TooMany
+        } else if hot_plugged {
+            DisplayChange::HotPlugged
+        } else {
+            return DisplayChange::Unchanged;
+        };
+        self.reported = Some(signature);
+        change
+    }
+}
+
+/// Opens a black, always-on-top fullscreen window on every monitor except the one holding the exam.
+pub fn blackout_secondary_monitors(app: &AppHandle) -> tauri::Result<()> {
+    let primary = app
+        .get_webview_window("main")
+        .and_then(|window| window.current_monitor().ok().flatten())
+        .or(app.primary_monitor()?);
+    let primary_position = primary.as_ref().map(|monitor| *monitor.position());
+
+    for (index, monitor) in app.available_monitors()?.iter().enumerate() {
+        if Some(*monitor.position()) == primary_position {
+            continue;
+        }
+        let label = format!("{}{}", BLACKOUT_LABEL_PREFIX, index);
+        if app.get_webview_window(&label).is_some() {
+            continue;
+        }
+        let position = monitor.position().to_logical::<f64>(monitor.scale_factor());
+        let size = monitor.size().to_logical::<f64>(monitor.scale_factor());
+        WebviewWindowBuilder::new(app, &label, WebviewUrl::App("blackout.html".into()))
+            .title("")
+            .position(position.x, position.y)
+            .inner_size(size.width, size.height)
+            .decorations(false)
+            .always_on_top(true)
+            .skip_taskbar(true)
+            .resizable(false)
+            .focused(false)
+            .fullscreen(true)
+            .build()?;
+        log::info!("Blacked out monitor {:?}", monitor.name());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn topology(connectors: &[&str]) -> DisplayTopology {
+        DisplayTopology {
+            monitors: vec![],
+            drm_connectors: connectors.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn too_many_displays_are_reported_once_per_topology() {
+        let policy = DisplayPolicy::default();
+        let mut watcher = DisplayWatcher::default();
+        let two = topology(&["card0-eDP-1", "card0-HDMI-A-1"]);
+        assert_eq!(watcher.check(&two, &policy), DisplayChange::TooMany);
+        assert_eq!(watcher.check(&two, &policy), DisplayChange::Unchanged);
+        assert_eq!(watcher.check(&two, &policy), DisplayChange::Unchanged);
+
+        let other = topology(&["card0-eDP-1", "card0-DP-1"]);
+        assert_eq!(watcher.check(&other, &policy), DisplayChange::TooMany);
+        assert_eq!(watcher.check(&other, &policy), DisplayChange::Unchanged);
+    }
+
+    #[test]
+    fn hot_plugs_are_reported_when_they_happen() {
+        let policy = DisplayPolicy::default();
+        let mut watcher = DisplayWatcher::default();
+        let one = topology(&["card0-eDP-1"]);
+        let two = topology(&["card0-eDP-1", "card0-HDMI-A-1"]);
+        assert_eq!(watcher.check(&one, &policy), DisplayChange::Unchanged);
+        assert_eq!(watcher.check(&one, &policy), DisplayChange::Unchanged);
+        assert_eq!(watcher.check(&two, &policy), DisplayChange::TooMany);
+        assert_eq!(watcher.check(&one, &policy), DisplayChange::HotPlugged);
+        assert_eq!(watcher.check(&one, &policy), DisplayChange::Unchanged);
+        // plugging the same monitor back in is reported again
+        assert_eq!(watcher.check(&two, &policy), DisplayChange::TooMany);
+    }
+
+    #[test]
+    fn connected_drm_connectors_are_listed() {
+        let root = std::env::temp_dir().join(format!("drm-{}", std::process::id()));
+        for (name, status) in [
+            ("card0-eDP-1", "connected\n"),
+            ("card0-HDMI-A-1", "disconnected\n"),
+            ("card0-DP-1", "connected\n"),
+            ("card0", ""),
+        ] {
+            std::fs::create_dir_all(root.join(name)).unwrap();
+            std::fs::write(root.join(name).join("status"), status).unwrap();
+        }
+        let connectors = read_drm_connectors(&root);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!(connectors, ["card0-DP-1", "card0-eDP-1"]);
+    }
+}
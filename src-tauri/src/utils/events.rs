@@ -0,0 +1,233 @@
+//! Every event the secure browser sends to the webview.
+//!
+//! Events must go through [`emit`] so the names and payloads stay in sync with the
+//! TypeScript definitions in `bindings/events.d.ts`. Regenerate that file with
+//! `cargo run --bin event_types` after changing anything here.
+
+use crate::utils::autosave::SavedAnswer;
+use crate::utils::mode::AppMode;
+use crate::utils::sync::SyncStatus;
+use crate::utils::types::{SessionState, SessionStatus, Violation, ViolationKind};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tauri::{AppHandle, Emitter};
+use ts_rs::TS;
+
+/// Sent when the session is locked and the candidate can no longer interact with the exam.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct LockNotice {
+    /// Counts the locks of the session, an unlock code only opens the lock it was made for
+    #[ts(type = "number")]
+    pub lock_id: u64,
+    pub reason: String,
+    #[ts(type = "number | null")]
+    pub violation_id: Option<u64>,
+    /// Read out to the proctor for an unlock code, missing when codes are not set up
+    pub challenge: Option<String>,
+}
+
+/// Sent when the proctor gives the candidate more time. The page keeps the exam timer.
+#[derive(Debug, Clone, Serialize, TS)]
+pub struct TimeExtension {
+    pub minutes: u32,
+    /// All time added during the session
+    pub total_minutes: u32,
+}
+
+/// A message from the proctor for the candidate.
+#[derive(Debug, Clone, Serialize, TS)]
+pub struct ProctorNotice {
+    pub text: String,
+    /// Unix timestamp in milliseconds
+    #[ts(type = "number")]
+    pub received_at: i64,
+}
+
+#[derive(Debug, Clone)]
+pub enum AppEvent {
+    ShowPasswordPrompt,
+    ShowCtrlAltDeletePrompt,
+    ViolationRecorded(Violation),
+    SessionStateChanged(SessionState),
+    SessionLocked(LockNotice),
+    SessionUnlocked,
+    TimeExtended(TimeExtension),
+    ProctorMessage(ProctorNotice),
+    ExitRequested,
+}
+
+impl AppEvent {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AppEvent::ShowPasswordPrompt => "show-password-prompt",
+            AppEvent::ShowCtrlAltDeletePrompt => "show-ctrl-alt-delete-prompt",
+            AppEvent::ViolationRecorded(_) => "violation-recorded",
+            AppEvent::SessionStateChanged(_) => "session-state-changed",
+            AppEvent::SessionLocked(_) => "session-locked",
+            AppEvent::SessionUnlocked => "session-unlocked",
+            AppEvent::TimeExtended(_) => "time-extended",
+            AppEvent::ProctorMessage(_) => "proctor-message",
+            AppEvent::ExitRequested => "exit-requested",
+        }
+    }
+
+    fn payload(&self) -> Result<Value, serde_json::Error> {
+        match self {
+            AppEvent::ShowPasswordPrompt
+            | AppEvent::ShowCtrlAltDeletePrompt
+            | AppEvent::SessionUnlocked
+            | AppEvent::ExitRequested => Ok(Value::Null),
+            AppEvent::ViolationRecorded(violation) => serde_json::to_value(violation),
+            AppEvent::SessionStateChanged(state) => serde_json::to_value(state),
+            AppEvent::SessionLocked(notice) => serde_json::to_value(notice),
+            AppEvent::TimeExtended(extension) => serde_json::to_value(extension),
+            AppEvent::ProctorMessage(notice) => serde_json::to_value(notice),
+        }
+    }
+
+    /// Event names and the TypeScript type of their payload, in declaration order.
+    fn catalogue() -> Vec<(&'static str, String)> {
+        vec![
+            ("show-password-prompt", "null".into()),
+            ("show-ctrl-alt-delete-prompt", "null".into()),
+            ("violation-recorded", Violation::name()),
+            ("session-state-changed", SessionState::name()),
+            ("session-locked", LockNotice::name()),
+            ("session-unlocked", "null".into()),
+            ("time-extended", TimeExtension::name()),
+            ("proctor-message", ProctorNotice::name()),
+            ("exit-requested", "null".into()),
+        ]
+    }
+}
+
+/// Emits an event to every webview.
+/// Failing to emit is logged rather than treated as fatal, the app must keep enforcing the exam either way.
+pub fn emit(app: &AppHandle, event: AppEvent) {
+    let name = event.name();
+    let payload = match event.payload() {
+        Ok(payload) => payload,
+        Err(err) => {
+            log::error!("Could not serialize payload for `{}`: {}", name, err);
+            return;
+        }
+    };
+    match app.emit(name, payload) {
+        Ok(_) => log::info!("Emitted `{}`", name),
+        Err(err) => log::error!("Failed to emit `{}`: {}", name, err),
+    }
+}
+
+/// Builds the contents of `bindings/events.d.ts`.
+pub fn typescript_definitions() -> String {
+    let mut out = String::from(
+        "// This file is generated by `cargo run --bin event_types`. Do not edit it by hand.\n\n",
+    );
+    for decl in [
+        ViolationKind::decl(),
+        Violation::decl(),
+        SessionStatus::decl(),
+        AppMode::decl(),
+        SessionState::decl(),
+        LockNotice::decl(),
+        TimeExtension::decl(),
+        ProctorNotice::decl(),
+        SavedAnswer::decl(),
+        SyncStatus::decl(),
+    ] {
+        out.push_str(&format!("export {}\n\n", decl));
+    }
+    out.push_str("export interface AppEventMap {\n");
+    for (name, payload) in AppEvent::catalogue() {
+        out.push_str(&format!("  \"{}\": {};\n", name, payload));
+    }
+    out.push_str("}\n\nexport type AppEventName = keyof AppEventMap;\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// One event of every kind, in declaration order.
+    fn every_event() -> Vec<AppEvent> {
+        let events = vec![
+            AppEvent::ShowPasswordPrompt,
+            AppEvent::ShowCtrlAltDeletePrompt,
+            AppEvent::ViolationRecorded(Violation {
+                id: 1,
+                kind: ViolationKind::FocusLost,
+                detail: "focus lost".into(),
+                occurred_at: 0,
+            }),
+            AppEvent::SessionStateChanged(SessionState {
+                id: "session".into(),
+                status: SessionStatus::Active,
+                mode: AppMode::Kiosk,
+                seat: None,
+                started_at: 0,
+                violation_count: 0,
+                resumed: false,
+                extra_minutes: 0,
+            }),
+            AppEvent::SessionLocked(LockNotice {
+                lock_id: 1,
+                reason: "locked".into(),
+                violation_id: None,
+                challenge: None,
+            }),
+            AppEvent::SessionUnlocked,
+            AppEvent::TimeExtended(TimeExtension {
+                minutes: 5,
+                total_minutes: 5,
+            }),
+            AppEvent::ProctorMessage(ProctorNotice {
+                text: "hello".into(),
+                received_at: 0,
+            }),
+            AppEvent::ExitRequested,
+        ];
+        // No wildcard arm, so a new event does not compile until it is listed above.
+        for event in &events {
+            match event {
+                AppEvent::ShowPasswordPrompt
+                | AppEvent::ShowCtrlAltDeletePrompt
+                | AppEvent::ViolationRecorded(_)
+                | AppEvent::SessionStateChanged(_)
+                | AppEvent::SessionLocked(_)
+                | AppEvent::SessionUnlocked
+                | AppEvent::TimeExtended(_)
+                | AppEvent::ProctorMessage(_)
+                | AppEvent::ExitRequested => {}
+            }
+        }
+        events
+    }
+
+    #[test]
+    fn catalogue_lists_every_event_under_its_name() {
+        let emitted: Vec<&str> = every_event().iter().map(AppEvent::name).collect();
+        let listed: Vec<&str> = AppEvent::catalogue()
+            .iter()
+            .map(|(name, _)| *name)
+            .collect();
+        assert_eq!(listed, emitted);
+    }
+
+    #[test]
+    fn catalogue_types_match_the_payloads() {
+        for (event, (name, payload)) in every_event().iter().zip(AppEvent::catalogue()) {
+            let value = event.payload().unwrap();
+            assert_eq!(value.is_null(), payload == "null", "payload of `{}`", name);
+        }
+    }
+
+    #[test]
+    fn checked_in_bindings_are_up_to_date() {
+        assert_eq!(
+            include_str!("../../bindings/events.d.ts"),
+            typescript_definitions(),
+            "run `cargo run --bin event_types` to regenerate bindings/events.d.ts"
+        );
+    }
+}
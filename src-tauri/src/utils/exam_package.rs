@@ -0,0 +1,402 @@
+//! Signed, encrypted exam packages for offline exams.
+//!
+//! A package is one JSON file holding the exam pages and questions, encrypted with AES-256-GCM
+//! and signed with the exam board's ed25519 key. The content key is stored wrapped, never in
+//! the clear: once under the release key for the exam id and start time, which only the
+//! console hands out once its clock passes the start time, see `shared::release`, and once
+//! under a key derived from the proctor code.
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use shared::release::release_key;
+use std::collections::BTreeMap;
+
+/// PBKDF2 rounds for proctor codes, codes are short so this is deliberately slow
+pub const CODE_ITERATIONS: u32 = 600_000;
+
+/// The content key encrypted under a key-encryption key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WrappedKey {
+    /// PBKDF2 salt, only for keys wrapped under a proctor code
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub salt: Option<String>,
+    #[serde(default)]
+    pub iterations: u32,
+    pub nonce: String,
+    pub key: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageHeader {
+    pub exam_id: String,
+    /// Page loaded first, e.g. `index.html`
+    pub entry: String,
+    /// Unix timestamp in milliseconds before which the console does not release the key
+    pub not_before: i64,
+    pub time_key: Option<WrappedKey>,
+    pub code_key: Option<WrappedKey>,
+}
+
+/// The package as stored on disk. The signature covers the header bytes, nonce and ciphertext.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PackageFile {
+    pub header: String,
+    pub nonce: String,
+    pub ciphertext: String,
+    pub signature: String,
+}
+
+/// A package whose signature has been checked, still encrypted.
+#[derive(Debug, Clone)]
+pub struct SealedPackage {
+    pub header: PackageHeader,
+    nonce: Vec<u8>,
+    ciphertext: Vec<u8>,
+}
+
+/// Decrypted package content, file paths to bytes.
+#[derive(Debug, Clone, Default)]
+pub struct ExamContent {
+    pub files: BTreeMap<String, Vec<u8>>,
+}
+
+/// Encrypted form of [`ExamContent`], file contents in base64.
+#[derive(Serialize, Deserialize)]
+struct ContentFiles {
+    files: BTreeMap<String, String>,
+}
+
+fn decode(field: &str, value: &str) -> Result<Vec<u8>, String> {
+    BASE64
+        .decode(value)
+        .map_err(|err| format!("package field `{}` is not base64: {}", field, err))
+}
+
+fn signed_bytes(header: &[u8], nonce: &[u8], ciphertext: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(header.len() + nonce.len() + ciphertext.len());
+    bytes.extend_from_slice(header);
+    bytes.extend_from_slice(nonce);
+    bytes.extend_from_slice(ciphertext);
+    bytes
+}
+
+fn code_kek(code: &str, salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut kek = [0u8; 32];
+    pbkdf2::pbkdf2_hmac::<Sha256>(code.trim().as_bytes(), salt, iterations, &mut kek);
+    kek
+}
+
+fn wrap(kek: &[u8; 32], key: &[u8], salt: Option<&[u8]>, iterations: u32) -> WrappedKey {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(kek));
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let wrapped = cipher
+        .encrypt(&nonce, key)
+        .expect("encrypting a 32 byte key cannot fail");
+    WrappedKey {
+        salt: salt.map(|salt| BASE64.encode(salt)),
+        iterations,
+        nonce: BASE64.encode(nonce),
+        key: BASE64.encode(wrapped),
+    }
+}
+
+fn unwrap(kek: &[u8; 32], wrapped: &WrappedKey) -> Result<Vec<u8>, String> {
+    let cipher = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(kek));
+    let nonce = decode("nonce", &wrapped.nonce)?;
+    if nonce.len() != 12 {
+        return Err("wrapped key nonce has the wrong length".into());
+    }
+    cipher
+        .decrypt(
+            Nonce::from_slice(&nonce),
+            decode("key", &wrapped.key)?.as_slice(),
+        )
+        .map_err(|_| "wrong key".to_string())
+}
+
+pub fn parse_verifying_key(hex_key: &str) -> Result<VerifyingKey, String> {
+    let bytes: [u8; 32] = hex::decode(hex_key.trim())
+        .map_err(|err| format!("public key is not hex: {}", err))?
+        .try_into()
+        .map_err(|_| "public key must be 32 bytes".to_string())?;
+    VerifyingKey::from_bytes(&bytes).map_err(|err| format!("invalid public key: {}", err))
+}
+
+/// Options for [`seal`], used by the packaging tool.
+pub struct SealOptions<'a> {
+    pub exam_id: String,
+    pub entry: String,
+    pub not_before: i64,
+    pub release_secret: Option<&'a [u8]>,
+    pub proctor_code: Option<&'a str>,
+}
+
+/// Encrypts and signs `content` into a package.
+pub fn seal(
+    content: &ExamContent,
+    options: SealOptions,
+    signing_key: &SigningKey,
+) -> Result<PackageFile, String> {
+    if options.release_secret.is_none() && options.proctor_code.is_none() {
+        return Err("a package needs a release secret, a proctor code, or both".into());
+    }
+    let key = Aes256Gcm::generate_key(&mut OsRng);
+    let time_key = options.release_secret.map(|secret| {
+        let kek = release_key(secret, &options.exam_id, options.not_before);
+        wrap(&kek, &key, None, 0)
+    });
+    let code_key = options.proctor_code.map(|code| {
+        let salt = Aes256Gcm::generate_nonce(&mut OsRng);
+        wrap(
+            &code_kek(code, &salt, CODE_ITERATIONS),
+            &key,
+            Some(&salt),
+            CODE_ITERATIONS,
+        )
+    });
+    let header = serde_json::to_vec(&PackageHeader {
+        exam_id: options.exam_id,
+        entry: options.entry,
+        not_before: options.not_before,
+        time_key,
+        code_key,
+    })
+    .map_err(|err| err.to_string())?;
+
+    let files = ContentFiles {
+        files: content
+            .files
+            .iter()
+            .map(|(path, bytes)| (path.clone(), BASE64.encode(bytes)))
+            .collect(),
+    };
+    let plaintext = serde_json::to_vec(&files).map_err(|err| err.to_string())?;
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = Aes256Gcm::new(&key)
+        .encrypt(&nonce, plaintext.as_slice())
+        .map_err(|_| "could not encrypt the package".to_string())?;
+    let signature = signing_key.sign(&signed_bytes(&header, &nonce, &ciphertext));
+    Ok(PackageFile {
+        header: BASE64.encode(header),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(ciphertext),
+        signature: BASE64.encode(signature.to_bytes()),
+    })
+}
+
+impl PackageFile {
+    /// Checks the signature and parses the header. Nothing is decrypted yet.
+    pub fn verify(&self, public_key: &VerifyingKey) -> Result<SealedPackage, String> {
+        let header = decode("header", &self.header)?;
+        let nonce = decode("nonce", &self.nonce)?;
+        let ciphertext = decode("ciphertext", &self.ciphertext)?;
+        let signature = Signature::from_slice(&decode("signature", &self.signature)?)
+            .map_err(|err| format!("invalid signature: {}", err))?;
+        public_key
+            .verify(&signed_bytes(&header, &nonce, &ciphertext), &signature)
+            .map_err(|_| "package signature does not match".to_string())?;
+        if nonce.len() != 12 {
+            return Err("package nonce has the wrong length".into());
+        }
+        let header = serde_json::from_slice(&header)
+            .map_err(|err| format!("invalid package header: {}", err))?;
+        Ok(SealedPackage {
+            header,
+            nonce,
+            ciphertext,
+        })
+    }
+}
+
+impl SealedPackage {
+    fn decrypt(&self, key: &[u8]) -> Result<ExamContent, String> {
+        if key.len() != 32 {
+            return Err("content key has the wrong length".into());
+        }
+        let plaintext = Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(key))
+            .decrypt(Nonce::from_slice(&self.nonce), self.ciphertext.as_slice())
+            .map_err(|_| "could not decrypt the package".to_string())?;
+        let content: ContentFiles = serde_json::from_slice(&plaintext)
+            .map_err(|err| format!("invalid package content: {}", err))?;
+        let mut files = BTreeMap::new();
+        for (path, encoded) in content.files {
+            files.insert(path.clone(), decode(&path, &encoded)?);
+        }
+        Ok(ExamContent { files })
+    }
+
+    /// Opens the package with the release key the console gave out at the start time.
+    pub fn unlock_with_release_key(&self, released: &[u8; 32]) -> Result<ExamContent, String> {
+        let wrapped = self
+            .header
+            .time_key
+            .as_ref()
+            .ok_or("this package can only be opened with a proctor code")?;
+        let key = unwrap(released, wrapped)
+            .map_err(|_| "the release key does not open this package".to_string())?;
+        self.decrypt(&key)
+    }
+
+    pub fn unlock_with_code(&self, code: &str) -> Result<ExamContent, String> {
+        let wrapped = self
+            .header
+            .code_key
+            .as_ref()
+            .ok_or("this package cannot be opened with a proctor code")?;
+        let salt = decode("salt", wrapped.salt.as_deref().unwrap_or_default())?;
+        let key = unwrap(&code_kek(code, &salt, wrapped.iterations), wrapped)
+            .map_err(|_| "wrong proctor code".to_string())?;
+        self.decrypt(&key)
+    }
+}
+
+/// Content type for a file served from a package.
+pub fn mime_type(path: &str) -> &'static str {
+    match path.rsplit('.').next().unwrap_or_default() {
+        "html" | "htm" => "text/html",
+        "js" | "mjs" => "text/javascript",
+        "css" => "text/css",
+        "json" => "application/json",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RELEASE_SECRET: &[u8] = b"board release secret";
+    const CODE: &str = "7K2M-QX9P";
+    const NOT_BEFORE: i64 = 1_700_000_000_000;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[5; 32])
+    }
+
+    fn content() -> ExamContent {
+        ExamContent {
+            files: [
+                ("index.html".to_string(), b"<h1>Exam</h1>".to_vec()),
+                ("questions.json".to_string(), b"[1, 2, 3]".to_vec()),
+            ]
+            .into(),
+        }
+    }
+
+    fn package(release_secret: Option<&[u8]>, proctor_code: Option<&str>) -> PackageFile {
+        let options = SealOptions {
+            exam_id: "exam-1".into(),
+            entry: "index.html".into(),
+            not_before: NOT_BEFORE,
+            release_secret,
+            proctor_code,
+        };
+        seal(&content(), options, &signing_key()).unwrap()
+    }
+
+    #[test]
+    fn a_package_opens_with_the_release_key() {
+        let sealed = package(Some(RELEASE_SECRET), None)
+            .verify(&signing_key().verifying_key())
+            .unwrap();
+        assert_eq!(sealed.header.exam_id, "exam-1");
+        assert_eq!(sealed.header.entry, "index.html");
+        assert!(sealed.header.code_key.is_none());
+
+        let released = release_key(RELEASE_SECRET, "exam-1", NOT_BEFORE);
+        let opened = sealed.unlock_with_release_key(&released).unwrap();
+        assert_eq!(opened.files, content().files);
+
+        // the key for another start time does not open it
+        let early = release_key(RELEASE_SECRET, "exam-1", NOT_BEFORE - 1);
+        assert!(sealed.unlock_with_release_key(&early).is_err());
+        assert!(sealed.unlock_with_code(CODE).is_err());
+    }
+
+    #[test]
+    fn a_package_opens_with_the_proctor_code() {
+        let sealed = package(None, Some(CODE))
+            .verify(&signing_key().verifying_key())
+            .unwrap();
+        let wrapped = sealed.header.code_key.as_ref().unwrap();
+        assert_eq!(wrapped.iterations, CODE_ITERATIONS);
+        assert!(sealed.header.time_key.is_none());
+
+        // surrounding whitespace is not part of the code
+        let opened = sealed.unlock_with_code(" 7K2M-QX9P\n").unwrap();
+        assert_eq!(opened.files, content().files);
+        assert_eq!(
+            sealed.unlock_with_code("7K2M-QX9Q").unwrap_err(),
+            "wrong proctor code"
+        );
+        let released = release_key(RELEASE_SECRET, "exam-1", NOT_BEFORE);
+        assert!(sealed.unlock_with_release_key(&released).is_err());
+    }
+
+    #[test]
+    fn a_tampered_package_is_rejected() {
+        let file = package(Some(RELEASE_SECRET), None);
+        let public_key = signing_key().verifying_key();
+
+        // an earlier start time in the header
+        let mut header: PackageHeader =
+            serde_json::from_slice(&BASE64.decode(&file.header).unwrap()).unwrap();
+        header.not_before = 0;
+        let tampered = PackageFile {
+            header: BASE64.encode(serde_json::to_vec(&header).unwrap()),
+            ..file.clone()
+        };
+        assert_eq!(
+            tampered.verify(&public_key).unwrap_err(),
+            "package signature does not match"
+        );
+
+        let mut ciphertext = BASE64.decode(&file.ciphertext).unwrap();
+        ciphertext[0] ^= 1;
+        let tampered = PackageFile {
+            ciphertext: BASE64.encode(ciphertext),
+            ..file.clone()
+        };
+        assert!(tampered.verify(&public_key).is_err());
+
+        let mut signature = BASE64.decode(&file.signature).unwrap();
+        signature[10] ^= 1;
+        let tampered = PackageFile {
+            signature: BASE64.encode(signature),
+            ..file.clone()
+        };
+        assert_eq!(
+            tampered.verify(&public_key).unwrap_err(),
+            "package signature does not match"
+        );
+
+        // signed by someone else
+        let other = SigningKey::from_bytes(&[6; 32]).verifying_key();
+        assert!(file.verify(&other).is_err());
+        assert!(file.verify(&public_key).is_ok());
+    }
+
+    #[test]
+    fn a_package_needs_a_way_to_open_it() {
+        let options = SealOptions {
+            exam_id: "exam-1".into(),
+            entry: "index.html".into(),
+            not_before: NOT_BEFORE,
+            release_secret: None,
+            proctor_code: None,
+        };
+        assert!(seal(&content(), options, &signing_key()).is_err());
+    }
+}
@@ -0,0 +1,315 @@
+//! A stable machine fingerprint, used for seat binding and result attribution.
+//!
+//! Each hardware component is normalized and hashed with a per-deployment salt, so the raw
+//! serials never leave the machine. Fingerprints are compared component by component so a
+//! single changed part (a NIC swap, a new motherboard battery wiping the machine id) still matches.
+
+use crate::utils::secrets::FINGERPRINT_SALT;
+use crate::utils::types::{DeviceFingerprint, HardwareHashes, HardwareIdentity};
+use mac_address::get_mac_address;
+use sha2::{Digest, Sha256};
+pub use shared::fingerprint::is_same_machine;
+use std::collections::BTreeMap;
+use sysinfo::{CpuRefreshKind, RefreshKind, System};
+
+/// Values firmware vendors leave in unprogrammed DMI fields.
+const PLACEHOLDERS: [&str; 8] = [
+    "none",
+    "default string",
+    "to be filled by o.e.m.",
+    "not specified",
+    "not applicable",
+    "system serial number",
+    "base board serial number",
+    "unknown",
+];
+
+/// Raw, un-normalized component values as read from the machine.
+#[derive(Debug, Clone, Default)]
+pub struct FingerprintComponents {
+    pub product_uuid: Option<String>,
+    pub board_serial: Option<String>,
+    pub cpu_model: Option<String>,
+    pub primary_mac: Option<String>,
+    pub machine_id: Option<String>,
+}
+
+impl FingerprintComponents {
+    fn named(&self) -> [(&'static str, &Option<String>); 5] {
+        [
+            ("product_uuid", &self.product_uuid),
+            ("board_serial", &self.board_serial),
+            ("cpu_model", &self.cpu_model),
+            ("primary_mac", &self.primary_mac),
+            ("machine_id", &self.machine_id),
+        ]
+    }
+}
+
+/// The salt is set per deployment, see `utils::secrets`,
+/// so fingerprints from one exam provider cannot be correlated with another's.
+pub fn deployment_salt() -> Result<String, String> {
+    FINGERPRINT_SALT
+        .load()
+        .map(|salt| String::from_utf8_lossy(&salt).into_owned())
+}
+
+/// Lowercases and strips separators so the same value read through different APIs hashes the same.
+/// Returns `None` for empty values and firmware placeholders.
+pub fn normalize(value: &str) -> Option<String> {
+    let lowered = value.trim().to_lowercase();
+    if lowered.is_empty() || PLACEHOLDERS.contains(&lowered.as_str()) {
+        return None;
+    }
+    let normalized: String = lowered
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, ':' | '-' | '_' | '.'))
+        .collect();
+    // all zeroes or all ones is what unprogrammed uuids and serials look like
+    if normalized.is_empty() || normalized.chars().all(|c| c == '0' || c == 'f') {
+        return None;
+    }
+    Some(normalized)
+}
+
+fn hash_component(salt: &str, name: &str, value: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(salt.as_bytes());
+    hasher.update(b":");
+    hasher.update(name.as_bytes());
+    hasher.update(b":");
+    hasher.update(value.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Hashes each identity field on its own, the way fingerprint components are hashed.
+pub fn hash_identity(hardware: &HardwareIdentity, salt: &str) -> HardwareHashes {
+    let hash = |name: &str, value: &Option<String>| {
+        let value = normalize(value.as_deref()?)?;
+        Some(hash_component(salt, name, &value))
+    };
+    HardwareHashes {
+        product_uuid: hash("product_uuid", &hardware.product_uuid),
+        product_serial: hash("product_serial", &hardware.product_serial),
+        board_serial: hash("board_serial", &hardware.board_serial),
+        chassis_serial: hash("chassis_serial", &hardware.chassis_serial),
+    }
+}
+
+pub fn build_fingerprint(components: &FingerprintComponents, salt: &str) -> DeviceFingerprint {
+    let hashes: BTreeMap<String, String> = components
+        .named()
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = normalize(value.as_deref()?)?;
+            Some((name.to_string(), hash_component(salt, name, &value)))
+        })
+        .collect();
+
+    let mut hasher = Sha256::new();
+    for (name, hash) in &hashes {
+        hasher.update(name.as_bytes());
+        hasher.update(b"=");
+        hasher.update(hash.as_bytes());
+        hasher.update(b";");
+    }
+    DeviceFingerprint {
+        id: hex::encode(hasher.finalize()),
+        components: hashes,
+    }
+}
+
+fn cpu_model() -> Option<String> {
+    let sys =
+        System::new_with_specifics(RefreshKind::nothing().with_cpu(CpuRefreshKind::nothing()));
+    sys.cpus().first().map(|cpu| cpu.brand().to_string())
+}
+
+#[cfg(target_os = "linux")]
+fn machine_id() -> Option<String> {
+    std::fs::read_to_string("/etc/machine-id")
+        .or_else(|_| std::fs::read_to_string("/var/lib/dbus/machine-id"))
+        .ok()
+}
+
+#[cfg(target_os = "windows")]
+fn machine_id() -> Option<String> {
+    use winreg::enums::HKEY_LOCAL_MACHINE;
+    use winreg::RegKey;
+    let key = RegKey::predef(HKEY_LOCAL_MACHINE)
+        .open_subkey("SOFTWARE\\Microsoft\\Cryptography")
+        .ok()?;
+    key.get_value("MachineGuid").ok()
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "windows")))]
+fn machine_id() -> Option<String> {
+    None
+}
+
+pub fn collect_components(hardware: &HardwareIdentity) -> FingerprintComponents {
+    FingerprintComponents {
+        product_uuid: hardware.product_uuid.clone(),
+        board_serial: hardware.board_serial.clone(),
+        cpu_model: cpu_model(),
+        primary_mac: get_mac_address()
+            .ok()
+            .and_then(|mac| mac.map(|m| m.to_string())),
+        machine_id: machine_id(),
+    }
+}
+
+/// `None` when no salt is provisioned, unsalted hashes of the serials would give them away.
+pub fn device_fingerprint(hardware: &HardwareIdentity) -> Option<DeviceFingerprint> {
+    match deployment_salt() {
+        Ok(salt) => Some(build_fingerprint(&collect_components(hardware), &salt)),
+        Err(err) => {
+            log::warn!("No machine fingerprint: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn components() -> FingerprintComponents {
+        FingerprintComponents {
+            product_uuid: Some("4C4C4544-0042-3510-8052-B4C04F4A4E32".into()),
+            board_serial: Some("/7XJ4N2/CNWS2008A1001K/".into()),
+            cpu_model: Some("Intel(R) Core(TM) i5-8350U CPU @ 1.70GHz".into()),
+            primary_mac: Some("a4:83:e7:12:34:56".into()),
+            machine_id: Some("0f9a6a1c2b7d4e1f8a3b5c6d7e8f9012".into()),
+        }
+    }
+
+    #[test]
+    fn normalize_strips_case_and_separators() {
+        assert_eq!(
+            normalize(" A4:83:E7-12_34.56 "),
+            Some("a483e7123456".into())
+        );
+        assert_eq!(normalize("AB CD"), Some("abcd".into()));
+    }
+
+    #[test]
+    fn normalize_drops_placeholders() {
+        for value in [
+            "",
+            "   ",
+            "None",
+            "To Be Filled By O.E.M.",
+            "Default string",
+            "UNKNOWN",
+        ] {
+            assert_eq!(normalize(value), None, "{:?}", value);
+        }
+        assert_eq!(normalize("00000000-0000-0000-0000-000000000000"), None);
+        assert_eq!(normalize("FFFFFFFF-FFFF-FFFF-FFFF-FFFFFFFFFFFF"), None);
+        assert_eq!(normalize(":-_."), None);
+    }
+
+    #[test]
+    fn same_value_through_different_apis_hashes_the_same() {
+        let mut upper = components();
+        upper.primary_mac = Some("A4-83-E7-12-34-56".into());
+        assert_eq!(
+            build_fingerprint(&components(), "salt"),
+            build_fingerprint(&upper, "salt")
+        );
+    }
+
+    #[test]
+    fn salt_changes_every_hash() {
+        let one = build_fingerprint(&components(), "one");
+        let other = build_fingerprint(&components(), "other");
+        assert_ne!(one.id, other.id);
+        for (name, hash) in &one.components {
+            assert_ne!(other.components.get(name), Some(hash));
+        }
+    }
+
+    #[test]
+    fn one_changed_component_still_matches() {
+        let known = build_fingerprint(&components(), "salt");
+        let mut swapped = components();
+        swapped.primary_mac = Some("00:1b:21:aa:bb:cc".into());
+        let current = build_fingerprint(&swapped, "salt");
+        assert_ne!(known.id, current.id);
+        assert!(is_same_machine(&known, &current));
+    }
+
+    #[test]
+    fn two_changed_components_do_not_match() {
+        let known = build_fingerprint(&components(), "salt");
+        let mut other = components();
+        other.primary_mac = Some("00:1b:21:aa:bb:cc".into());
+        other.board_serial = Some("SOMEOTHERBOARD".into());
+        assert!(!is_same_machine(&known, &build_fingerprint(&other, "salt")));
+    }
+
+    #[test]
+    fn a_missing_component_is_not_a_change() {
+        let known = build_fingerprint(&components(), "salt");
+        let mut fewer = components();
+        fewer.machine_id = None;
+        assert!(is_same_machine(&known, &build_fingerprint(&fewer, "salt")));
+    }
+
+    #[test]
+    fn every_identity_field_is_hashed() {
+        let hardware = HardwareIdentity {
+            product_uuid: Some("4C4C4544-0042-3510-8052-B4C04F4A4E32".into()),
+            product_serial: Some("7XJ4N2".into()),
+            board_serial: Some("/7XJ4N2/CNWS2008A1001K/".into()),
+            chassis_serial: Some("To Be Filled By O.E.M.".into()),
+            unreadable: vec![],
+        };
+        let hashes = hash_identity(&hardware, "salt");
+        let product_serial = hashes.product_serial.clone().unwrap();
+        assert_eq!(product_serial.len(), 64);
+        assert!(!product_serial.contains("7xj4n2"));
+        // the same serial in another field hashes differently
+        let moved = HardwareIdentity {
+            chassis_serial: hardware.product_serial.clone(),
+            ..HardwareIdentity::default()
+        };
+        assert_ne!(
+            hash_identity(&moved, "salt").chassis_serial,
+            hashes.product_serial
+        );
+        assert_eq!(hashes.chassis_serial, None);
+        // the fields shared with the fingerprint hash to its components
+        let fingerprint = build_fingerprint(&components(), "salt");
+        assert_eq!(
+            hashes.product_uuid.as_ref(),
+            fingerprint.components.get("product_uuid")
+        );
+        assert_eq!(
+            hashes.board_serial.as_ref(),
+            fingerprint.components.get("board_serial")
+        );
+        assert_ne!(hash_identity(&hardware, "other"), hashes);
+    }
+
+    #[test]
+    fn empty_fingerprints_never_match() {
+        let empty = build_fingerprint(&FingerprintComponents::default(), "salt");
+        assert!(empty.components.is_empty());
+        assert!(!is_same_machine(&empty, &empty.clone()));
+    }
+
+    #[test]
+    fn too_few_shared_components_do_not_match() {
+        let sparse = FingerprintComponents {
+            cpu_model: Some("Intel(R) Core(TM) i5-8350U CPU @ 1.70GHz".into()),
+            primary_mac: Some("a4:83:e7:12:34:56".into()),
+            ..FingerprintComponents::default()
+        };
+        let fingerprint = build_fingerprint(&sparse, "salt");
+        assert!(!is_same_machine(&fingerprint, &fingerprint.clone()));
+        let full = build_fingerprint(&components(), "salt");
+        assert!(!is_same_machine(&full, &fingerprint));
+    }
+}
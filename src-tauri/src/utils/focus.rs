@@ -0,0 +1,271 @@
+//! Focus-loss and visibility tracking for the exam window.
+//!
+//! Always-on-top and fullscreen are not enough on their own: a notification, another
+//! always-on-top app or a workspace switch can still take focus. Every loss is counted
+//! and timed, fullscreen and focus are re-asserted, and repeated losses become a violation.
+
+use crate::utils::mode::AppMode;
+use crate::utils::session::record_violation;
+use crate::utils::types::{FocusSummary, ViolationKind};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tauri::{Manager, Window, WindowEvent};
+
+/// This many losses inside [`REPEATED_LOSS_WINDOW`] is reported as a violation.
+const REPEATED_LOSS_LIMIT: usize = 3;
+const REPEATED_LOSS_WINDOW: Duration = Duration::from_secs(60);
+
+pub struct FocusState(pub Mutex<FocusTracker>);
+
+/// A loss as counted by [`FocusTracker::focus_lost`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FocusLoss {
+    /// Losses inside the repeat window, this one included
+    pub recent: usize,
+    /// `recent` reached [`REPEATED_LOSS_LIMIT`]. The window then starts over, so one burst
+    /// of losses is reported once
+    pub repeated: bool,
+}
+
+#[derive(Debug, Default)]
+pub struct FocusTracker {
+    losses: u32,
+    lost_at: Option<Instant>,
+    total_unfocused: Duration,
+    longest_unfocused: Duration,
+    recent: VecDeque<Instant>,
+}
+
+impl FocusTracker {
+    /// Records a loss and counts it against the repeat window.
+    pub fn focus_lost(&mut self, now: Instant) -> FocusLoss {
+        if self.lost_at.is_some() {
+            // the platform can report the same loss twice
+            return FocusLoss {
+                recent: self.recent.len(),
+                repeated: false,
+            };
+        }
+        self.losses += 1;
+        self.lost_at = Some(now);
+        self.recent.push_back(now);
+        while let Some(oldest) = self.recent.front() {
+            if now.duration_since(*oldest) > REPEATED_LOSS_WINDOW {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        let recent = self.recent.len();
+        let repeated = recent >= REPEATED_LOSS_LIMIT;
+        if repeated {
+            self.recent.clear();
+        }
+        FocusLoss { recent, repeated }
+    }
+
+    /// Closes the current loss, returning how long the window was unfocused.
+    pub fn focus_regained(&mut self, now: Instant) -> Option<Duration> {
+        let lost_at = self.lost_at.take()?;
+        let unfocused = now.duration_since(lost_at);
+        self.total_unfocused += unfocused;
+        self.longest_unfocused = self.longest_unfocused.max(unfocused);
+        Some(unfocused)
+    }
+
+    pub fn summary(&self, now: Instant) -> FocusSummary {
+        let current = self
+            .lost_at
+            .map(|lost_at| now.duration_since(lost_at))
+            .unwrap_or_default();
+        FocusSummary {
+            losses: self.losses,
+            currently_unfocused: self.lost_at.is_some(),
+            total_unfocused_ms: (self.total_unfocused + current).as_millis() as u64,
+            longest_unfocused_ms: self.longest_unfocused.max(current).as_millis() as u64,
+        }
+    }
+}
+
+fn reassert_lockdown(window: &Window) {
+    if let Ok(true) = window.is_minimized() {
+        log::info!("Exam window was minimized, restoring");
+        if let Err(e) = window.unminimize() {
+            log::error!("Could not restore window: {:?}", e);
+        }
+    }
+    if let Ok(false) = window.is_fullscreen() {
+        if let Err(e) = window.set_fullscreen(true) {
+            log::error!("Could not re-enter fullscreen: {:?}", e);
+        }
+    }
+    if let Err(e) = window.set_always_on_top(true) {
+        log::error!("Could not set always on top: {:?}", e);
+    }
+}
+
+/// Handles every window event other than close requests for the main exam window.
+pub fn handle_window_event(window: &Window, event: &WindowEvent) {
+    if window.label() != "main" {
+        return;
+    }
+    let app = window.app_handle();
+    // practice mode keeps a normal window, losses are still counted
+    let kiosk = app.state::<AppMode>().is_kiosk();
+    match event {
+        WindowEvent::Focused(false) => {
+            let loss = {
+                let state = app.state::<FocusState>();
+                let Ok(mut tracker) = state.0.lock() else {
+                    return;
+                };
+                tracker.focus_lost(Instant::now())
+            };
+            log::info!(
+                "Exam window lost focus ({} in the last minute)",
+                loss.recent
+            );
+            if kiosk {
+                reassert_lockdown(window);
+                if let Err(e) = window.set_focus() {
+                    log::error!("Could not take focus back: {:?}", e);
+                }
+            }
+            if loss.repeated {
+                record_violation(
+                    app,
+                    ViolationKind::FocusLost,
+                    format!(
+                        "Exam window lost focus {} times within {} seconds",
+                        loss.recent,
+                        REPEATED_LOSS_WINDOW.as_secs()
+                    ),
+                );
+            }
+        }
+        WindowEvent::Focused(true) => {
+            let state = app.state::<FocusState>();
+            let unfocused = match state.0.lock() {
+                Ok(mut tracker) => tracker.focus_regained(Instant::now()),
+                Err(_) => None,
+            };
+            if let Some(unfocused) = unfocused {
+                log::info!("Exam window regained focus after {:?}", unfocused);
+            }
+        }
+        WindowEvent::Moved(position) => {
+            log::info!("Exam window moved to {:?}", position);
+            if kiosk {
+                reassert_lockdown(window);
+            }
+        }
+        WindowEvent::Resized(size) => {
+            // minimizing is reported as a resize on every platform
+            log::info!("Exam window resized to {:?}", size);
+            if kiosk {
+                reassert_lockdown(window);
+            }
+        }
+        WindowEvent::ScaleFactorChanged { scale_factor, .. } => {
+            log::info!("Exam window scale factor changed to {}", scale_factor);
+            if kiosk {
+                reassert_lockdown(window);
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SECOND: Duration = Duration::from_secs(1);
+
+    /// Loses focus at `at` and gets it back `for_` later.
+    fn blur(tracker: &mut FocusTracker, start: Instant, at: Duration, for_: Duration) -> FocusLoss {
+        let loss = tracker.focus_lost(start + at);
+        tracker.focus_regained(start + at + for_);
+        loss
+    }
+
+    #[test]
+    fn losses_are_counted_and_timed() {
+        let start = Instant::now();
+        let mut tracker = FocusTracker::default();
+        assert_eq!(tracker.focus_regained(start), None);
+
+        tracker.focus_lost(start);
+        assert_eq!(tracker.focus_regained(start + 2 * SECOND), Some(2 * SECOND));
+        blur(&mut tracker, start, 10 * SECOND, 5 * SECOND);
+        let summary = tracker.summary(start + 20 * SECOND);
+        assert_eq!(summary.losses, 2);
+        assert!(!summary.currently_unfocused);
+        assert_eq!(summary.total_unfocused_ms, 7_000);
+        assert_eq!(summary.longest_unfocused_ms, 5_000);
+    }
+
+    #[test]
+    fn the_current_loss_counts_until_focus_comes_back() {
+        let start = Instant::now();
+        let mut tracker = FocusTracker::default();
+        blur(&mut tracker, start, Duration::ZERO, 3 * SECOND);
+        tracker.focus_lost(start + 10 * SECOND);
+
+        let summary = tracker.summary(start + 18 * SECOND);
+        assert!(summary.currently_unfocused);
+        assert_eq!(summary.losses, 2);
+        assert_eq!(summary.total_unfocused_ms, 11_000);
+        assert_eq!(summary.longest_unfocused_ms, 8_000);
+    }
+
+    #[test]
+    fn a_repeated_blur_is_one_loss() {
+        let start = Instant::now();
+        let mut tracker = FocusTracker::default();
+        assert_eq!(
+            tracker.focus_lost(start),
+            FocusLoss {
+                recent: 1,
+                repeated: false
+            }
+        );
+        for later in 1..=5 {
+            let loss = tracker.focus_lost(start + later * SECOND);
+            assert_eq!(loss.recent, 1);
+            assert!(!loss.repeated);
+        }
+        // the loss started with the first blur
+        assert_eq!(tracker.focus_regained(start + 6 * SECOND), Some(6 * SECOND));
+        assert_eq!(tracker.summary(start + 6 * SECOND).losses, 1);
+    }
+
+    #[test]
+    fn a_burst_of_losses_is_reported_once() {
+        let start = Instant::now();
+        let mut tracker = FocusTracker::default();
+        let repeated: Vec<bool> = (0..7)
+            .map(|index| blur(&mut tracker, start, index * 5 * SECOND, SECOND).repeated)
+            .collect();
+        // the window starts over after each report
+        assert_eq!(repeated, [false, false, true, false, false, true, false]);
+        assert_eq!(tracker.summary(start + 60 * SECOND).losses, 7);
+    }
+
+    #[test]
+    fn losses_spread_out_are_not_repeated() {
+        let start = Instant::now();
+        let mut tracker = FocusTracker::default();
+        for index in 0..6 {
+            let loss = blur(&mut tracker, start, index * 31 * SECOND, SECOND);
+            assert!(!loss.repeated, "loss {}", index);
+            assert!(loss.recent <= 2);
+        }
+        // the third loss is just inside the minute of the first
+        let mut tracker = FocusTracker::default();
+        blur(&mut tracker, start, Duration::ZERO, SECOND);
+        blur(&mut tracker, start, 30 * SECOND, SECOND);
+        assert!(blur(&mut tracker, start, 60 * SECOND, SECOND).repeated);
+    }
+}
@@ -0,0 +1,289 @@
+//! Reads the machine's hardware identity through the platform's own APIs: sysfs on linux,
+//! WMI on windows and the IOKit registry on macos, without starting any other program.
+//!
+//! The linux reader takes the sysfs directory as a parameter, so it is tested against
+//! fixture trees instead of the real machine.
+
+use crate::utils::types::HardwareIdentity;
+use std::io::ErrorKind;
+use std::path::Path;
+
+pub const LINUX_DMI_ROOT: &str = "/sys/class/dmi/id";
+
+/// Outcome of reading a single identity field.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldRead {
+    Value(String),
+    Missing,
+    /// The field exists but needs elevated privileges, most serials on linux are root-only.
+    PermissionDenied,
+}
+
+fn clean(value: &str) -> Option<String> {
+    let value = value.trim().trim_matches(char::from(0)).trim();
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.to_string())
+    }
+}
+
+/// Reads one field from a sysfs dmi directory.
+pub fn read_sysfs_field(root: &Path, field: &str) -> FieldRead {
+    match std::fs::read_to_string(root.join(field)) {
+        Ok(contents) => match clean(&contents) {
+            Some(value) => FieldRead::Value(value),
+            None => FieldRead::Missing,
+        },
+        Err(err) if err.kind() == ErrorKind::PermissionDenied => FieldRead::PermissionDenied,
+        Err(err) => {
+            if err.kind() != ErrorKind::NotFound {
+                log::info!("Could not read {}: {}", root.join(field).display(), err);
+            }
+            FieldRead::Missing
+        }
+    }
+}
+
+/// Builds the identity from a sysfs dmi directory, normally [`LINUX_DMI_ROOT`].
+pub fn read_sysfs_identity(root: &Path) -> HardwareIdentity {
+    let mut identity = HardwareIdentity::default();
+    for (field, slot) in [
+        ("product_uuid", &mut identity.product_uuid),
+        ("product_serial", &mut identity.product_serial),
+        ("board_serial", &mut identity.board_serial),
+        ("chassis_serial", &mut identity.chassis_serial),
+    ] {
+        match read_sysfs_field(root, field) {
+            FieldRead::Value(value) => *slot = Some(value),
+            FieldRead::Missing => {}
+            FieldRead::PermissionDenied => identity.unreadable.push(field.to_string()),
+        }
+    }
+    if !identity.unreadable.is_empty() {
+        log::warn!(
+            "Hardware identity fields need elevated privileges: {}",
+            identity.unreadable.join(", ")
+        );
+    }
+    identity
+}
+
+/// Queries WMI on a thread of its own, COM may already be set up differently on the caller's.
+#[cfg(target_os = "windows")]
+pub fn read_hardware_identity() -> HardwareIdentity {
+    match std::thread::spawn(read_wmi_identity).join() {
+        Ok(Ok(identity)) => identity,
+        Ok(Err(err)) => {
+            log::error!("Could not query the hardware identity: {}", err);
+            HardwareIdentity::default()
+        }
+        Err(_) => {
+            log::error!("Hardware identity query panicked");
+            HardwareIdentity::default()
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn read_wmi_identity() -> wmi::WMIResult<HardwareIdentity> {
+    use serde::Deserialize;
+    use wmi::{COMLibrary, WMIConnection};
+
+    #[derive(Deserialize)]
+    struct Product {
+        #[serde(rename = "UUID")]
+        uuid: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename_all = "PascalCase")]
+    struct Serial {
+        serial_number: Option<String>,
+    }
+
+    let connection = WMIConnection::new(COMLibrary::new()?)?;
+    let serial = |class: &str| -> wmi::WMIResult<Option<String>> {
+        let rows: Vec<Serial> =
+            connection.raw_query(format!("SELECT SerialNumber FROM {}", class))?;
+        Ok(rows
+            .into_iter()
+            .find_map(|row| row.serial_number.as_deref().and_then(clean)))
+    };
+    let products: Vec<Product> =
+        connection.raw_query("SELECT UUID FROM Win32_ComputerSystemProduct")?;
+    Ok(HardwareIdentity {
+        product_uuid: products
+            .into_iter()
+            .find_map(|row| row.uuid.as_deref().and_then(clean)),
+        product_serial: serial("Win32_BIOS")?,
+        board_serial: serial("Win32_BaseBoard")?,
+        chassis_serial: serial("Win32_SystemEnclosure")?,
+        unreadable: vec![],
+    })
+}
+
+/// Reads the platform expert device from the IOKit registry.
+#[cfg(target_os = "macos")]
+pub fn read_hardware_identity() -> HardwareIdentity {
+    use io_kit_sys::{
+        kIOMasterPortDefault, IOObjectRelease, IOServiceGetMatchingService, IOServiceMatching,
+    };
+
+    // the matching dictionary is consumed by the lookup
+    let device = unsafe {
+        IOServiceGetMatchingService(
+            kIOMasterPortDefault,
+            IOServiceMatching(c"IOPlatformExpertDevice".as_ptr()),
+        )
+    };
+    if device == 0 {
+        log::error!("Could not find the IOPlatformExpertDevice");
+        return HardwareIdentity::default();
+    }
+    let identity = HardwareIdentity {
+        product_uuid: read_registry_string(device, "IOPlatformUUID"),
+        product_serial: read_registry_string(device, "IOPlatformSerialNumber"),
+        ..HardwareIdentity::default()
+    };
+    unsafe { IOObjectRelease(device) };
+    identity
+}
+
+#[cfg(target_os = "macos")]
+fn read_registry_string(
+    entry: io_kit_sys::types::io_registry_entry_t,
+    key: &str,
+) -> Option<String> {
+    use core_foundation::base::{kCFAllocatorDefault, CFType, TCFType};
+    use core_foundation::string::CFString;
+    use io_kit_sys::IORegistryEntryCreateCFProperty;
+
+    let key = CFString::new(key);
+    let value = unsafe {
+        IORegistryEntryCreateCFProperty(entry, key.as_concrete_TypeRef(), kCFAllocatorDefault, 0)
+    };
+    if value.is_null() {
+        return None;
+    }
+    let value = unsafe { CFType::wrap_under_create_rule(value) };
+    clean(&value.downcast::<CFString>()?.to_string())
+}
+
+#[cfg(target_os = "linux")]
+pub fn read_hardware_identity() -> HardwareIdentity {
+    read_sysfs_identity(Path::new(LINUX_DMI_ROOT))
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "macos", target_os = "linux")))]
+pub fn read_hardware_identity() -> HardwareIdentity {
+    HardwareIdentity::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A dmi directory under the temp dir, removed again when dropped.
+    struct Fixture(PathBuf);
+
+    impl Fixture {
+        fn new(name: &str, fields: &[(&str, &str)]) -> Self {
+            let root = std::env::temp_dir().join(format!("dmi-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&root).unwrap();
+            for (field, contents) in fields {
+                std::fs::write(root.join(field), contents).unwrap();
+            }
+            Self(root)
+        }
+    }
+
+    impl Drop for Fixture {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn fields_are_read_and_trimmed() {
+        let dmi = Fixture::new(
+            "read",
+            &[
+                ("product_uuid", "4c4c4544-0042-3510-8052-b4c04f4a4e32\n"),
+                ("product_serial", "  7XJ4N2\n"),
+                ("board_serial", "/7XJ4N2/CNWS2008A1001K/\0\n"),
+                ("chassis_serial", "7XJ4N2"),
+            ],
+        );
+        let identity = read_sysfs_identity(&dmi.0);
+        assert_eq!(
+            identity.product_uuid.as_deref(),
+            Some("4c4c4544-0042-3510-8052-b4c04f4a4e32")
+        );
+        assert_eq!(identity.product_serial.as_deref(), Some("7XJ4N2"));
+        assert_eq!(
+            identity.board_serial.as_deref(),
+            Some("/7XJ4N2/CNWS2008A1001K/")
+        );
+        assert_eq!(identity.chassis_serial.as_deref(), Some("7XJ4N2"));
+        assert!(identity.unreadable.is_empty());
+    }
+
+    #[test]
+    fn missing_and_empty_fields_are_none() {
+        let dmi = Fixture::new("missing", &[("product_uuid", "\n"), ("board_serial", " ")]);
+        let identity = read_sysfs_identity(&dmi.0);
+        assert_eq!(identity.product_uuid, None);
+        assert_eq!(identity.product_serial, None);
+        assert_eq!(identity.board_serial, None);
+        assert_eq!(
+            read_sysfs_field(&dmi.0, "chassis_serial"),
+            FieldRead::Missing
+        );
+        assert!(identity.unreadable.is_empty());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn fields_only_root_can_read_are_listed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dmi = Fixture::new(
+            "denied",
+            &[
+                ("product_uuid", "4c4c4544-0042-3510-8052-b4c04f4a4e32\n"),
+                ("product_serial", "7XJ4N2\n"),
+                ("board_serial", "/7XJ4N2/CNWS2008A1001K/\n"),
+            ],
+        );
+        for field in ["product_serial", "board_serial"] {
+            std::fs::set_permissions(dmi.0.join(field), std::fs::Permissions::from_mode(0o000))
+                .unwrap();
+        }
+        if std::fs::read(dmi.0.join("product_serial")).is_ok() {
+            // running as root, which reads the fields anyway
+            return;
+        }
+        assert_eq!(
+            read_sysfs_field(&dmi.0, "board_serial"),
+            FieldRead::PermissionDenied
+        );
+        let identity = read_sysfs_identity(&dmi.0);
+        assert_eq!(
+            identity.product_uuid.as_deref(),
+            Some("4c4c4544-0042-3510-8052-b4c04f4a4e32")
+        );
+        assert_eq!(identity.product_serial, None);
+        assert_eq!(identity.board_serial, None);
+        assert_eq!(identity.chassis_serial, None);
+        assert_eq!(identity.unreadable, ["product_serial", "board_serial"]);
+    }
+
+    #[test]
+    fn a_missing_directory_reads_as_empty() {
+        let identity = read_sysfs_identity(Path::new("/nonexistent/dmi/id"));
+        assert_eq!(identity.product_uuid, None);
+        assert!(identity.unreadable.is_empty());
+    }
+}
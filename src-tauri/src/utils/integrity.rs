@@ -0,0 +1,233 @@
+//! Verification of the app's own files against a signed manifest.
+//!
+//! The manifest is produced by the `integrity_manifest` tool after bundling and shipped next
+//! to the executable. It lists the SHA-256 of the executable and the sidecars, by file name,
+//! and of every frontend asset embedded in the executable, by asset path. It is signed with
+//! an ed25519 key whose public half is built into the app from SECURE_BROWSER_INTEGRITY_KEY;
+//! unlike other secrets it is not read from the environment at run time, since whoever can
+//! swap a binary can also set a variable. A build without the key only runs in practice mode
+//! or as a debug build, see [`key_optional`].
+
+use crate::utils::mode::AppMode;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tauri::AppHandle;
+
+pub const MANIFEST_FILE_NAME: &str = "integrity.json";
+/// Sidecars started by the app, without the platform's executable suffix
+pub const SIDECARS: &[&str] = &["mapper"];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct IntegrityPolicy {
+    /// Manifest to check against, `integrity.json` next to the executable by default
+    pub manifest: Option<PathBuf>,
+    /// Seconds between checks after the one at startup, 0 only checks at startup
+    pub interval_secs: u64,
+}
+
+impl Default for IntegrityPolicy {
+    fn default() -> Self {
+        Self {
+            manifest: None,
+            interval_secs: 60,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// App version the manifest was made for
+    pub version: String,
+    /// File name in the executable's directory to hex SHA-256
+    pub files: BTreeMap<String, String>,
+    /// Embedded asset path to hex SHA-256
+    pub assets: BTreeMap<String, String>,
+}
+
+/// The manifest as stored on disk. The signature covers the manifest bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestFile {
+    pub manifest: String,
+    pub signature: String,
+}
+
+impl ManifestFile {
+    pub fn sign(manifest: &Manifest, signing_key: &SigningKey) -> Result<Self, String> {
+        let manifest = serde_json::to_string(manifest).map_err(|err| err.to_string())?;
+        let signature = signing_key.sign(manifest.as_bytes());
+        Ok(Self {
+            manifest,
+            signature: BASE64.encode(signature.to_bytes()),
+        })
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> Result<Manifest, String> {
+        let signature: [u8; 64] = BASE64
+            .decode(&self.signature)
+            .map_err(|err| format!("manifest signature is not base64: {}", err))?
+            .try_into()
+            .map_err(|_| "manifest signature must be 64 bytes".to_string())?;
+        key.verify(self.manifest.as_bytes(), &Signature::from_bytes(&signature))
+            .map_err(|_| "manifest signature does not match".to_string())?;
+        serde_json::from_str(&self.manifest).map_err(|err| format!("invalid manifest: {}", err))
+    }
+}
+
+/// The manifest key built into the app, `None` for builds made without one.
+pub fn public_key() -> Option<Result<VerifyingKey, String>> {
+    option_env!("SECURE_BROWSER_INTEGRITY_KEY")
+        .filter(|key| !key.is_empty())
+        .map(crate::utils::exam_package::parse_verifying_key)
+}
+
+/// Whether the app may run unverified in `mode` when it was built without a manifest key.
+pub fn key_optional(mode: AppMode) -> bool {
+    !mode.is_kiosk() || cfg!(debug_assertions)
+}
+
+/// Checks at launch that the build can verify its files if `mode` needs it to.
+pub fn check_key(mode: AppMode) -> Result<(), String> {
+    match public_key() {
+        Some(key) => key.map(|_| ()),
+        None if key_optional(mode) => Ok(()),
+        None => Err(
+            "this build has no integrity key, build it with SECURE_BROWSER_INTEGRITY_KEY set"
+                .into(),
+        ),
+    }
+}
+
+pub fn hash_bytes(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+pub fn hash_file(path: &Path) -> std::io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Hashes of assets as the asset provider hands them out.
+pub fn hash_assets<'a>(
+    assets: impl Iterator<Item = (Cow<'a, str>, Cow<'a, [u8]>)>,
+) -> BTreeMap<String, String> {
+    assets
+        .map(|(path, bytes)| (path.into_owned(), hash_bytes(&bytes)))
+        .collect()
+}
+
+/// Name of a sidecar as the bundler installs it next to the executable.
+pub fn sidecar_file_name(name: &str) -> String {
+    format!("{}{}", name, std::env::consts::EXE_SUFFIX)
+}
+
+/// Reads the manifest and checks its signature.
+pub fn load(path: &Path, key: &VerifyingKey) -> Result<Manifest, String> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read manifest {}: {}", path.display(), err))?;
+    let file: ManifestFile = serde_json::from_str(&contents)
+        .map_err(|err| format!("invalid manifest {}: {}", path.display(), err))?;
+    file.verify(key)
+}
+
+/// Compares the files and assets on this machine with the manifest and describes every
+/// difference, an empty list means everything matched. `required` are file names that must
+/// be covered, so a manifest that leaves out a binary does not pass it unchecked.
+pub fn check(
+    manifest: &Manifest,
+    dir: &Path,
+    required: &[String],
+    assets: &BTreeMap<String, String>,
+) -> Vec<String> {
+    let mut problems = Vec::new();
+    for name in required {
+        if !manifest.files.contains_key(name) {
+            problems.push(format!("{} is not covered by the manifest", name));
+        }
+    }
+    for (name, expected) in &manifest.files {
+        match hash_file(&dir.join(name)) {
+            Ok(actual) if actual == *expected => {}
+            Ok(_) => problems.push(format!("{} was modified", name)),
+            Err(err) => problems.push(format!("{} could not be read: {}", name, err)),
+        }
+    }
+    for (path, expected) in &manifest.assets {
+        match assets.get(path) {
+            Some(actual) if actual == expected => {}
+            Some(_) => problems.push(format!("asset {} was modified", path)),
+            None => problems.push(format!("asset {} is missing", path)),
+        }
+    }
+    for path in assets.keys() {
+        if !manifest.assets.contains_key(path) {
+            problems.push(format!("asset {} is not in the manifest", path));
+        }
+    }
+    problems
+}
+
+/// A verified manifest and where to check it, kept for the periodic checks.
+#[derive(Debug, Clone)]
+pub struct IntegrityCheck {
+    manifest: Manifest,
+    dir: PathBuf,
+    required: Vec<String>,
+}
+
+impl IntegrityCheck {
+    /// Finds the executable and loads the manifest. `Ok(None)` when the build carries no
+    /// manifest key and [`key_optional`] allows that in `mode`.
+    pub fn load(policy: &IntegrityPolicy, mode: AppMode) -> Result<Option<Self>, String> {
+        let key = match public_key() {
+            Some(key) => key?,
+            None if key_optional(mode) => return Ok(None),
+            None => return Err("this build has no integrity key".into()),
+        };
+        let exe = std::env::current_exe()
+            .map_err(|err| format!("could not locate the executable: {}", err))?;
+        let dir = exe
+            .parent()
+            .ok_or("the executable has no parent directory")?
+            .to_path_buf();
+        let manifest_path = policy
+            .manifest
+            .clone()
+            .unwrap_or_else(|| dir.join(MANIFEST_FILE_NAME));
+        let manifest = load(&manifest_path, &key)?;
+        let mut required: Vec<String> = SIDECARS
+            .iter()
+            .map(|name| sidecar_file_name(name))
+            .collect();
+        if let Some(name) = exe.file_name() {
+            required.push(name.to_string_lossy().into_owned());
+        }
+        Ok(Some(Self {
+            manifest,
+            dir,
+            required,
+        }))
+    }
+
+    pub fn manifest(&self) -> &Manifest {
+        &self.manifest
+    }
+
+    pub fn check(&self, assets: &BTreeMap<String, String>) -> Vec<String> {
+        check(&self.manifest, &self.dir, &self.required, assets)
+    }
+
+    /// Checks against the assets embedded in the running app.
+    pub fn verify(&self, app: &AppHandle) -> Vec<String> {
+        let resolver = app.asset_resolver();
+        self.check(&hash_assets(resolver.iter()))
+    }
+}
@@ -1,13 +1,41 @@
 use std::process::Command;
+pub mod audit;
+pub mod autosave;
+pub mod capability;
+pub mod cli;
+pub mod clipboard;
+pub mod commands;
+pub mod content;
+pub mod diagnostics;
+pub mod discovery;
+pub mod display;
+pub mod events;
+pub mod exam_package;
+pub mod fingerprint;
+pub mod focus;
+pub mod hardware;
+pub mod integrity;
+pub mod mode;
+pub mod navigation;
+pub mod offline;
+pub mod policy;
+pub mod proctor;
+pub mod proxy;
+pub mod secrets;
+pub mod session;
+pub mod sidecar;
+pub mod sync;
 pub mod types;
+pub mod unlock;
+pub mod watchdog;
+use crate::utils::events::{emit, AppEvent};
 use crate::utils::types::{HostInfo, PortStatus, ProcessIdentifier, USBDevice, UdpEndpoint, WebRtcReport, RawUdpEndpoint};
-use crate::{AppState, RemoteChecker, SchedulerState};
-use mac_address::get_mac_address;
+use crate::{RemoteChecker, SchedulerState};
 use serde::Serialize;
 use std::net::UdpSocket;
 use sysinfo::System;
 use tauri::Manager;
-use tauri::{AppHandle, Emitter};
+use tauri::AppHandle;
 use tauri_plugin_global_shortcut::{Code, Modifiers, Shortcut, ShortcutEvent, ShortcutState};
 use std::str;
 
@@ -31,8 +59,7 @@ pub fn build_bindings(
             }
             ShortcutState::Released => {
                 log::info!("Ctrl-K Released!");
-                app.emit("show-password-prompt", ())
-                    .expect("Failed to emit show-password-prompt"); 
+                emit(app, AppEvent::ShowPasswordPrompt);
                 app.exit(0);
             }
         }
@@ -43,8 +70,7 @@ pub fn build_bindings(
             }
             ShortcutState::Released => {
                 log::info!("Ctrl+Alt+Delete Released!");
-                app.emit("show-ctrl-alt-delete-prompt", ())
-                    .expect("Failed to emit show-ctrl-alt-delete-prompt");
+                emit(app, AppEvent::ShowCtrlAltDeletePrompt);
             }
         }
     } else if shortcut == minimized_shortcut {
@@ -92,78 +118,17 @@ pub fn is_running_in_rdp() -> bool {
     false
 }
 
-pub fn get_windows_serial() -> Option<String> {
-    let output = Command::new("wmic")
-        .args(&["bios", "get", "serialnumber"])
-        .output()
-        .ok()?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    for line in stdout.lines() {
-        if line.trim().is_empty() || line.contains("SerialNumber") {
-            continue;
-        }
-        return Some(line.trim().to_string());
-    }
-
-    None
-}
-
-pub fn get_macos_serial() -> Option<String> {
-    let output = Command::new("system_profiler")
-        .args(["SPHardwareDataType"])
-        .output()
-        .ok()?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    for line in stdout.lines() {
-        if line.trim_start().starts_with("Serial Number") {
-            return Some(line.split(':').nth(1)?.trim().to_string());
-        }
-    }
-
-    None
-}
-
-pub fn get_linux_serial() -> Option<String> {
-    let output = Command::new("cat")
-        .args(&["/sys/class/dmi/id/product_serial"])
-        .output()
-        .ok()?;
-
-    let stdout = String::from_utf8_lossy(&output.stdout);
-    for line in stdout.lines() {
-        if line.trim().is_empty() {
-            continue;
-        }
-        return Some(line.trim().to_string());
-    }
-
-    None
-}
-
 pub fn get_host_info() -> HostInfo {
-    let mut host_info = HostInfo::default();
-    let mac_address = get_mac_address()
-        .ok()
-        .and_then(|mac| mac.map(|m| m.to_string()));
-    host_info.mac_address = mac_address;
-    host_info.processor_id = Some(std::process::id().to_string());
-    host_info.os = std::env::consts::OS.to_string();
-    host_info.arch = std::env::consts::ARCH.to_string();
-    #[cfg(target_os = "windows")]
-    {
-        host_info.serial_number = get_windows_serial();
-    }
-    #[cfg(target_os = "macos")]
-    {
-        host_info.serial_number = get_macos_serial();
-    }
-    #[cfg(target_os = "linux")]
-    {
-        host_info.serial_number = get_linux_serial();
+    let hardware = hardware::read_hardware_identity();
+    HostInfo {
+        os: std::env::consts::OS.to_string(),
+        arch: std::env::consts::ARCH.to_string(),
+        fingerprint: fingerprint::device_fingerprint(&hardware),
+        hardware: fingerprint::deployment_salt()
+            .ok()
+            .map(|salt| fingerprint::hash_identity(&hardware, &salt)),
+        unreadable: hardware.unreadable,
     }
-    host_info
 }
 
 pub fn is_disallowed_device_connected() -> Vec<USBDevice> {
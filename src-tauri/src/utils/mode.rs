@@ -0,0 +1,185 @@
+//! Kiosk and practice modes.
+//!
+//! Kiosk mode is the real exam: full lockdown and violations are enforced.
+//! Practice mode is for candidates trying the exam out and for development: the window
+//! stays a normal window, detectors only report, and a watermark makes the mode obvious.
+//! Kiosk builds, see `utils::policy`, never run practice mode.
+
+use serde::{Deserialize, Serialize};
+use ts_rs::TS;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum AppMode {
+    Kiosk,
+    #[value(alias = "dev")]
+    Practice,
+}
+
+/// What the app does to the machine and the window in a given mode.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Lockdown {
+    pub fullscreen: bool,
+    pub always_on_top: bool,
+    pub content_protected: bool,
+    pub hide_from_taskbar: bool,
+    /// Disable Ctrl+Alt+Delete actions and run the key mapper sidecar
+    pub block_system_keys: bool,
+    /// End or lock the session on violations instead of only recording them
+    pub enforce_violations: bool,
+    pub devtools: bool,
+    /// Block the context menu, copying and dragging content out of the page
+    pub block_page_interaction: bool,
+    pub watermark: Option<&'static str>,
+}
+
+impl AppMode {
+    pub fn lockdown(&self) -> Lockdown {
+        match self {
+            AppMode::Kiosk => Lockdown {
+                fullscreen: true,
+                always_on_top: true,
+                content_protected: true,
+                hide_from_taskbar: true,
+                block_system_keys: true,
+                enforce_violations: true,
+                devtools: false,
+                block_page_interaction: true,
+                watermark: None,
+            },
+            AppMode::Practice => Lockdown {
+                fullscreen: false,
+                always_on_top: false,
+                content_protected: false,
+                hide_from_taskbar: false,
+                block_system_keys: false,
+                enforce_violations: false,
+                devtools: true,
+                block_page_interaction: false,
+                watermark: Some("PRACTICE MODE"),
+            },
+        }
+    }
+
+    pub fn is_kiosk(&self) -> bool {
+        *self == AppMode::Kiosk
+    }
+}
+
+/// Picks the mode of a launch from the one `requested` on the command line and the one
+/// `configured` in the policy. A policy without a mode is a kiosk policy with the mode locked.
+pub fn resolve_mode(
+    requested: Option<AppMode>,
+    configured: Option<AppMode>,
+    locked: bool,
+    kiosk_build: bool,
+) -> Result<AppMode, String> {
+    let policy_mode = configured.unwrap_or(AppMode::Kiosk);
+    let locked = locked || configured.is_none();
+    let mode = match requested {
+        Some(requested) if requested != policy_mode && locked => {
+            return Err(format!(
+                "the policy locks the mode to {:?}, --mode cannot change it",
+                policy_mode
+            ))
+        }
+        Some(requested) => requested,
+        None => policy_mode,
+    };
+    if kiosk_build && !mode.is_kiosk() {
+        return Err(format!(
+            "this is a kiosk build, {:?} mode is not available",
+            mode
+        ));
+    }
+    Ok(mode)
+}
+
+/// Script injected into every page in modes that carry a watermark.
+pub fn watermark_script(text: &str) -> String {
+    format!(
+        r#"(function () {{
+    if (document.getElementById("__secure_browser_watermark")) return;
+    var mark = document.createElement("div");
+    mark.id = "__secure_browser_watermark";
+    mark.textContent = {text};
+    mark.style.cssText = "position:fixed;inset:0;display:flex;align-items:center;justify-content:center;" +
+        "font:bold 8vw sans-serif;color:rgba(200,0,0,0.15);transform:rotate(-30deg);" +
+        "pointer-events:none;user-select:none;z-index:2147483647;";
+    document.documentElement.appendChild(mark);
+}})();"#,
+        text = serde_json::to_string(text).unwrap_or_else(|_| "\"\"".into())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kiosk_locks_everything_down() {
+        let lockdown = AppMode::Kiosk.lockdown();
+        assert!(lockdown.fullscreen);
+        assert!(lockdown.always_on_top);
+        assert!(lockdown.content_protected);
+        assert!(lockdown.hide_from_taskbar);
+        assert!(lockdown.block_system_keys);
+        assert!(lockdown.enforce_violations);
+        assert!(lockdown.block_page_interaction);
+        assert!(!lockdown.devtools);
+        assert_eq!(lockdown.watermark, None);
+    }
+
+    #[test]
+    fn practice_is_watermarked() {
+        let lockdown = AppMode::Practice.lockdown();
+        assert!(!lockdown.enforce_violations);
+        assert!(!lockdown.block_system_keys);
+        assert_eq!(lockdown.watermark, Some("PRACTICE MODE"));
+    }
+
+    #[test]
+    fn missing_mode_is_locked_kiosk() {
+        assert_eq!(resolve_mode(None, None, false, false), Ok(AppMode::Kiosk));
+        assert_eq!(
+            resolve_mode(Some(AppMode::Kiosk), None, false, false),
+            Ok(AppMode::Kiosk)
+        );
+        assert!(resolve_mode(Some(AppMode::Practice), None, false, false).is_err());
+    }
+
+    #[test]
+    fn locked_mode_cannot_be_changed() {
+        assert!(resolve_mode(Some(AppMode::Practice), Some(AppMode::Kiosk), true, false).is_err());
+        assert!(resolve_mode(Some(AppMode::Kiosk), Some(AppMode::Practice), true, false).is_err());
+        assert_eq!(
+            resolve_mode(Some(AppMode::Kiosk), Some(AppMode::Kiosk), true, false),
+            Ok(AppMode::Kiosk)
+        );
+    }
+
+    #[test]
+    fn unlocked_mode_follows_the_command_line() {
+        assert_eq!(
+            resolve_mode(Some(AppMode::Practice), Some(AppMode::Kiosk), false, false),
+            Ok(AppMode::Practice)
+        );
+        assert_eq!(
+            resolve_mode(None, Some(AppMode::Practice), false, false),
+            Ok(AppMode::Practice)
+        );
+    }
+
+    #[test]
+    fn kiosk_builds_refuse_practice() {
+        assert!(resolve_mode(None, Some(AppMode::Practice), false, true).is_err());
+        assert!(resolve_mode(Some(AppMode::Practice), Some(AppMode::Kiosk), false, true).is_err());
+        assert_eq!(resolve_mode(None, None, false, true), Ok(AppMode::Kiosk));
+    }
+
+    #[test]
+    fn watermark_text_is_escaped() {
+        let script = watermark_script("a\"</div>");
+        assert!(script.contains(r#"mark.textContent = "a\"</div>";"#));
+    }
+}
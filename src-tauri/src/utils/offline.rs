@@ -0,0 +1,402 @@
+//! Offline exams, served from an encrypted package through the `exam://` scheme.
+//!
+//! The package is verified at launch but stays encrypted until the console releases its key or
+//! a proctor enters the code. Until then every `exam://` request gets a waiting page. The
+//! release goes by the console's clock, not this machine's, see `shared::release`: from the
+//! launch on the app asks `offline.release_url` for the key until the console gives it out.
+
+use crate::utils::audit::audit;
+use crate::utils::exam_package::{
+    mime_type, parse_verifying_key, ExamContent, PackageFile, SealedPackage,
+};
+use crate::utils::proctor::{ProctorCredentials, ProctorPolicy};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::Deserialize;
+use shared::proctor::{sign, verify_response, SIGNATURE_HEADER};
+use shared::release::{ReleaseRequest, ReleaseResponse, NONCE_LENGTH};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::http::{header, Request, Response, StatusCode};
+use tauri::{AppHandle, Manager, Url};
+
+pub const SCHEME: &str = "exam";
+const REACHABLE_TIMEOUT: Duration = Duration::from_secs(3);
+/// Time between release requests while the console holds the key back
+const RELEASE_INTERVAL: Duration = Duration::from_secs(10);
+/// Proctor codes tried before the package only opens at the start time
+pub const MAX_CODE_ATTEMPTS: u32 = 5;
+
+const LOCKED_PAGE: &str = r#"<!doctype html>
+<html>
+<head><meta charset="utf-8"><title>Exam not started</title></head>
+<body style="font-family:sans-serif;text-align:center;padding-top:20vh">
+<h1>The exam has not started yet</h1>
+<p>This page opens by itself at the start time, or a proctor can unlock it now.</p>
+<form id="unlock">
+<input id="code" type="password" placeholder="Proctor code" autocomplete="off">
+<button type="submit">Unlock</button>
+</form>
+<p id="error" style="color:#b00"></p>
+<script>
+document.getElementById("unlock").addEventListener("submit", function (event) {
+    event.preventDefault();
+    window.__TAURI_INTERNALS__
+        .invoke("unlock_exam_package", { code: document.getElementById("code").value })
+        .then(function () { location.reload(); })
+        .catch(function (err) { document.getElementById("error").textContent = err; });
+});
+setTimeout(function () { location.reload(); }, 15000);
+</script>
+</body>
+</html>"#;
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OfflinePolicy {
+    /// Signed exam package used when the hosted exam is unreachable
+    pub package: Option<PathBuf>,
+    /// Hex ed25519 key the package must be signed with
+    pub public_key: Option<String>,
+    /// Use the package even when the hosted exam is reachable
+    pub always: bool,
+    /// Console that releases the package key at the start time, e.g.
+    /// `http://10.0.0.2:8787/release`. Without it only the proctor code opens the package
+    pub release_url: Option<String>,
+}
+
+/// Where to ask for the release key and the keys to ask with.
+#[derive(Debug, Clone)]
+pub struct Release {
+    pub url: String,
+    pub credentials: ProctorCredentials,
+}
+
+impl OfflinePolicy {
+    /// Reads the package and checks its signature, `None` when no package is configured.
+    pub fn load_package(&self) -> Result<Option<SealedPackage>, String> {
+        let Some(path) = &self.package else {
+            return Ok(None);
+        };
+        let key = self
+            .public_key
+            .as_deref()
+            .ok_or("`offline.package` is set but `offline.public_key` is not")?;
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read exam package {}: {}", path.display(), err))?;
+        let file: PackageFile = serde_json::from_str(&contents)
+            .map_err(|err| format!("invalid exam package {}: {}", path.display(), err))?;
+        file.verify(&parse_verifying_key(key)?).map(Some)
+    }
+
+    /// The console to ask for the release key, `None` without a `release_url`. Asking needs
+    /// the proctor key and `proctor.console_key`, like the heartbeats.
+    pub fn release(&self, proctor: &ProctorPolicy) -> Result<Option<Release>, String> {
+        let Some(url) = &self.release_url else {
+            return Ok(None);
+        };
+        Ok(Some(Release {
+            url: url.clone(),
+            credentials: proctor.keys()?,
+        }))
+    }
+}
+
+#[derive(Default)]
+pub struct OfflineState {
+    pub package: Option<SealedPackage>,
+    content: Option<ExamContent>,
+    code_attempts: u32,
+}
+
+pub struct OfflineExam(pub Mutex<OfflineState>);
+
+impl OfflineState {
+    pub fn new(package: Option<SealedPackage>) -> Self {
+        Self {
+            package,
+            content: None,
+            code_attempts: 0,
+        }
+    }
+
+    pub fn is_unlocked(&self) -> bool {
+        self.content.is_some()
+    }
+
+    /// Counts a proctor code attempt and hands out the package to try it on, `None` when the
+    /// package is already open. The attempt counts before the code is checked, so attempts
+    /// made side by side cannot get past the limit.
+    fn start_code_attempt(&mut self) -> Result<Option<SealedPackage>, String> {
+        if self.is_unlocked() {
+            return Ok(None);
+        }
+        let package = self.package.as_ref().ok_or("no exam package is loaded")?;
+        if self.code_attempts >= MAX_CODE_ATTEMPTS {
+            return Err(
+                "too many wrong proctor codes, the exam opens at the start time".to_string(),
+            );
+        }
+        self.code_attempts += 1;
+        Ok(Some(package.clone()))
+    }
+
+    /// The request for the release key, `None` once there is nothing left to release.
+    fn release_request(&self) -> Option<ReleaseRequest> {
+        let (Some(package), None) = (&self.package, &self.content) else {
+            return None;
+        };
+        package.header.time_key.as_ref()?;
+        let mut nonce = [0u8; NONCE_LENGTH];
+        OsRng.fill_bytes(&mut nonce);
+        Some(ReleaseRequest {
+            exam_id: package.header.exam_id.clone(),
+            not_before: package.header.not_before,
+            nonce: hex::encode(nonce),
+        })
+    }
+}
+
+async fn request_release(
+    client: &reqwest::Client,
+    release: &Release,
+    request: &ReleaseRequest,
+) -> Result<[u8; 32], String> {
+    let body = serde_json::to_vec(request).map_err(|err| err.to_string())?;
+    let response = client
+        .post(&release.url)
+        .header(SIGNATURE_HEADER, sign(&release.credentials.key, &body))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let reason = response.text().await.unwrap_or_default();
+        return Err(format!("console answered {}: {}", status, reason));
+    }
+    let signature = response
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .ok_or("response is not signed")?;
+    let bytes = response.bytes().await.map_err(|err| err.to_string())?;
+    verify_response(&release.credentials.console_key, &bytes, &signature)
+        .map_err(|err| format!("response rejected: {}", err))?;
+    let reply: ReleaseResponse =
+        serde_json::from_slice(&bytes).map_err(|err| format!("invalid response: {}", err))?;
+    reply.key_for(request)
+}
+
+/// Asks the console for the package key until it is released or the package is opened
+/// with a proctor code.
+pub async fn run_release(app: AppHandle, release: Release) {
+    let client = match reqwest::Client::builder()
+        .timeout(REACHABLE_TIMEOUT)
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!("Could not build the release client: {}", err);
+            return;
+        }
+    };
+    loop {
+        let request = match app.state::<OfflineExam>().0.lock() {
+            Ok(state) => state.release_request(),
+            Err(_) => None,
+        };
+        let Some(request) = request else {
+            return;
+        };
+        match request_release(&client, &release, &request).await {
+            Ok(key) => {
+                let state = app.state::<OfflineExam>();
+                let Ok(mut state) = state.0.lock() else {
+                    return;
+                };
+                let Some(package) = &state.package else {
+                    return;
+                };
+                match package.unlock_with_release_key(&key) {
+                    Ok(content) => {
+                        state.content = Some(content);
+                        audit(
+                            &app,
+                            "exam_package_unlocked",
+                            serde_json::json!({ "exam_id": request.exam_id, "by": "start_time" }),
+                        );
+                        return;
+                    }
+                    Err(err) => log::error!("Could not release the exam package: {}", err),
+                }
+            }
+            Err(err) => log::info!("Exam package not released yet: {}", err),
+        }
+        tokio::time::sleep(RELEASE_INTERVAL).await;
+    }
+}
+
+/// Where the package entry page is served, custom schemes are plain http on Windows.
+pub fn entry_url(entry: &str) -> Url {
+    let base = if cfg!(any(windows, target_os = "android")) {
+        format!("http://{}.localhost/", SCHEME)
+    } else {
+        format!("{}://localhost/", SCHEME)
+    };
+    Url::parse(&base)
+        .and_then(|base| base.join(entry))
+        .expect("exam scheme url is valid")
+}
+
+/// Whether the hosted exam answers a TCP connection at all.
+pub fn is_reachable(url: &Url) -> bool {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return false;
+    };
+    match (host, port).to_socket_addrs() {
+        Ok(addrs) => addrs
+            .into_iter()
+            .any(|addr| TcpStream::connect_timeout(&addr, REACHABLE_TIMEOUT).is_ok()),
+        Err(_) => false,
+    }
+}
+
+/// Opens the package with a proctor code. The key derivation is slow on purpose, so it runs
+/// on a blocking thread and the `exam://` handler is not held up meanwhile.
+pub async fn unlock_with_code(app: &AppHandle, code: String) -> Result<(), String> {
+    let package = {
+        let state = app.state::<OfflineExam>();
+        let mut state = state.0.lock().map_err(|err| err.to_string())?;
+        let attempt = state.start_code_attempt();
+        if let Err(err) = &attempt {
+            audit(
+                app,
+                "exam_package_unlock_refused",
+                serde_json::json!({ "reason": err, "attempts": state.code_attempts }),
+            );
+        }
+        match attempt? {
+            Some(package) => package,
+            None => return Ok(()),
+        }
+    };
+    let exam_id = package.header.exam_id.clone();
+    let unlocked = tauri::async_runtime::spawn_blocking(move || package.unlock_with_code(&code))
+        .await
+        .map_err(|err| err.to_string())?;
+    let state = app.state::<OfflineExam>();
+    let mut state = state.0.lock().map_err(|err| err.to_string())?;
+    match unlocked {
+        Ok(content) => {
+            if !state.is_unlocked() {
+                state.content = Some(content);
+                audit(
+                    app,
+                    "exam_package_unlocked",
+                    serde_json::json!({ "exam_id": exam_id, "by": "proctor_code" }),
+                );
+            }
+            Ok(())
+        }
+        Err(err) => {
+            audit(
+                app,
+                "exam_package_unlock_failed",
+                serde_json::json!({
+                    "exam_id": exam_id,
+                    "reason": err,
+                    "attempts": state.code_attempts,
+                }),
+            );
+            Err(err)
+        }
+    }
+}
+
+fn response(status: StatusCode, content_type: &str, body: Vec<u8>) -> Response<Vec<u8>> {
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, "no-store")
+        .body(body)
+        .unwrap_or_default()
+}
+
+/// Handler for the `exam://` scheme.
+pub fn handle_request(app: &AppHandle, request: &Request<Vec<u8>>) -> Response<Vec<u8>> {
+    let state = app.state::<OfflineExam>();
+    let Ok(state) = state.0.lock() else {
+        return response(StatusCode::INTERNAL_SERVER_ERROR, "text/plain", Vec::new());
+    };
+    let Some(package) = &state.package else {
+        return response(StatusCode::NOT_FOUND, "text/plain", Vec::new());
+    };
+    let path = request.uri().path().trim_start_matches('/');
+    let path = if path.is_empty() {
+        package.header.entry.as_str()
+    } else {
+        path
+    };
+    let Some(content) = &state.content else {
+        return response(
+            StatusCode::LOCKED,
+            "text/html",
+            LOCKED_PAGE.as_bytes().to_vec(),
+        );
+    };
+    match content.files.get(path) {
+        Some(bytes) => response(StatusCode::OK, mime_type(path), bytes.clone()),
+        None => response(StatusCode::NOT_FOUND, "text/plain", Vec::new()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::exam_package::{seal, SealOptions};
+    use ed25519_dalek::SigningKey;
+
+    fn sealed() -> SealedPackage {
+        let signing_key = SigningKey::from_bytes(&[3; 32]);
+        let content = ExamContent {
+            files: [("index.html".to_string(), b"<p>exam</p>".to_vec())].into(),
+        };
+        let options = SealOptions {
+            exam_id: "exam-1".into(),
+            entry: "index.html".into(),
+            not_before: 0,
+            release_secret: Some(b"release"),
+            proctor_code: None,
+        };
+        seal(&content, options, &signing_key)
+            .and_then(|file| file.verify(&signing_key.verifying_key()))
+            .unwrap()
+    }
+
+    #[test]
+    fn code_attempts_stop_at_the_limit() {
+        let mut state = OfflineState::new(Some(sealed()));
+        for _ in 0..MAX_CODE_ATTEMPTS {
+            assert!(state.start_code_attempt().unwrap().is_some());
+        }
+        let err = state.start_code_attempt().unwrap_err();
+        assert!(err.contains("too many"), "{}", err);
+        assert_eq!(state.code_attempts, MAX_CODE_ATTEMPTS);
+    }
+
+    #[test]
+    fn an_open_package_needs_no_code() {
+        let mut state = OfflineState::new(Some(sealed()));
+        state.content = Some(ExamContent::default());
+        state.code_attempts = MAX_CODE_ATTEMPTS;
+        assert!(state.start_code_attempt().unwrap().is_none());
+
+        let mut state = OfflineState::new(None);
+        assert!(state.start_code_attempt().is_err());
+    }
+}
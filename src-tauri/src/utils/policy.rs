@@ -0,0 +1,225 @@
+//! Deployment policy, loaded from a JSON file.
+//!
+//! Every field is optional in the file, anything left out falls back to the defaults below.
+//!
+//! Kiosk builds, made with SECURE_BROWSER_POLICY_KEY set, only run the policy installed as
+//! `policy.json` next to the executable, signed with the `sign_policy` tool. The key is built
+//! in rather than read at run time, for the same reason as the integrity key: whoever can pick
+//! the policy at launch can turn the lockdown off.
+
+use crate::utils::clipboard::ClipboardPolicy;
+use crate::utils::content::ContentPolicy;
+use crate::utils::display::DisplayPolicy;
+use crate::utils::integrity::IntegrityPolicy;
+use crate::utils::mode::AppMode;
+use crate::utils::navigation::NavigationPolicy;
+use crate::utils::offline::OfflinePolicy;
+use crate::utils::proctor::ProctorPolicy;
+use crate::utils::proxy::ProxyPolicy;
+use crate::utils::sidecar::SidecarPolicy;
+use crate::utils::sync::SubmissionPolicy;
+use crate::utils::unlock::UnlockPolicy;
+use crate::utils::watchdog::WatchdogPolicy;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use shared::keys::RuleSet;
+use std::path::{Path, PathBuf};
+
+pub const POLICY_ENV: &str = "SECURE_BROWSER_POLICY";
+pub const POLICY_FILE_NAME: &str = "policy.json";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Policy {
+    pub mode: Option<AppMode>,
+    pub seat: Option<String>,
+    pub exam_url: Option<String>,
+    pub log_dir: Option<PathBuf>,
+    /// Fields the command line may not override in kiosk mode, e.g. `["mode", "exam_url"]`
+    pub locked: Vec<String>,
+    pub displays: DisplayPolicy,
+    pub navigation: NavigationPolicy,
+    pub content: ContentPolicy,
+    pub proxy: ProxyPolicy,
+    pub offline: OfflinePolicy,
+    pub submission: SubmissionPolicy,
+    pub clipboard: ClipboardPolicy,
+    pub keyboard: KeyboardPolicy,
+    pub sidecar: SidecarPolicy,
+    pub integrity: IntegrityPolicy,
+    pub watchdog: WatchdogPolicy,
+    pub proctor: ProctorPolicy,
+    pub unlock: UnlockPolicy,
+}
+
+/// Key suppression rules for the mapper sidecar, see `shared::keys` for the syntax.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct KeyboardPolicy {
+    /// One rule per entry, e.g. `"block alt+tab"`. The built-in rules apply when empty
+    pub rules: Vec<String>,
+}
+
+/// A policy as installed in kiosk builds. The signature covers the policy bytes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyFile {
+    pub policy: String,
+    pub signature: String,
+}
+
+impl PolicyFile {
+    /// Signs the policy text as given, after checking that it parses.
+    pub fn sign(policy: &str, signing_key: &SigningKey) -> Result<Self, String> {
+        serde_json::from_str::<Policy>(policy).map_err(|err| format!("invalid policy: {}", err))?;
+        Ok(Self {
+            policy: policy.to_string(),
+            signature: BASE64.encode(signing_key.sign(policy.as_bytes()).to_bytes()),
+        })
+    }
+
+    pub fn verify(&self, key: &VerifyingKey) -> Result<Policy, String> {
+        let signature: [u8; 64] = BASE64
+            .decode(&self.signature)
+            .map_err(|err| format!("policy signature is not base64: {}", err))?
+            .try_into()
+            .map_err(|_| "policy signature must be 64 bytes".to_string())?;
+        key.verify(self.policy.as_bytes(), &Signature::from_bytes(&signature))
+            .map_err(|_| "policy signature does not match".to_string())?;
+        serde_json::from_str(&self.policy).map_err(|err| format!("invalid policy: {}", err))
+    }
+}
+
+/// The policy key built into kiosk builds, `None` for builds made without one.
+pub fn public_key() -> Option<Result<VerifyingKey, String>> {
+    option_env!("SECURE_BROWSER_POLICY_KEY")
+        .filter(|key| !key.is_empty())
+        .map(crate::utils::exam_package::parse_verifying_key)
+}
+
+/// Whether this build only runs its signed policy.
+pub fn is_kiosk_build() -> bool {
+    public_key().is_some()
+}
+
+/// `policy.json` next to the executable, where kiosk builds are installed with their policy.
+pub fn installed_path() -> Result<PathBuf, String> {
+    let exe = std::env::current_exe()
+        .map_err(|err| format!("could not locate the executable: {}", err))?;
+    Ok(exe
+        .parent()
+        .ok_or("the executable has no parent directory")?
+        .join(POLICY_FILE_NAME))
+}
+
+impl KeyboardPolicy {
+    pub fn rule_set(&self) -> Result<RuleSet, String> {
+        if self.rules.is_empty() {
+            return Ok(RuleSet::default());
+        }
+        RuleSet::parse(&self.rules.join("\n"))
+            .map_err(|err| format!("invalid `keyboard.rules`: {}", err))
+    }
+}
+
+impl Policy {
+    pub fn is_locked(&self, field: &str) -> bool {
+        self.locked.iter().any(|locked| locked == field)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read policy {}: {}", path.display(), err))?;
+        serde_json::from_str(&contents)
+            .map_err(|err| format!("invalid policy {}: {}", path.display(), err))
+    }
+
+    /// Reads a signed policy and checks its signature.
+    pub fn load_signed(path: &Path, key: &VerifyingKey) -> Result<Self, String> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|err| format!("could not read policy {}: {}", path.display(), err))?;
+        let file: PolicyFile = serde_json::from_str(&contents)
+            .map_err(|err| format!("invalid policy {}: {}", path.display(), err))?;
+        file.verify(key)
+            .map_err(|err| format!("{}: {}", path.display(), err))
+    }
+
+    /// Loads the policy named by `SECURE_BROWSER_POLICY`, or the defaults when it is unset.
+    pub fn from_env() -> Result<Self, String> {
+        match std::env::var(POLICY_ENV) {
+            Ok(path) if !path.is_empty() => Self::load(Path::new(&path)),
+            _ => Ok(Self::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = r#"{"mode": "kiosk", "seat": "A1", "locked": ["mode", "seat"]}"#;
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_bytes(&[4; 32])
+    }
+
+    #[test]
+    fn a_signed_policy_verifies() {
+        let file = PolicyFile::sign(POLICY, &signing_key()).unwrap();
+        assert_eq!(file.policy, POLICY);
+        let policy = file.verify(&signing_key().verifying_key()).unwrap();
+        assert_eq!(policy.mode, Some(AppMode::Kiosk));
+        assert_eq!(policy.seat.as_deref(), Some("A1"));
+        assert!(policy.is_locked("mode") && policy.is_locked("seat"));
+        assert!(!policy.is_locked("exam_url"));
+    }
+
+    #[test]
+    fn a_tampered_policy_is_rejected() {
+        let key = signing_key().verifying_key();
+        let file = PolicyFile::sign(POLICY, &signing_key()).unwrap();
+
+        let unlocked = PolicyFile {
+            policy: POLICY.replace(r#""locked": ["mode", "seat"]"#, r#""locked": []"#),
+            ..file.clone()
+        };
+        assert_ne!(unlocked.policy, file.policy);
+        assert_eq!(
+            unlocked.verify(&key).unwrap_err(),
+            "policy signature does not match"
+        );
+        // whitespace is part of what was signed
+        let reformatted = PolicyFile {
+            policy: format!("{} ", POLICY),
+            ..file.clone()
+        };
+        assert!(reformatted.verify(&key).is_err());
+
+        let mut signature = BASE64.decode(&file.signature).unwrap();
+        signature[0] ^= 1;
+        let forged = PolicyFile {
+            signature: BASE64.encode(&signature),
+            ..file.clone()
+        };
+        assert!(forged.verify(&key).is_err());
+        let short = PolicyFile {
+            signature: BASE64.encode(&signature[..32]),
+            ..file.clone()
+        };
+        assert_eq!(
+            short.verify(&key).unwrap_err(),
+            "policy signature must be 64 bytes"
+        );
+
+        let other = SigningKey::from_bytes(&[5; 32]).verifying_key();
+        assert!(file.verify(&other).is_err());
+    }
+
+    #[test]
+    fn only_valid_policies_are_signed() {
+        assert!(PolicyFile::sign("{\"mode\": \"holiday\"}", &signing_key()).is_err());
+        assert!(PolicyFile::sign("not json", &signing_key()).is_err());
+        assert!(PolicyFile::sign("{}", &signing_key()).is_ok());
+    }
+}
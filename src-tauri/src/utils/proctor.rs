@@ -0,0 +1,327 @@
+//! Heartbeats to the proctoring server and the commands it sends back, see `shared::proctor`.
+//!
+//! Every heartbeat carries the session state and the violations the server has not
+//! acknowledged yet. Heartbeats are signed with the proctor key and answers with the
+//! console's own ed25519 key, pinned in the policy as `console_key`, so a seat cannot pass for
+//! the console. An answer has to echo the heartbeat's session and random nonce. Commands in an
+//! answer that passes both checks are carried out once each and acknowledged in the next
+//! heartbeat; any other answer is dropped along with its commands. The app does not start
+//! when heartbeats are on but either key is missing. Centres without internet run the
+//! `console` crate on the invigilator's laptop, `cargo run --bin mock_proctor` is a server to
+//! try it with. With `discovery` enabled the console is found over mDNS, see
+//! `utils::discovery`, and `endpoint` is the fallback.
+
+use crate::utils::audit::audit;
+use crate::utils::discovery::{self, DiscoveryPolicy};
+use crate::utils::events::{emit, AppEvent, ProctorNotice};
+use crate::utils::secrets::PROCTOR_KEY;
+use crate::utils::session::{self, SessionStore};
+use crate::utils::types::{DeviceFingerprint, SessionStatus};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use serde::Deserialize;
+use shared::discovery::parse_public_key;
+use shared::proctor::{
+    sign, verify_response, CommandAction, Heartbeat, HeartbeatResponse, ProctorCommand,
+    ViolationReport, NONCE_LENGTH, SIGNATURE_HEADER,
+};
+use std::collections::HashSet;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+
+/// Time between a terminate command and the app closing, so the candidate sees why
+const TERMINATE_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProctorPolicy {
+    /// Heartbeats are only sent when set or when discovery is on
+    pub endpoint: Option<String>,
+    pub interval_secs: u64,
+    pub timeout_secs: u64,
+    /// Missed heartbeats in a row before the server is recorded as unreachable
+    pub max_missed: u32,
+    /// Hex ed25519 public key the console signs its answers with, printed when it starts
+    pub console_key: Option<String>,
+    pub discovery: DiscoveryPolicy,
+}
+
+impl Default for ProctorPolicy {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            interval_secs: 5,
+            timeout_secs: 5,
+            max_missed: 3,
+            console_key: None,
+            discovery: DiscoveryPolicy::default(),
+        }
+    }
+}
+
+pub fn proctor_key() -> Result<Vec<u8>, String> {
+    PROCTOR_KEY.load()
+}
+
+/// Keys the heartbeat client signs and checks with.
+#[derive(Debug, Clone)]
+pub struct ProctorCredentials {
+    pub key: Vec<u8>,
+    /// Hex ed25519 public key of the console
+    pub console_key: String,
+}
+
+impl ProctorPolicy {
+    pub fn enabled(&self) -> bool {
+        self.endpoint.is_some() || self.discovery.enabled
+    }
+
+    /// The proctor key and the console key, an error unless both are provisioned.
+    pub fn keys(&self) -> Result<ProctorCredentials, String> {
+        let console_key = self
+            .console_key
+            .clone()
+            .ok_or("talking to the console needs `proctor.console_key` in the policy")?;
+        parse_public_key(&console_key)?;
+        Ok(ProctorCredentials {
+            key: proctor_key()?,
+            console_key,
+        })
+    }
+
+    /// The keys for the heartbeats, `None` when they are off. A policy that turns them on
+    /// without a console key, or a build without a proctor key, is an error.
+    pub fn credentials(&self) -> Result<Option<ProctorCredentials>, String> {
+        if !self.enabled() {
+            return Ok(None);
+        }
+        self.keys().map(Some)
+    }
+}
+
+#[derive(Debug, Default)]
+struct ClientState {
+    seq: u64,
+    acked_violation: u64,
+    /// Commands carried out but not yet acknowledged to the server
+    pending_acks: Vec<String>,
+    done: HashSet<String>,
+    missed: u32,
+    unreachable: bool,
+}
+
+fn heartbeat(
+    app: &AppHandle,
+    state: &ClientState,
+    fingerprint: &Option<DeviceFingerprint>,
+) -> Option<Heartbeat> {
+    let store = app.state::<SessionStore>();
+    let session = store.0.lock().ok()?;
+    // enums go out under their serde names, e.g. `active`
+    let text = |value: serde_json::Value| value.as_str().unwrap_or_default().to_string();
+    let mut nonce = [0u8; NONCE_LENGTH];
+    OsRng.fill_bytes(&mut nonce);
+    Some(Heartbeat {
+        seq: state.seq,
+        nonce: hex::encode(nonce),
+        session_id: session.state.id.clone(),
+        seat: session.state.seat.clone(),
+        fingerprint: fingerprint.clone(),
+        status: text(serde_json::json!(session.state.status)),
+        mode: text(serde_json::json!(session.state.mode)),
+        violation_count: session.state.violation_count,
+        violations: session
+            .violations
+            .iter()
+            .filter(|violation| violation.id > state.acked_violation)
+            .map(|violation| ViolationReport {
+                id: violation.id,
+                kind: text(serde_json::json!(violation.kind)),
+                detail: violation.detail.clone(),
+                occurred_at: violation.occurred_at,
+            })
+            .collect(),
+        acked_commands: state.pending_acks.clone(),
+        unlock_challenge: session
+            .lock
+            .as_ref()
+            .and_then(|event| event.notice.challenge.clone()),
+        sent_at: chrono::Utc::now().timestamp_millis(),
+    })
+}
+
+async fn send(
+    client: &reqwest::Client,
+    endpoint: &str,
+    credentials: &ProctorCredentials,
+    heartbeat: &Heartbeat,
+) -> Result<HeartbeatResponse, String> {
+    let body = serde_json::to_vec(heartbeat).map_err(|err| err.to_string())?;
+    let response = client
+        .post(endpoint)
+        .header(SIGNATURE_HEADER, sign(&credentials.key, &body))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(body)
+        .send()
+        .await
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(format!("proctor answered {}", response.status()));
+    }
+    let signature = response
+        .headers()
+        .get(SIGNATURE_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+        .ok_or("response is not signed")?;
+    let bytes = response.bytes().await.map_err(|err| err.to_string())?;
+    verify_response(&credentials.console_key, &bytes, &signature)
+        .map_err(|err| format!("response rejected: {}", err))?;
+    let reply: HeartbeatResponse =
+        serde_json::from_slice(&bytes).map_err(|err| format!("invalid response: {}", err))?;
+    reply
+        .answers(heartbeat)
+        .map_err(|err| format!("response rejected: {}", err))?;
+    Ok(reply)
+}
+
+fn apply(app: &AppHandle, command: &ProctorCommand) {
+    log::info!("Proctor command {}: {:?}", command.id, command.action);
+    audit(
+        app,
+        "proctor_command",
+        serde_json::json!({ "id": command.id, "command": command.action }),
+    );
+    match &command.action {
+        CommandAction::Lock { reason } => session::lock(app, reason.clone(), None),
+        CommandAction::Unlock => session::unlock(app, "proctor_command", None),
+        CommandAction::ExtendTime { minutes } => session::extend_time(app, *minutes),
+        CommandAction::Terminate { reason } => {
+            log::info!("Session terminated by the proctor: {}", reason);
+            session::set_status(app, SessionStatus::Ended);
+            emit(
+                app,
+                AppEvent::ProctorMessage(ProctorNotice {
+                    text: reason.clone(),
+                    received_at: chrono::Utc::now().timestamp_millis(),
+                }),
+            );
+            let app = app.clone();
+            tauri::async_runtime::spawn(async move {
+                tokio::time::sleep(TERMINATE_DELAY).await;
+                app.exit(0);
+            });
+        }
+        CommandAction::Message { text } => emit(
+            app,
+            AppEvent::ProctorMessage(ProctorNotice {
+                text: text.clone(),
+                received_at: chrono::Utc::now().timestamp_millis(),
+            }),
+        ),
+    }
+}
+
+async fn find_endpoint(
+    app: &AppHandle,
+    client: &reqwest::Client,
+    policy: &ProctorPolicy,
+    pinned: &str,
+) -> Option<String> {
+    if policy.discovery.enabled {
+        if let Some(endpoint) = discovery::discover(app, client, &policy.discovery, pinned).await {
+            return Some(endpoint);
+        }
+        if let Some(fallback) = &policy.endpoint {
+            log::warn!("No proctor console found, using {}", fallback);
+        }
+    }
+    policy.endpoint.clone()
+}
+
+/// Sends heartbeats until the app exits, with the credentials from
+/// [`ProctorPolicy::credentials`].
+pub async fn run(app: AppHandle, policy: ProctorPolicy, credentials: Option<ProctorCredentials>) {
+    let Some(credentials) = credentials else {
+        log::info!("No proctor endpoint configured, heartbeats are off");
+        return;
+    };
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(policy.timeout_secs))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!("Could not build the proctor client: {}", err);
+            return;
+        }
+    };
+    // reading the hardware blocks on WMI and IOKit, keep it off the async workers
+    let fingerprint = tauri::async_runtime::spawn_blocking(|| {
+        let hardware = crate::utils::hardware::read_hardware_identity();
+        crate::utils::fingerprint::device_fingerprint(&hardware)
+    })
+    .await
+    .ok()
+    .flatten();
+    let interval = Duration::from_secs(policy.interval_secs.max(1));
+    let mut state = ClientState::default();
+    let mut endpoint = None;
+
+    loop {
+        if endpoint.is_none() {
+            endpoint = find_endpoint(&app, &client, &policy, &credentials.console_key).await;
+        }
+        let Some(url) = endpoint.clone() else {
+            tokio::time::sleep(interval).await;
+            continue;
+        };
+        state.seq += 1;
+        let Some(heartbeat) = heartbeat(&app, &state, &fingerprint) else {
+            log::error!("Could not read the session for the heartbeat");
+            tokio::time::sleep(interval).await;
+            continue;
+        };
+        match send(&client, &url, &credentials, &heartbeat).await {
+            Ok(reply) => {
+                if state.unreachable {
+                    audit(
+                        &app,
+                        "proctor_reachable",
+                        serde_json::json!({ "missed": state.missed }),
+                    );
+                }
+                state.missed = 0;
+                state.unreachable = false;
+                // the acknowledgements went out with this heartbeat
+                state.pending_acks.clear();
+                if let Some(acked) = reply.acked_violation {
+                    state.acked_violation = state.acked_violation.max(acked);
+                }
+                for command in reply.commands {
+                    if state.done.insert(command.id.clone()) {
+                        apply(&app, &command);
+                    }
+                    state.pending_acks.push(command.id);
+                }
+            }
+            Err(err) => {
+                state.missed = state.missed.saturating_add(1);
+                log::warn!("Heartbeat {} failed: {}", heartbeat.seq, err);
+                if state.missed == policy.max_missed {
+                    state.unreachable = true;
+                    audit(
+                        &app,
+                        "proctor_unreachable",
+                        serde_json::json!({ "missed": state.missed, "error": err }),
+                    );
+                }
+                // the console may have moved, look for it again
+                if policy.discovery.enabled && state.missed >= policy.max_missed {
+                    endpoint = None;
+                }
+            }
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
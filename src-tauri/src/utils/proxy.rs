@@ -0,0 +1,554 @@
+//! Local filtering proxy for exam traffic.
+//!
+//! The exam webview sends every request through this proxy. Hosts outside the allowlist get a
+//! `403`, everything else is forwarded, and each request is recorded to the audit log.
+//! HTTPS goes through `CONNECT` tunnels, so only the host of those is known, never the path.
+
+use crate::utils::audit::audit;
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tauri::AppHandle;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Largest request head accepted from the webview
+const MAX_HEAD: usize = 16 * 1024;
+const HOP_BY_HOP: [&str; 4] = [
+    "connection",
+    "proxy-connection",
+    "keep-alive",
+    "proxy-authorization",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct ProxyPolicy {
+    pub enabled: bool,
+    /// Hosts the exam may reach besides the navigation origins, `*.example.com` matches subdomains
+    pub allowed_hosts: Vec<String>,
+}
+
+impl Default for ProxyPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            allowed_hosts: Vec::new(),
+        }
+    }
+}
+
+/// Metadata of one proxied request, as written to the audit log.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProxyRecord {
+    pub method: String,
+    pub host: String,
+    pub port: u16,
+    /// `None` for HTTPS tunnels
+    pub path: Option<String>,
+    pub allowed: bool,
+    pub status: u16,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+pub type Recorder = Arc<dyn Fn(ProxyRecord) + Send + Sync>;
+
+pub fn host_allowed(patterns: &[String], host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_ascii_lowercase();
+    patterns.iter().any(|pattern| {
+        let pattern = pattern.trim_end_matches('.').to_ascii_lowercase();
+        match pattern.strip_prefix("*.") {
+            Some(domain) => host.ends_with(&format!(".{}", domain)),
+            None => host == pattern,
+        }
+    })
+}
+
+/// Splits `host:port`, including bracketed IPv6 hosts. An IPv6 host without brackets is
+/// ambiguous and refused.
+fn split_authority(authority: &str, default_port: u16) -> Option<(String, u16)> {
+    let (host, port) = if let Some(rest) = authority.strip_prefix('[') {
+        let (host, rest) = rest.split_once(']')?;
+        match rest {
+            "" => (host, None),
+            rest => (host, Some(rest.strip_prefix(':')?)),
+        }
+    } else {
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (authority, None),
+        };
+        if host.contains(':') {
+            return None;
+        }
+        (host, port)
+    };
+    if host.is_empty() {
+        return None;
+    }
+    let port = match port {
+        Some(port) => port.parse().ok()?,
+        None => default_port,
+    };
+    Some((host.to_string(), port))
+}
+
+struct RequestHead {
+    method: String,
+    target: String,
+    version: String,
+    headers: Vec<(String, String)>,
+}
+
+fn parse_head(head: &str) -> Option<RequestHead> {
+    let mut lines = head.split("\r\n");
+    let mut request_line = lines.next()?.split_whitespace();
+    let method = request_line.next()?.to_string();
+    let target = request_line.next()?.to_string();
+    let version = request_line.next()?.to_string();
+    let headers = lines
+        .filter(|line| !line.is_empty())
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim().to_string(), value.trim().to_string()))
+        })
+        .collect();
+    Some(RequestHead {
+        method,
+        target,
+        version,
+        headers,
+    })
+}
+
+/// Reads up to the end of the request head. Returns the head and whatever body bytes
+/// arrived with it, or `None` if the client went away first.
+async fn read_head(stream: &mut TcpStream) -> std::io::Result<Option<(String, Vec<u8>)>> {
+    let mut buf = Vec::with_capacity(1024);
+    let mut chunk = [0u8; 1024];
+    loop {
+        let read = stream.read(&mut chunk).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        buf.extend_from_slice(&chunk[..read]);
+        if let Some(end) = buf.windows(4).position(|window| window == b"\r\n\r\n") {
+            let rest = buf.split_off(end + 4);
+            return Ok(Some((String::from_utf8_lossy(&buf).into_owned(), rest)));
+        }
+        if buf.len() > MAX_HEAD {
+            return Err(std::io::Error::other("request head too large"));
+        }
+    }
+}
+
+/// Parses the status code out of the first bytes of a response.
+fn response_status(bytes: &[u8]) -> Option<u16> {
+    let line = bytes.split(|byte| *byte == b'\r').next()?;
+    let line = std::str::from_utf8(line).ok()?;
+    line.split_whitespace().nth(1)?.parse().ok()
+}
+
+async fn respond(client: &mut TcpStream, status: u16, reason: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        status, reason
+    );
+    client.write_all(response.as_bytes()).await
+}
+
+/// Copies `from` into `to` until EOF, counting bytes and keeping the first chunk seen.
+async fn pipe<R, W>(
+    mut from: R,
+    mut to: W,
+    total: &mut u64,
+    first: &mut Vec<u8>,
+) -> std::io::Result<()>
+where
+    R: AsyncRead + Unpin,
+    W: AsyncWrite + Unpin,
+{
+    let mut buf = [0u8; 8192];
+    loop {
+        let read = from.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        if first.is_empty() {
+            first.extend_from_slice(&buf[..read]);
+        }
+        to.write_all(&buf[..read]).await?;
+        *total += read as u64;
+    }
+    to.shutdown().await.ok();
+    Ok(())
+}
+
+#[derive(Clone)]
+pub struct FilteringProxy {
+    hosts: Arc<Vec<String>>,
+    recorder: Recorder,
+}
+
+impl FilteringProxy {
+    pub fn new(hosts: Vec<String>, recorder: Recorder) -> Self {
+        Self {
+            hosts: Arc::new(hosts),
+            recorder,
+        }
+    }
+
+    pub async fn serve(self, listener: TcpListener) {
+        loop {
+            let client = match listener.accept().await {
+                Ok((client, _)) => client,
+                Err(err) => {
+                    log::error!("Proxy could not accept a connection: {}", err);
+                    continue;
+                }
+            };
+            let proxy = self.clone();
+            tokio::spawn(async move {
+                if let Err(err) = proxy.handle(client).await {
+                    log::warn!("Proxy connection failed: {}", err);
+                }
+            });
+        }
+    }
+
+    async fn handle(&self, mut client: TcpStream) -> std::io::Result<()> {
+        let Some((head, rest)) = read_head(&mut client).await? else {
+            return Ok(());
+        };
+        let Some(request) = parse_head(&head) else {
+            return respond(&mut client, 400, "Bad Request").await;
+        };
+        if request.method.eq_ignore_ascii_case("CONNECT") {
+            self.tunnel(client, request, rest).await
+        } else {
+            self.forward(client, request, rest).await
+        }
+    }
+
+    /// Refuses the request if its host is not allowed. Returns whether it was refused.
+    async fn refuse(
+        &self,
+        client: &mut TcpStream,
+        request: &RequestHead,
+        host: &str,
+        port: u16,
+        path: Option<String>,
+    ) -> std::io::Result<bool> {
+        if host_allowed(&self.hosts, host) {
+            return Ok(false);
+        }
+        log::warn!("Proxy blocked {} {}:{}", request.method, host, port);
+        (self.recorder)(ProxyRecord {
+            method: request.method.clone(),
+            host: host.to_string(),
+            port,
+            path,
+            allowed: false,
+            status: 403,
+            bytes_sent: 0,
+            bytes_received: 0,
+        });
+        respond(client, 403, "Forbidden").await?;
+        Ok(true)
+    }
+
+    async fn tunnel(
+        &self,
+        mut client: TcpStream,
+        request: RequestHead,
+        rest: Vec<u8>,
+    ) -> std::io::Result<()> {
+        let Some((host, port)) = split_authority(&request.target, 443) else {
+            return respond(&mut client, 400, "Bad Request").await;
+        };
+        if self
+            .refuse(&mut client, &request, &host, port, None)
+            .await?
+        {
+            return Ok(());
+        }
+        let mut upstream = match TcpStream::connect((host.as_str(), port)).await {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                log::warn!("Proxy could not reach {}:{}: {}", host, port, err);
+                return respond(&mut client, 502, "Bad Gateway").await;
+            }
+        };
+        client
+            .write_all(b"HTTP/1.1 200 Connection Established\r\n\r\n")
+            .await?;
+        upstream.write_all(&rest).await?;
+        let (sent, received) = tokio::io::copy_bidirectional(&mut client, &mut upstream)
+            .await
+            .unwrap_or((0, 0));
+        (self.recorder)(ProxyRecord {
+            method: request.method,
+            host,
+            port,
+            path: None,
+            allowed: true,
+            status: 200,
+            bytes_sent: sent + rest.len() as u64,
+            bytes_received: received,
+        });
+        Ok(())
+    }
+
+    async fn forward(
+        &self,
+        mut client: TcpStream,
+        request: RequestHead,
+        rest: Vec<u8>,
+    ) -> std::io::Result<()> {
+        // plain HTTP requests to a proxy carry the absolute url
+        let target = request.target.as_str();
+        let Some(without_scheme) = target
+            .get(..7)
+            .filter(|scheme| scheme.eq_ignore_ascii_case("http://"))
+            .map(|_| &target[7..])
+        else {
+            return respond(&mut client, 400, "Bad Request").await;
+        };
+        let (authority, path) = match without_scheme.find('/') {
+            Some(index) => without_scheme.split_at(index),
+            None => (without_scheme, "/"),
+        };
+        let Some((host, port)) = split_authority(authority, 80) else {
+            return respond(&mut client, 400, "Bad Request").await;
+        };
+        let path = path.to_string();
+        if self
+            .refuse(&mut client, &request, &host, port, Some(path.clone()))
+            .await?
+        {
+            return Ok(());
+        }
+        let mut upstream = match TcpStream::connect((host.as_str(), port)).await {
+            Ok(upstream) => upstream,
+            Err(err) => {
+                log::warn!("Proxy could not reach {}:{}: {}", host, port, err);
+                return respond(&mut client, 502, "Bad Gateway").await;
+            }
+        };
+
+        // one request per connection keeps the accounting per request
+        let mut head = format!("{} {} {}\r\n", request.method, path, request.version);
+        for (name, value) in &request.headers {
+            if !HOP_BY_HOP.contains(&name.to_ascii_lowercase().as_str()) {
+                head.push_str(&format!("{}: {}\r\n", name, value));
+            }
+        }
+        head.push_str("Connection: close\r\n\r\n");
+        upstream.write_all(head.as_bytes()).await?;
+        upstream.write_all(&rest).await?;
+
+        let mut sent = 0u64;
+        let mut received = 0u64;
+        let mut first = Vec::new();
+        {
+            let (client_read, client_write) = client.split();
+            let (upstream_read, upstream_write) = upstream.split();
+            let mut ignored = Vec::new();
+            let upload = pipe(client_read, upstream_write, &mut sent, &mut ignored);
+            let download = pipe(upstream_read, client_write, &mut received, &mut first);
+            tokio::pin!(upload, download);
+            // the response ends the exchange, the webview may hold its side open for keep-alive
+            tokio::select! {
+                _ = &mut download => {}
+                _ = &mut upload => {
+                    let _ = (&mut download).await;
+                }
+            }
+        }
+        (self.recorder)(ProxyRecord {
+            method: request.method,
+            host,
+            port,
+            path: Some(path),
+            allowed: true,
+            status: response_status(&first).unwrap_or(0),
+            bytes_sent: (head.len() + rest.len()) as u64 + sent,
+            bytes_received: received,
+        });
+        Ok(())
+    }
+}
+
+/// Starts the proxy on a free loopback port, recording requests to the audit log.
+pub fn start(app: &AppHandle, hosts: Vec<String>) -> std::io::Result<SocketAddr> {
+    let listener = std::net::TcpListener::bind(("127.0.0.1", 0))?;
+    listener.set_nonblocking(true)?;
+    let addr = listener.local_addr()?;
+    let app_handle = app.clone();
+    let proxy = FilteringProxy::new(
+        hosts,
+        Arc::new(move |record: ProxyRecord| {
+            let kind = if record.allowed {
+                "network_request"
+            } else {
+                "network_blocked"
+            };
+            audit(
+                &app_handle,
+                kind,
+                serde_json::to_value(&record).unwrap_or_default(),
+            );
+        }),
+    );
+    tauri::async_runtime::spawn(async move {
+        match TcpListener::from_std(listener) {
+            Ok(listener) => proxy.serve(listener).await,
+            Err(err) => log::error!("Could not start the proxy: {}", err),
+        }
+    });
+    Ok(addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn hosts(patterns: &[&str]) -> Vec<String> {
+        patterns.iter().map(|pattern| pattern.to_string()).collect()
+    }
+
+    /// Starts a proxy on a loopback port, returning its address and what it recorded.
+    async fn start_proxy(allowed: &[&str]) -> (SocketAddr, Arc<Mutex<Vec<ProxyRecord>>>) {
+        let records = Arc::new(Mutex::new(Vec::new()));
+        let proxy = FilteringProxy::new(hosts(allowed), {
+            let records = records.clone();
+            Arc::new(move |record| records.lock().unwrap().push(record))
+        });
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(proxy.serve(listener));
+        (addr, records)
+    }
+
+    /// Sends `request` through the proxy and reads until it closes the connection.
+    async fn exchange(proxy: SocketAddr, request: &str) -> String {
+        let mut client = TcpStream::connect(proxy).await.unwrap();
+        client.write_all(request.as_bytes()).await.unwrap();
+        let mut response = Vec::new();
+        client.read_to_end(&mut response).await.unwrap();
+        String::from_utf8(response).unwrap()
+    }
+
+    /// The record is written once the exchange is over, shortly after the client sees the end.
+    async fn record(records: &Mutex<Vec<ProxyRecord>>) -> ProxyRecord {
+        for _ in 0..100 {
+            if let Some(record) = records.lock().unwrap().first() {
+                return record.clone();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("nothing was recorded");
+    }
+
+    #[test]
+    fn hosts_match_exactly_or_by_subdomain() {
+        let patterns = hosts(&["exam.example.com", "*.cdn.example.com", "Trailing.Dot."]);
+        assert!(host_allowed(&patterns, "exam.example.com"));
+        assert!(host_allowed(&patterns, "EXAM.example.com."));
+        assert!(host_allowed(&patterns, "img.cdn.example.com"));
+        assert!(host_allowed(&patterns, "a.b.cdn.example.com"));
+        assert!(host_allowed(&patterns, "trailing.dot"));
+        assert!(!host_allowed(&patterns, "cdn.example.com"));
+        assert!(!host_allowed(&patterns, "example.com"));
+        assert!(!host_allowed(&patterns, "evilcdn.example.com"));
+        assert!(!host_allowed(&patterns, "exam.example.com.evil.test"));
+        assert!(!host_allowed(&[], "exam.example.com"));
+    }
+
+    #[test]
+    fn authorities_split_into_host_and_port() {
+        let split = |authority| split_authority(authority, 443);
+        assert_eq!(split("example.com"), Some(("example.com".into(), 443)));
+        assert_eq!(
+            split("example.com:8443"),
+            Some(("example.com".into(), 8443))
+        );
+        assert_eq!(split("example.com.:80"), Some(("example.com.".into(), 80)));
+        assert_eq!(split("[::1]"), Some(("::1".into(), 443)));
+        assert_eq!(split("[::1]:8080"), Some(("::1".into(), 8080)));
+        assert_eq!(split("[2001:db8::1]:80"), Some(("2001:db8::1".into(), 80)));
+        for bad in [
+            "",
+            ":80",
+            "[]:80",
+            "[::1",
+            "[::1]80",
+            "::1",
+            "2001:db8::1",
+            "host:port",
+        ] {
+            assert_eq!(split(bad), None, "{:?}", bad);
+        }
+    }
+
+    #[tokio::test]
+    async fn tunnels_to_other_hosts_are_refused() {
+        let (proxy, records) = start_proxy(&["exam.example.com"]).await;
+        let response = exchange(
+            proxy,
+            "CONNECT evil.example.net:443 HTTP/1.1\r\nHost: evil.example.net:443\r\n\r\n",
+        )
+        .await;
+        assert!(response.starts_with("HTTP/1.1 403"), "{}", response);
+
+        let record = record(&records).await;
+        assert_eq!(record.method, "CONNECT");
+        assert_eq!(record.host, "evil.example.net");
+        assert_eq!(record.port, 443);
+        assert_eq!(record.path, None);
+        assert!(!record.allowed);
+        assert_eq!(record.status, 403);
+    }
+
+    #[tokio::test]
+    async fn forwarded_requests_are_accounted() {
+        const RESPONSE: &str = "HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello";
+        let upstream = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = upstream.local_addr().unwrap().port();
+        let upstream = tokio::spawn(async move {
+            let (mut stream, _) = upstream.accept().await.unwrap();
+            let (head, _) = read_head(&mut stream).await.unwrap().unwrap();
+            stream.write_all(RESPONSE.as_bytes()).await.unwrap();
+            head
+        });
+
+        let (proxy, records) = start_proxy(&["127.0.0.1"]).await;
+        let request = format!(
+            "GET http://127.0.0.1:{port}/exam?page=2 HTTP/1.1\r\nHost: 127.0.0.1:{port}\r\n\
+             Proxy-Connection: keep-alive\r\n\r\n"
+        );
+        assert_eq!(exchange(proxy, &request).await, RESPONSE);
+
+        let head = upstream.await.unwrap();
+        assert!(
+            head.starts_with("GET /exam?page=2 HTTP/1.1\r\n"),
+            "{}",
+            head
+        );
+        assert!(!head.to_ascii_lowercase().contains("proxy-connection"));
+        assert!(head.contains("Connection: close\r\n"));
+
+        let record = record(&records).await;
+        assert_eq!(record.method, "GET");
+        assert_eq!(record.host, "127.0.0.1");
+        assert_eq!(record.port, port);
+        assert_eq!(record.path.as_deref(), Some("/exam?page=2"));
+        assert!(record.allowed);
+        assert_eq!(record.status, 200);
+        assert_eq!(record.bytes_sent, head.len() as u64);
+        assert_eq!(record.bytes_received, RESPONSE.len() as u64);
+    }
+}
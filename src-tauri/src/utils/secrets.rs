@@ -0,0 +1,91 @@
+//! Keys and salts set per deployment.
+//!
+//! Each secret is built into the app from its variable when the app is built. Outside kiosk
+//! mode the same variable is also read at run time, when nothing is built in, which is what
+//! development and practice runs use. Kiosk mode never reads it, since whoever starts the app
+//! would otherwise pick the key. There are no fallback values: a secret that is not
+//! provisioned is an error, and kiosk mode does not start without the ones in [`KIOSK`].
+
+use crate::utils::mode::AppMode;
+use std::sync::OnceLock;
+
+/// Whether secrets may come from the environment, set once the mode is known.
+static FROM_ENVIRONMENT: OnceLock<bool> = OnceLock::new();
+
+#[derive(Debug, Clone, Copy)]
+pub struct Secret {
+    /// What the secret is for, used in messages
+    pub name: &'static str,
+    pub env: &'static str,
+    /// `option_env!` of `env`
+    pub built_in: Option<&'static str>,
+}
+
+pub const FINGERPRINT_SALT: Secret = Secret {
+    name: "fingerprint salt",
+    env: "SECURE_BROWSER_FINGERPRINT_SALT",
+    built_in: option_env!("SECURE_BROWSER_FINGERPRINT_SALT"),
+};
+
+pub const AUDIT_KEY: Secret = Secret {
+    name: "audit key",
+    env: "SECURE_BROWSER_AUDIT_KEY",
+    built_in: option_env!("SECURE_BROWSER_AUDIT_KEY"),
+};
+
+pub const STORE_KEY: Secret = Secret {
+    name: "answer store key",
+    env: "SECURE_BROWSER_STORE_KEY",
+    built_in: option_env!("SECURE_BROWSER_STORE_KEY"),
+};
+
+/// Optional, without it locked sessions are only unlocked from the console
+pub const UNLOCK_SECRET: Secret = Secret {
+    name: "unlock secret",
+    env: shared::unlock::SECRET_ENV,
+    built_in: option_env!("SECURE_BROWSER_UNLOCK_SECRET"),
+};
+
+/// Needed once the policy turns proctoring on, see `utils::proctor`
+pub const PROCTOR_KEY: Secret = Secret {
+    name: "proctor key",
+    env: shared::proctor::KEY_ENV,
+    built_in: option_env!("SECURE_BROWSER_PROCTOR_KEY"),
+};
+
+/// Secrets kiosk mode cannot run without
+pub const KIOSK: &[Secret] = &[FINGERPRINT_SALT, AUDIT_KEY, STORE_KEY];
+
+/// Lets secrets come from the environment for this run, never in kiosk mode.
+pub fn allow_environment(mode: AppMode) {
+    let _ = FROM_ENVIRONMENT.set(!mode.is_kiosk());
+}
+
+impl Secret {
+    pub fn load(&self) -> Result<Vec<u8>, String> {
+        if let Some(value) = self.built_in.filter(|value| !value.is_empty()) {
+            return Ok(value.as_bytes().to_vec());
+        }
+        if FROM_ENVIRONMENT.get().copied().unwrap_or(false) {
+            if let Ok(value) = std::env::var(self.env) {
+                if !value.is_empty() {
+                    return Ok(value.into_bytes());
+                }
+            }
+        }
+        Err(format!(
+            "no {} is provisioned, build the app with {} set",
+            self.name, self.env
+        ))
+    }
+}
+
+/// Checks that the secrets kiosk mode needs are built in.
+pub fn check(mode: AppMode) -> Result<(), String> {
+    if !mode.is_kiosk() {
+        return Ok(());
+    }
+    KIOSK
+        .iter()
+        .try_for_each(|secret| secret.load().map(|_| ()))
+}
@@ -0,0 +1,579 @@
+use crate::utils::audit::audit;
+use crate::utils::events::{emit, AppEvent, LockNotice, TimeExtension};
+use crate::utils::mode::AppMode;
+use crate::utils::policy::Policy;
+use crate::utils::types::{DetectorStatus, SessionState, SessionStatus, Violation, ViolationKind};
+use crate::utils::unlock::{unlock_secret, LockEvent};
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::OsRng;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use sysinfo::{Pid, ProcessesToUpdate, System};
+use tauri::{AppHandle, Manager};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Directory under the app data dir the sessions are saved in, next to `answers`
+pub const SESSION_DIR: &str = "sessions";
+
+/// Latest detector results, updated by the scheduled tasks.
+pub struct DetectorState(pub Mutex<DetectorStatus>);
+
+/// The exam session and every violation recorded during it.
+pub struct SessionStore(pub Mutex<Session>);
+
+pub struct Session {
+    pub state: SessionState,
+    pub violations: Vec<Violation>,
+    /// Set while the session is locked
+    pub lock: Option<LockEvent>,
+    next_violation_id: u64,
+    next_lock_id: u64,
+    saved: Option<SaveFile>,
+}
+
+/// Where the session is written after every change, see [`Session::attach`].
+struct SaveFile {
+    path: PathBuf,
+    key: Vec<u8>,
+}
+
+/// What a relaunch needs to carry on where the killed run stopped.
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    state: SessionState,
+    violations: Vec<Violation>,
+    lock: Option<LockEvent>,
+    next_violation_id: u64,
+    next_lock_id: u64,
+}
+
+/// The snapshot as stored on disk. The MAC, under the audit key, covers the snapshot's JSON,
+/// so the file cannot be edited to lift a lock.
+#[derive(Serialize, Deserialize)]
+struct SignedSnapshot {
+    snapshot: String,
+    mac: String,
+}
+
+fn snapshot_mac(key: &[u8], snapshot: &str) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(key).expect("hmac accepts keys of any length");
+    mac.update(snapshot.as_bytes());
+    mac
+}
+
+fn read_snapshot(path: &Path, key: &[u8]) -> Result<Snapshot, String> {
+    let json = std::fs::read_to_string(path)
+        .map_err(|err| format!("could not read {}: {}", path.display(), err))?;
+    let signed: SignedSnapshot =
+        serde_json::from_str(&json).map_err(|err| format!("invalid saved session: {}", err))?;
+    let expected =
+        hex::decode(&signed.mac).map_err(|err| format!("saved session mac is not hex: {}", err))?;
+    snapshot_mac(key, &signed.snapshot)
+        .verify_slice(&expected)
+        .map_err(|_| "saved session mac does not match".to_string())?;
+    serde_json::from_str(&signed.snapshot).map_err(|err| format!("invalid saved session: {}", err))
+}
+
+/// The file session `id` is saved in.
+pub fn saved_path(data_dir: &Path, id: &str) -> PathBuf {
+    data_dir.join(SESSION_DIR).join(format!("{}.json", id))
+}
+
+/// Names the run using a saved session, next to the saved file.
+fn marker_path(saved: &Path) -> PathBuf {
+    saved.with_extension("pid")
+}
+
+/// `<pid> <start time>` of a running process. The start time tells the process apart from a
+/// later one that got the same pid.
+fn process_marker(pid: u32) -> Option<String> {
+    let pid = Pid::from_u32(pid);
+    let mut system = System::new();
+    system.refresh_processes(ProcessesToUpdate::Some(&[pid]), false);
+    let process = system.process(pid)?;
+    Some(format!("{} {}", pid.as_u32(), process.start_time()))
+}
+
+/// The pid of another run still using the session, `None` when the marker is missing, ours,
+/// or left behind by a run that was killed.
+fn running_holder(marker: &Path) -> Option<u32> {
+    let text = std::fs::read_to_string(marker).ok()?;
+    let pid: u32 = text.split_whitespace().next()?.parse().ok()?;
+    if pid == std::process::id() {
+        return None;
+    }
+    (process_marker(pid)? == text.trim()).then_some(pid)
+}
+
+fn new_session_id() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng.fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+/// Checks an id passed on the command line, it names files so it must look like ours.
+pub fn validate_session_id(id: &str) -> Result<String, String> {
+    let id = id.trim().to_ascii_lowercase();
+    if id.len() != 32 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("`{}` is not a session id", id));
+    }
+    Ok(id)
+}
+
+impl Session {
+    pub fn new(mode: AppMode, seat: Option<String>) -> Self {
+        Self {
+            state: SessionState {
+                id: new_session_id(),
+                status: SessionStatus::Starting,
+                mode,
+                seat,
+                started_at: chrono::Utc::now().timestamp_millis(),
+                violation_count: 0,
+                resumed: false,
+                extra_minutes: 0,
+            },
+            violations: vec![],
+            lock: None,
+            next_violation_id: 1,
+            next_lock_id: 1,
+            saved: None,
+        }
+    }
+
+    /// Continues the session `id` after a relaunch, so its answer store is picked up again.
+    /// Its lock and violations come back with [`Session::attach`].
+    pub fn resume(mode: AppMode, seat: Option<String>, id: String) -> Self {
+        let mut session = Self::new(mode, seat);
+        session.state.id = id;
+        session.state.resumed = true;
+        session
+    }
+
+    /// Saves the session in `path` from now on. A resumed session first takes back the lock,
+    /// violations and counters the earlier run saved there, one that was never saved, whose
+    /// file does not verify, or that another run is still using is refused.
+    pub fn attach(&mut self, path: &Path, key: Vec<u8>) -> Result<(), String> {
+        let marker = marker_path(path);
+        if self.state.resumed {
+            if let Some(pid) = running_holder(&marker) {
+                return Err(format!(
+                    "session {} is in use by process {}",
+                    self.state.id, pid
+                ));
+            }
+            let snapshot = read_snapshot(path, &key)?;
+            if snapshot.state.id != self.state.id {
+                return Err(format!(
+                    "{} holds session {}",
+                    path.display(),
+                    snapshot.state.id
+                ));
+            }
+            self.state = SessionState {
+                mode: self.state.mode,
+                seat: self.state.seat.clone(),
+                resumed: true,
+                ..snapshot.state
+            };
+            self.violations = snapshot.violations;
+            self.lock = snapshot.lock;
+            self.next_violation_id = snapshot.next_violation_id;
+            self.next_lock_id = snapshot.next_lock_id;
+        }
+        self.saved = Some(SaveFile {
+            path: path.to_path_buf(),
+            key,
+        });
+        self.save()?;
+        let ours = process_marker(std::process::id()).ok_or("could not read this process")?;
+        std::fs::write(&marker, ours)
+            .map_err(|err| format!("could not write {}: {}", marker.display(), err))
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let Some(saved) = &self.saved else {
+            return Ok(());
+        };
+        let snapshot = serde_json::to_string(&Snapshot {
+            state: self.state.clone(),
+            violations: self.violations.clone(),
+            lock: self.lock.clone(),
+            next_violation_id: self.next_violation_id,
+            next_lock_id: self.next_lock_id,
+        })
+        .map_err(|err| err.to_string())?;
+        let mac = hex::encode(snapshot_mac(&saved.key, &snapshot).finalize().into_bytes());
+        let json = serde_json::to_string(&SignedSnapshot { snapshot, mac })
+            .map_err(|err| err.to_string())?;
+        if let Some(parent) = saved.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        // a kill halfway through the write must not leave a broken file behind
+        let partial = saved.path.with_extension("json.partial");
+        std::fs::write(&partial, json)
+            .and_then(|_| std::fs::rename(&partial, &saved.path))
+            .map_err(|err| format!("could not write {}: {}", saved.path.display(), err))
+    }
+
+    /// Saves the session after a change, a failure is logged and the session goes on.
+    pub fn persist(&self) {
+        if let Err(err) = self.save() {
+            log::error!("Could not save the session: {}", err);
+        }
+    }
+
+    pub fn record_violation(
+        &mut self,
+        kind: ViolationKind,
+        detail: impl Into<String>,
+    ) -> Violation {
+        let violation = Violation {
+            id: self.next_violation_id,
+            kind,
+            detail: detail.into(),
+            occurred_at: chrono::Utc::now().timestamp_millis(),
+        };
+        self.next_violation_id += 1;
+        self.violations.push(violation.clone());
+        self.state.violation_count = self.violations.len();
+        log::info!("Violation recorded: {:?}", violation);
+        self.persist();
+        violation
+    }
+
+    pub fn set_status(&mut self, status: SessionStatus) {
+        log::info!("Session status: {:?} -> {:?}", self.state.status, status);
+        self.state.status = status;
+        self.persist();
+    }
+
+    /// Starts a lock event, `None` when the session is already locked.
+    pub fn start_lock(
+        &mut self,
+        reason: String,
+        violation_id: Option<u64>,
+        with_challenge: bool,
+    ) -> Option<LockNotice> {
+        if self.lock.is_some() {
+            return None;
+        }
+        let lock_id = self.next_lock_id;
+        self.next_lock_id += 1;
+        let challenge = with_challenge.then(|| {
+            let mut nonce = [0u8; 16];
+            OsRng.fill_bytes(&mut nonce);
+            shared::unlock::challenge(&self.state.id, lock_id, violation_id, &nonce)
+        });
+        let notice = LockNotice {
+            lock_id,
+            reason,
+            violation_id,
+            challenge,
+        };
+        self.lock = Some(LockEvent {
+            notice: notice.clone(),
+            attempts: 0,
+        });
+        self.persist();
+        Some(notice)
+    }
+
+    /// Lifts the lock, `None` when there is none or the session has ended.
+    pub fn end_lock(&mut self) -> Option<LockEvent> {
+        // a terminated session stays ended
+        if self.state.status == SessionStatus::Ended {
+            return None;
+        }
+        let event = self.lock.take();
+        self.persist();
+        event
+    }
+
+    /// Adds proctor time, returns the total.
+    pub fn extend_time(&mut self, minutes: u32) -> u32 {
+        self.state.extra_minutes = self.state.extra_minutes.saturating_add(minutes);
+        self.persist();
+        self.state.extra_minutes
+    }
+}
+
+/// Starts the exam once the app is set up. A resumed session that was locked or ended stays
+/// that way, and a restored lock is shown again.
+pub fn begin(app: &AppHandle) {
+    let (status, notice) = {
+        let store = app.state::<SessionStore>();
+        let Ok(session) = store.0.lock() else {
+            log::error!("Could not lock session to start it");
+            return;
+        };
+        let notice = session.lock.as_ref().map(|event| event.notice.clone());
+        (session.state.status, notice)
+    };
+    match (status, notice) {
+        (SessionStatus::Ended, _) => set_status(app, SessionStatus::Ended),
+        (_, Some(notice)) => {
+            audit(app, "session_lock_restored", serde_json::json!(notice));
+            set_status(app, SessionStatus::Locked);
+            emit(app, AppEvent::SessionLocked(notice));
+        }
+        (_, None) => set_status(app, SessionStatus::Active),
+    }
+}
+
+/// Records a violation in the managed session and tells the front-end about it.
+pub fn record_violation(
+    app: &AppHandle,
+    kind: ViolationKind,
+    detail: impl Into<String>,
+) -> Option<Violation> {
+    let violation = {
+        let store = app.state::<SessionStore>();
+        let mut session = store.0.lock().ok()?;
+        session.record_violation(kind, detail)
+    };
+    audit(app, "violation", serde_json::json!(violation));
+    emit(app, AppEvent::ViolationRecorded(violation.clone()));
+    if app
+        .state::<Policy>()
+        .unlock
+        .lock_on
+        .contains(&violation.kind)
+    {
+        lock(app, violation.detail.clone(), Some(violation.id));
+    }
+    Some(violation)
+}
+
+/// Moves the managed session to `status` and tells the front-end about it.
+pub fn set_status(app: &AppHandle, status: SessionStatus) {
+    let state = {
+        let store = app.state::<SessionStore>();
+        let Ok(mut session) = store.0.lock() else {
+            log::error!("Could not lock session to set status {:?}", status);
+            return;
+        };
+        session.set_status(status);
+        session.state.clone()
+    };
+    audit(
+        app,
+        "session_status",
+        serde_json::json!({ "status": status }),
+    );
+    emit(app, AppEvent::SessionStateChanged(state));
+}
+
+/// Locks the session, the front-end blocks the exam until [`unlock`].
+pub fn lock(app: &AppHandle, reason: impl Into<String>, violation_id: Option<u64>) {
+    let with_challenge = unlock_secret().is_some();
+    let notice = {
+        let store = app.state::<SessionStore>();
+        let Ok(mut session) = store.0.lock() else {
+            log::error!("Could not lock session to lock it");
+            return;
+        };
+        session.start_lock(reason.into(), violation_id, with_challenge)
+    };
+    let Some(notice) = notice else {
+        log::info!("Session is already locked");
+        return;
+    };
+    audit(app, "session_locked", serde_json::json!(notice));
+    set_status(app, SessionStatus::Locked);
+    emit(app, AppEvent::SessionLocked(notice));
+}
+
+/// Ends the lock event. `by` is how, e.g. `unlock_code`, and `proctor` who gave the code.
+pub fn unlock(app: &AppHandle, by: &str, proctor: Option<&str>) {
+    let event = {
+        let store = app.state::<SessionStore>();
+        let Ok(mut session) = store.0.lock() else {
+            log::error!("Could not lock session to unlock it");
+            return;
+        };
+        session.end_lock()
+    };
+    let Some(event) = event else {
+        log::info!("Session is not locked, nothing to unlock");
+        return;
+    };
+    audit(
+        app,
+        "session_unlocked",
+        serde_json::json!({
+            "lock_id": event.notice.lock_id,
+            "violation_id": event.notice.violation_id,
+            "by": by,
+            "proctor": proctor,
+        }),
+    );
+    set_status(app, SessionStatus::Active);
+    emit(app, AppEvent::SessionUnlocked);
+}
+
+/// Adds proctor time to the session and tells the front-end about it.
+pub fn extend_time(app: &AppHandle, minutes: u32) {
+    let total_minutes = {
+        let store = app.state::<SessionStore>();
+        let Ok(mut session) = store.0.lock() else {
+            log::error!("Could not lock session to extend the time");
+            return;
+        };
+        session.extend_time(minutes)
+    };
+    audit(
+        app,
+        "time_extended",
+        serde_json::json!({ "minutes": minutes, "total_minutes": total_minutes }),
+    );
+    emit(
+        app,
+        AppEvent::TimeExtended(TimeExtension {
+            minutes,
+            total_minutes,
+        }),
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"session-test-key";
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("session-{}-{}.json", name, std::process::id()))
+    }
+
+    /// Removes a saved session and the marker of the run that saved it.
+    fn remove(path: &Path) {
+        std::fs::remove_file(path).unwrap();
+        let _ = std::fs::remove_file(marker_path(path));
+    }
+
+    fn resumed(session: &Session, path: &Path) -> Result<Session, String> {
+        let mut resumed = Session::resume(AppMode::Kiosk, None, session.state.id.clone());
+        resumed.attach(path, KEY.to_vec())?;
+        Ok(resumed)
+    }
+
+    #[test]
+    fn a_locked_session_is_still_locked_after_resume() {
+        let path = temp_path("locked");
+        let mut session = Session::new(AppMode::Kiosk, Some("A1".into()));
+        session.attach(&path, KEY.to_vec()).unwrap();
+        session.record_violation(ViolationKind::FocusLost, "left the window");
+        let violation = session.record_violation(ViolationKind::Clipboard, "copied");
+        let notice = session
+            .start_lock("copied".into(), Some(violation.id), true)
+            .unwrap();
+        session.set_status(SessionStatus::Locked);
+        session.lock.as_mut().unwrap().attempts = 2;
+        session.persist();
+
+        let mut resumed = resumed(&session, &path).unwrap();
+        remove(&path);
+        assert!(resumed.state.resumed);
+        assert_eq!(resumed.state.status, SessionStatus::Locked);
+        let event = resumed.lock.as_ref().expect("the lock survives");
+        assert_eq!(event.notice.lock_id, notice.lock_id);
+        assert_eq!(event.notice.challenge, notice.challenge);
+        assert_eq!(event.attempts, 2);
+        assert_eq!(resumed.violations.len(), 2);
+        assert_eq!(resumed.state.violation_count, 2);
+        // ids go on from the killed run, the console has acknowledged the old ones
+        assert_eq!(
+            resumed
+                .record_violation(ViolationKind::FocusLost, "again")
+                .id,
+            3
+        );
+        assert!(resumed.start_lock("again".into(), None, false).is_none());
+        assert_eq!(resumed.end_lock().unwrap().notice.lock_id, 1);
+        assert_eq!(
+            resumed
+                .start_lock("later".into(), None, false)
+                .unwrap()
+                .lock_id,
+            2
+        );
+    }
+
+    #[test]
+    fn an_ended_session_stays_ended() {
+        let path = temp_path("ended");
+        let mut session = Session::new(AppMode::Kiosk, None);
+        session.attach(&path, KEY.to_vec()).unwrap();
+        session.start_lock("terminated".into(), None, false);
+        session.set_status(SessionStatus::Ended);
+
+        let mut resumed = resumed(&session, &path).unwrap();
+        remove(&path);
+        assert_eq!(resumed.state.status, SessionStatus::Ended);
+        assert!(resumed.end_lock().is_none());
+    }
+
+    #[test]
+    fn a_session_that_was_not_saved_or_was_edited_is_not_resumed() {
+        let path = temp_path("refused");
+        let mut session = Session::new(AppMode::Kiosk, None);
+        let err = resumed(&session, &path).err().unwrap();
+        assert!(err.contains("could not read"), "{}", err);
+
+        session.attach(&path, KEY.to_vec()).unwrap();
+        session.start_lock("locked".into(), None, false);
+        let saved = std::fs::read_to_string(&path).unwrap();
+        let edited = saved.replace("\\\"lock\\\":{", "\\\"lock\\\":null,\\\"x\\\":{");
+        assert_ne!(saved, edited);
+        std::fs::write(&path, edited).unwrap();
+        assert_eq!(
+            resumed(&session, &path).err().as_deref(),
+            Some("saved session mac does not match")
+        );
+
+        // nor is another session's file
+        std::fs::write(&path, saved).unwrap();
+        let mut other = Session::resume(AppMode::Kiosk, None, "0".repeat(32));
+        let err = other.attach(&path, KEY.to_vec()).unwrap_err();
+        remove(&path);
+        assert!(err.contains("holds session"), "{}", err);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_session_another_run_is_using_is_not_resumed() {
+        let path = temp_path("in-use");
+        let mut session = Session::new(AppMode::Kiosk, None);
+        session.attach(&path, KEY.to_vec()).unwrap();
+        // the marker this run left does not count
+        assert!(resumed(&session, &path).is_ok());
+
+        let mut other = std::process::Command::new("sleep")
+            .arg("30")
+            .spawn()
+            .unwrap();
+        let marker = marker_path(&path);
+        std::fs::write(&marker, process_marker(other.id()).unwrap()).unwrap();
+        let err = resumed(&session, &path).err().unwrap();
+        other.kill().unwrap();
+        other.wait().unwrap();
+        assert!(err.contains("is in use by process"), "{}", err);
+
+        // a run that was killed leaves its marker behind, it is taken over
+        assert!(resumed(&session, &path).is_ok());
+        assert_eq!(
+            std::fs::read_to_string(&marker).unwrap(),
+            process_marker(std::process::id()).unwrap()
+        );
+        // as is one whose pid went to another process since
+        let pid = std::process::id();
+        std::fs::write(&marker, format!("{} 1", pid + 1)).unwrap();
+        assert!(resumed(&session, &path).is_ok());
+        remove(&path);
+    }
+}
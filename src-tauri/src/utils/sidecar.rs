@@ -0,0 +1,489 @@
+//! Supervision of the mapper sidecar and the app's end of the protocol in `shared::ipc`.
+//!
+//! Without the mapper the candidate gets Alt+Tab and friends back, so it is restarted when
+//! it exits or stops sending heartbeats. Restarts back off and are limited to a number per
+//! time window; once that budget is spent the failure is recorded as a violation. The app
+//! sends the mapper heartbeats in turn, so it can act as the app's watchdog. Supervision
+//! goes through [`SidecarHost`], so it can be tested against the `dummy_sidecar` binary.
+
+use crate::utils::audit::audit;
+use crate::utils::mode::AppMode;
+use crate::utils::session::record_violation;
+use crate::utils::types::ViolationKind;
+use serde::Deserialize;
+use shared::ipc::{decode, encode, HostMessage, MapperMessage};
+use shared::watchdog::WatchdogConfig;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager};
+use tauri_plugin_shell::process::{CommandChild, CommandEvent};
+use tauri_plugin_shell::ShellExt;
+use tokio::sync::mpsc;
+
+const SIDECAR_NAME: &str = "mapper";
+/// How often a running sidecar is checked for a missed heartbeat and sent one of ours
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+/// How long the mapper gets to release the keyboard and exit after a shutdown request
+const SHUTDOWN_GRACE: Duration = Duration::from_secs(2);
+const SHUTDOWN_POLL: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SidecarPolicy {
+    /// The mapper is restarted when no message arrived for this long
+    pub heartbeat_timeout_secs: u64,
+    /// Restarts allowed inside `restart_window_secs` before giving up
+    pub max_restarts: usize,
+    pub restart_window_secs: u64,
+    pub max_backoff_secs: u64,
+}
+
+impl Default for SidecarPolicy {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout_secs: 10,
+            max_restarts: 5,
+            restart_window_secs: 300,
+            max_backoff_secs: 30,
+        }
+    }
+}
+
+/// Counts recent restarts and spaces them out.
+#[derive(Debug)]
+pub struct RestartBudget {
+    max_restarts: usize,
+    window: Duration,
+    max_backoff: Duration,
+    recent: VecDeque<Instant>,
+}
+
+impl RestartBudget {
+    pub fn new(policy: &SidecarPolicy) -> Self {
+        Self {
+            max_restarts: policy.max_restarts,
+            window: Duration::from_secs(policy.restart_window_secs),
+            max_backoff: Duration::from_secs(policy.max_backoff_secs),
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Delay before the next restart, `None` once the budget for the window is spent.
+    pub fn next_restart(&mut self, now: Instant) -> Option<Duration> {
+        while let Some(oldest) = self.recent.front() {
+            if now.duration_since(*oldest) > self.window {
+                self.recent.pop_front();
+            } else {
+                break;
+            }
+        }
+        if self.recent.len() >= self.max_restarts {
+            return None;
+        }
+        self.recent.push_back(now);
+        Some(crate::utils::sync::backoff(
+            self.recent.len() as u32,
+            self.max_backoff,
+        ))
+    }
+}
+
+#[derive(Default)]
+pub struct SidecarProcess {
+    child: Option<CommandChild>,
+    /// Set on exit so the supervisor does not restart what is being shut down
+    stopping: bool,
+    /// Set once the running child has terminated
+    terminated: Arc<AtomicBool>,
+}
+
+pub struct SidecarState(pub Mutex<SidecarProcess>);
+
+/// The configuration line written to the mapper's stdin right after it starts.
+pub fn configure_line(
+    rules: &[String],
+    mode: AppMode,
+    watchdog: Option<&WatchdogConfig>,
+) -> String {
+    let mode = serde_json::to_value(mode)
+        .ok()
+        .and_then(|mode| mode.as_str().map(str::to_string))
+        .unwrap_or_default();
+    let message = HostMessage::Configure {
+        rules: rules.to_vec(),
+        mode,
+        watchdog: watchdog.cloned(),
+    };
+    format!("{}\n", encode(&message))
+}
+
+pub fn heartbeat_line() -> String {
+    let at = chrono::Utc::now().timestamp_millis();
+    format!("{}\n", encode(&HostMessage::Heartbeat { at }))
+}
+
+pub fn shutdown_line() -> String {
+    format!("{}\n", encode(&HostMessage::Shutdown))
+}
+
+/// Output and end of one sidecar run.
+#[derive(Debug)]
+pub enum SidecarEvent {
+    Stdout(Vec<u8>),
+    Stderr(Vec<u8>),
+    Terminated {
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+}
+
+/// What supervision needs from the outside: starting the process, talking to it and
+/// reporting on it. The app implements it over the shell plugin, the tests over a dummy
+/// sidecar.
+pub trait SidecarHost: Send + Sync {
+    /// Starts the sidecar and keeps its handle.
+    fn spawn(&self) -> Result<mpsc::Receiver<SidecarEvent>, String>;
+    /// Writes to the running sidecar's stdin.
+    fn write(&self, bytes: &[u8]) -> Result<(), String>;
+    /// Kills the running sidecar, if any.
+    fn kill(&self);
+    /// Whether the app is exiting, nothing is restarted then.
+    fn stopping(&self) -> bool;
+    fn message(&self, message: MapperMessage);
+    /// The sidecar stopped on its own, `detail` says why.
+    fn exited(&self, detail: serde_json::Value);
+    fn restarted(&self);
+    /// The restart budget is spent.
+    fn gave_up(&self, reason: String);
+}
+
+/// Handles a protocol message from the mapper.
+pub fn handle_message(app: &AppHandle, message: MapperMessage) {
+    match message {
+        MapperMessage::Ready {
+            backend,
+            rules,
+            mode,
+        } => {
+            log::info!(
+                "[Sidecar] Keyboard captured with {} ({} rules)",
+                backend,
+                rules
+            );
+            audit(
+                app,
+                "mapper_ready",
+                serde_json::json!({ "backend": backend, "rules": rules, "mode": mode }),
+            );
+        }
+        MapperMessage::Suppressed { key, rule, at } => {
+            audit(
+                app,
+                "key_suppressed",
+                serde_json::json!({ "key": key, "rule": rule, "at": at }),
+            );
+        }
+        MapperMessage::Heartbeat { at } => {
+            log::debug!("[Sidecar] Heartbeat at {}", at);
+        }
+        MapperMessage::Error { message } => {
+            log::error!("[Sidecar] {}", message);
+            audit(
+                app,
+                "mapper_error",
+                serde_json::json!({ "message": message }),
+            );
+        }
+    }
+}
+
+/// The mapper bundled with the app, run through the shell plugin.
+pub struct AppSidecar(pub AppHandle);
+
+impl SidecarHost for AppSidecar {
+    fn spawn(&self) -> Result<mpsc::Receiver<SidecarEvent>, String> {
+        let (mut events, child) = self
+            .0
+            .shell()
+            .sidecar(SIDECAR_NAME)
+            .and_then(|command| command.spawn())
+            .map_err(|err| err.to_string())?;
+        let terminated = Arc::new(AtomicBool::new(false));
+        match self.0.state::<SidecarState>().0.lock() {
+            Ok(mut process) => {
+                process.child = Some(child);
+                process.terminated = terminated.clone();
+            }
+            Err(err) => log::error!("Could not store the sidecar handle: {}", err),
+        }
+        let (sender, receiver) = mpsc::channel(64);
+        tauri::async_runtime::spawn(async move {
+            while let Some(event) = events.recv().await {
+                let event = match event {
+                    CommandEvent::Stdout(line) => SidecarEvent::Stdout(line),
+                    CommandEvent::Stderr(line) => SidecarEvent::Stderr(line),
+                    CommandEvent::Terminated(payload) => {
+                        terminated.store(true, Ordering::SeqCst);
+                        SidecarEvent::Terminated {
+                            code: payload.code,
+                            signal: payload.signal,
+                        }
+                    }
+                    CommandEvent::Error(err) => {
+                        log::error!("[Sidecar error] {}", err);
+                        continue;
+                    }
+                    _ => continue,
+                };
+                if sender.send(event).await.is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(receiver)
+    }
+
+    fn write(&self, bytes: &[u8]) -> Result<(), String> {
+        let state = self.0.state::<SidecarState>();
+        let mut process = state.0.lock().map_err(|err| err.to_string())?;
+        match process.child.as_mut() {
+            Some(child) => child.write(bytes).map_err(|err| err.to_string()),
+            None => Err("the sidecar is not running".into()),
+        }
+    }
+
+    fn kill(&self) {
+        let child = match self.0.state::<SidecarState>().0.lock() {
+            Ok(mut process) => process.child.take(),
+            Err(_) => None,
+        };
+        if let Some(child) = child {
+            if let Err(err) = child.kill() {
+                log::error!("Could not kill the sidecar: {}", err);
+            }
+        }
+    }
+
+    fn stopping(&self) -> bool {
+        self.0
+            .state::<SidecarState>()
+            .0
+            .lock()
+            .map(|process| process.stopping)
+            .unwrap_or(true)
+    }
+
+    fn message(&self, message: MapperMessage) {
+        handle_message(&self.0, message);
+    }
+
+    fn exited(&self, detail: serde_json::Value) {
+        if let Ok(mut process) = self.0.state::<SidecarState>().0.lock() {
+            process.child = None;
+        }
+        log::error!("[Sidecar] Stopped: {}", detail);
+        audit(&self.0, "mapper_exited", detail);
+    }
+
+    fn restarted(&self) {
+        audit(&self.0, "mapper_restarted", serde_json::json!({}));
+    }
+
+    fn gave_up(&self, reason: String) {
+        record_violation(&self.0, ViolationKind::KeyboardGuardLost, reason);
+    }
+}
+
+/// Why a sidecar run ended.
+enum Exit {
+    Terminated {
+        code: Option<i32>,
+        signal: Option<i32>,
+    },
+    HeartbeatTimeout,
+    SpawnFailed(String),
+}
+
+impl Exit {
+    fn describe(&self) -> serde_json::Value {
+        match self {
+            Exit::Terminated { code, signal } => {
+                serde_json::json!({ "reason": "terminated", "code": code, "signal": signal })
+            }
+            Exit::HeartbeatTimeout => serde_json::json!({ "reason": "heartbeat_timeout" }),
+            Exit::SpawnFailed(err) => serde_json::json!({ "reason": "spawn_failed", "error": err }),
+        }
+    }
+}
+
+/// Starts the sidecar once and follows it until it exits or goes quiet.
+async fn run_once<H: SidecarHost>(host: &H, configure: &str, timeout: Duration) -> Exit {
+    let mut events = match host.spawn() {
+        Ok(events) => events,
+        Err(err) => return Exit::SpawnFailed(err),
+    };
+    if let Err(err) = host.write(configure.as_bytes()) {
+        log::error!("Could not configure the sidecar: {}", err);
+    }
+
+    // the first heartbeat is due one timeout after the start
+    let mut last_seen = Instant::now();
+    // an interval keeps ticking while the mapper is busy reporting keys, a sleep would not
+    let mut tick = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        tokio::select! {
+            event = events.recv() => match event {
+                Some(SidecarEvent::Stdout(line)) => {
+                    let line = String::from_utf8_lossy(&line);
+                    match decode::<MapperMessage>(&line) {
+                        Ok(message) => {
+                            last_seen = Instant::now();
+                            host.message(message);
+                        }
+                        // lines outside the protocol are only logged
+                        Err(err) => log::info!("[Sidecar stdout] {:?} ({})", line.trim(), err),
+                    }
+                }
+                Some(SidecarEvent::Stderr(line)) => {
+                    log::info!("[Sidecar stderr] {:?}", String::from_utf8_lossy(&line).trim());
+                }
+                Some(SidecarEvent::Terminated { code, signal }) => {
+                    return Exit::Terminated { code, signal };
+                }
+                None => {
+                    return Exit::Terminated {
+                        code: None,
+                        signal: None,
+                    };
+                }
+            },
+            _ = tick.tick() => {
+                if last_seen.elapsed() > timeout {
+                    host.kill();
+                    return Exit::HeartbeatTimeout;
+                }
+                if let Err(err) = host.write(heartbeat_line().as_bytes()) {
+                    log::error!("Could not send the sidecar a heartbeat: {}", err);
+                }
+            }
+        }
+    }
+}
+
+/// Keeps the sidecar running until the host is stopping or the restart budget is spent.
+/// `configure` is written to every new process, see [`configure_line`].
+pub async fn supervise_with<H: SidecarHost>(host: &H, configure: String, policy: &SidecarPolicy) {
+    let timeout = Duration::from_secs(policy.heartbeat_timeout_secs);
+    let mut budget = RestartBudget::new(policy);
+    loop {
+        let exit = run_once(host, &configure, timeout).await;
+        if host.stopping() {
+            return;
+        }
+        host.exited(exit.describe());
+
+        match budget.next_restart(Instant::now()) {
+            Some(delay) => {
+                log::info!("[Sidecar] Restarting in {:?}", delay);
+                tokio::time::sleep(delay).await;
+                if host.stopping() {
+                    return;
+                }
+                host.restarted();
+            }
+            None => {
+                host.gave_up(format!(
+                    "Key mapper could not be kept running, {} restarts within {} seconds",
+                    policy.max_restarts, policy.restart_window_secs
+                ));
+                return;
+            }
+        }
+    }
+}
+
+/// Keeps the bundled mapper running until the app exits or the restart budget is spent.
+pub async fn supervise(
+    app: AppHandle,
+    rules: Vec<String>,
+    mode: AppMode,
+    watchdog: Option<WatchdogConfig>,
+    policy: SidecarPolicy,
+) {
+    let configure = configure_line(&rules, mode, watchdog.as_ref());
+    supervise_with(&AppSidecar(app), configure, &policy).await;
+}
+
+/// Waits up to `grace` for `terminated` to be set, true when it was.
+fn wait_for_exit(terminated: &AtomicBool, grace: Duration) -> bool {
+    let deadline = Instant::now() + grace;
+    while !terminated.load(Ordering::SeqCst) {
+        if Instant::now() >= deadline {
+            return false;
+        }
+        std::thread::sleep(SHUTDOWN_POLL);
+    }
+    true
+}
+
+/// Asks the mapper to release the keyboard and gives it [`SHUTDOWN_GRACE`] to exit before
+/// killing it. The supervisor does not restart it.
+pub fn stop(app: &AppHandle) {
+    let stopped = match app.state::<SidecarState>().0.lock() {
+        Ok(mut process) => {
+            process.stopping = true;
+            process
+                .child
+                .take()
+                .map(|child| (child, process.terminated.clone()))
+        }
+        Err(_) => None,
+    };
+    let Some((mut child, terminated)) = stopped else {
+        return;
+    };
+    match child.write(shutdown_line().as_bytes()) {
+        Ok(_) if wait_for_exit(&terminated, SHUTDOWN_GRACE) => {
+            log::info!("[Sidecar] Exited after the shutdown request");
+            return;
+        }
+        Ok(_) => log::warn!(
+            "[Sidecar] Still running {:?} after the shutdown request",
+            SHUTDOWN_GRACE
+        ),
+        Err(err) => log::error!("Could not ask the sidecar to shut down: {}", err),
+    }
+    match child.kill() {
+        Ok(_) => log::info!("[Sidecar] Killed on exit"),
+        Err(err) => log::error!("Could not kill the sidecar: {}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn waiting_for_exit_returns_once_the_sidecar_terminated() {
+        let terminated = Arc::new(AtomicBool::new(false));
+        let flag = terminated.clone();
+        let exiting = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            flag.store(true, Ordering::SeqCst);
+        });
+        let started = Instant::now();
+        assert!(wait_for_exit(&terminated, Duration::from_secs(10)));
+        assert!(started.elapsed() < Duration::from_secs(5));
+        exiting.join().unwrap();
+    }
+
+    #[test]
+    fn waiting_for_exit_gives_up_after_the_grace_period() {
+        let terminated = AtomicBool::new(false);
+        let started = Instant::now();
+        assert!(!wait_for_exit(&terminated, Duration::from_millis(200)));
+        assert!(started.elapsed() >= Duration::from_millis(200));
+    }
+}
@@ -0,0 +1,188 @@
+//! Background queue replaying autosaved answers to the submission endpoint.
+//!
+//! Answers go out oldest first, one at a time, each with its idempotency key so the endpoint
+//! can drop a retry it has already applied. Failures back off exponentially up to a cap and
+//! are retried for as long as the app runs.
+
+use crate::utils::autosave::AnswerState;
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+use tauri::{AppHandle, Manager};
+use ts_rs::TS;
+
+/// How often an idle queue looks for new answers
+const IDLE_POLL: Duration = Duration::from_secs(2);
+const BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SubmissionPolicy {
+    /// Answers are only kept locally when unset
+    pub endpoint: Option<String>,
+    pub max_backoff_secs: u64,
+    pub timeout_secs: u64,
+}
+
+impl Default for SubmissionPolicy {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            max_backoff_secs: 60,
+            timeout_secs: 15,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, TS)]
+pub struct SyncStatus {
+    pub endpoint_configured: bool,
+    #[ts(type = "number")]
+    pub pending: usize,
+    #[ts(type = "number")]
+    pub synced: usize,
+    /// Failed attempts since the last successful submission
+    pub failed_attempts: u32,
+    #[ts(type = "number | null")]
+    pub last_synced_at: Option<i64>,
+    pub last_error: Option<String>,
+    #[ts(type = "number | null")]
+    pub next_attempt_at: Option<i64>,
+}
+
+pub struct SyncState(pub Mutex<SyncStatus>);
+
+/// Delay before retry number `attempt`, doubling from one second up to `max`.
+pub fn backoff(attempt: u32, max: Duration) -> Duration {
+    let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+    BASE_BACKOFF.saturating_mul(factor).min(max)
+}
+
+/// The sync status with the queue counters read from the answer store.
+pub fn current_status(app: &AppHandle) -> Result<SyncStatus, String> {
+    let mut status = app
+        .state::<SyncState>()
+        .0
+        .lock()
+        .map_err(|err| err.to_string())?
+        .clone();
+    let answers = app.state::<AnswerState>();
+    let store = answers.0.lock().map_err(|err| err.to_string())?;
+    if let Some(store) = store.as_ref() {
+        status.pending = store.pending().len();
+        status.synced = store.synced_count();
+    }
+    Ok(status)
+}
+
+fn update_status(app: &AppHandle, update: impl FnOnce(&mut SyncStatus)) {
+    match app.state::<SyncState>().0.lock() {
+        Ok(mut status) => update(&mut status),
+        Err(err) => log::error!("Could not lock sync status: {}", err),
+    }
+}
+
+/// Runs the queue until the app exits.
+pub async fn run_queue(app: AppHandle, policy: SubmissionPolicy) {
+    let Some(endpoint) = policy.endpoint else {
+        log::info!("No submission endpoint configured, answers are kept locally only");
+        return;
+    };
+    update_status(&app, |status| status.endpoint_configured = true);
+    let client = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(policy.timeout_secs))
+        .build()
+    {
+        Ok(client) => client,
+        Err(err) => {
+            log::error!("Could not build the submission client: {}", err);
+            return;
+        }
+    };
+    let max_backoff = Duration::from_secs(policy.max_backoff_secs);
+    let mut attempts = 0u32;
+
+    loop {
+        let next = {
+            let answers = app.state::<AnswerState>();
+            let store = answers.0.lock();
+            match store.as_deref() {
+                Ok(Some(store)) => store
+                    .pending()
+                    .first()
+                    .map(|record| (store.session_id().to_string(), record.clone())),
+                _ => None,
+            }
+        };
+        let Some((session_id, record)) = next else {
+            tokio::time::sleep(IDLE_POLL).await;
+            continue;
+        };
+
+        let body = serde_json::json!({
+            "session_id": session_id,
+            "seq": record.seq,
+            "question_id": record.question_id,
+            "answer": record.answer,
+            "saved_at": record.saved_at,
+        });
+        let outcome = match client
+            .post(&endpoint)
+            .header("Idempotency-Key", &record.idempotency_key)
+            .json(&body)
+            .send()
+            .await
+        {
+            // a conflict means the endpoint already has this answer
+            Ok(response)
+                if response.status().is_success()
+                    || response.status() == reqwest::StatusCode::CONFLICT =>
+            {
+                Ok(())
+            }
+            Ok(response) => Err(format!("endpoint answered {}", response.status())),
+            Err(err) => Err(err.to_string()),
+        };
+
+        match outcome {
+            Ok(()) => {
+                attempts = 0;
+                let marked = app
+                    .state::<AnswerState>()
+                    .0
+                    .lock()
+                    .map_err(|err| err.to_string())
+                    .and_then(|mut store| match store.as_mut() {
+                        Some(store) => store.mark_synced(record.seq).map_err(|err| err.to_string()),
+                        None => Ok(()),
+                    });
+                if let Err(err) = marked {
+                    log::error!("Could not mark answer {} as synced: {}", record.seq, err);
+                }
+                update_status(&app, |status| {
+                    status.failed_attempts = 0;
+                    status.last_synced_at = Some(chrono::Utc::now().timestamp_millis());
+                    status.last_error = None;
+                    status.next_attempt_at = None;
+                });
+            }
+            Err(err) => {
+                attempts = attempts.saturating_add(1);
+                let delay = backoff(attempts, max_backoff);
+                log::warn!(
+                    "Submitting answer {} failed ({}), retrying in {:?}",
+                    record.seq,
+                    err,
+                    delay
+                );
+                update_status(&app, |status| {
+                    status.failed_attempts = attempts;
+                    status.last_error = Some(err);
+                    status.next_attempt_at =
+                        Some(chrono::Utc::now().timestamp_millis() + delay.as_millis() as i64);
+                });
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
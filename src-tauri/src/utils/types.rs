@@ -1,8 +1,11 @@
 use serde::{Serialize, Deserialize};
+pub use shared::fingerprint::DeviceFingerprint;
+use ts_rs::TS;
+use crate::utils::mode::AppMode;
 
 /// A status report for port numbers.
 /// if this type exists, it means a udp port is open.
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PortStatus {
     port: u32,
     running: bool,
@@ -14,7 +17,7 @@ impl PortStatus {
     }
 }
 /// A struct that identifies a running flagged process
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 
 pub struct ProcessIdentifier {
     pub process_id: i32,
@@ -25,7 +28,7 @@ pub struct ProcessIdentifier {
     pub cpu_usage: f32,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebRtcReport {
     pub ports: Vec<PortStatus>,
     pub processes: Vec<ProcessIdentifier>,
@@ -42,18 +45,51 @@ pub enum Triggers {
     DisAllowedInputDectected(Vec<USBDevice>),
     UDPDectected,
     RemoteApplicationDectected(WebRtcReport),
+    TooManyDisplays(DisplayTopology),
+    DisplayHotPlugged(DisplayTopology),
+    /// Size in bytes of the new clipboard content
+    ClipboardChanged(usize),
+    /// What no longer matches the integrity manifest
+    IntegrityFailed(Vec<String>),
 }
 
-#[derive(Debug, Clone, Serialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct HostInfo {
     pub os: String,
     pub arch: String,
-    pub mac_address: Option<String>,
-    pub serial_number: Option<String>,
-    pub processor_id: Option<String>,
+    /// Salted hashes of the hardware identity, the serials and addresses themselves are not
+    /// handed out
+    pub fingerprint: Option<DeviceFingerprint>,
+    /// The same kind of hashes for each identity field on its own, `None` without a
+    /// deployment salt
+    pub hardware: Option<HardwareHashes>,
+    /// Hardware identity fields that exist but could not be read without elevated privileges
+    pub unreadable: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Salted hash of each field of the [`HardwareIdentity`], `None` where the field is missing,
+/// unreadable or a firmware placeholder.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub struct HardwareHashes {
+    pub product_uuid: Option<String>,
+    pub product_serial: Option<String>,
+    pub board_serial: Option<String>,
+    pub chassis_serial: Option<String>,
+}
+
+/// Firmware identity fields, each read separately so a missing one does not hide the others.
+/// Deliberately not serializable, only the hashes in [`DeviceFingerprint`] leave the machine.
+#[derive(Debug, Clone, Default)]
+pub struct HardwareIdentity {
+    pub product_uuid: Option<String>,
+    pub product_serial: Option<String>,
+    pub board_serial: Option<String>,
+    pub chassis_serial: Option<String>,
+    /// Fields that exist but could not be read without elevated privileges
+    pub unreadable: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct USBDevice {
     /// Platform specific unique ID
     pub id: String,
@@ -67,7 +103,124 @@ pub struct USBDevice {
     pub serial_number: Option<String>,
 }
 
+/// The latest result of each background detector, as returned to the front-end.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DetectorStatus {
+    pub web_rtc: Option<WebRtcReport>,
+    /// Unix timestamp in milliseconds of the last remote application check
+    pub web_rtc_checked_at: Option<i64>,
+    pub disallowed_devices: Vec<USBDevice>,
+    /// Unix timestamp in milliseconds of the last usb device check
+    pub devices_checked_at: Option<i64>,
+    pub displays: Option<DisplayTopology>,
+    pub focus: FocusSummary,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct FocusSummary {
+    /// Number of times the exam window lost focus
+    pub losses: u32,
+    pub currently_unfocused: bool,
+    pub total_unfocused_ms: u64,
+    pub longest_unfocused_ms: u64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DisplayInfo {
+    pub name: Option<String>,
+    /// Physical pixels
+    pub width: u32,
+    pub height: u32,
+    pub x: i32,
+    pub y: i32,
+    pub scale_factor: f64,
+}
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct DisplayTopology {
+    /// Monitors the windowing system reports
+    pub monitors: Vec<DisplayInfo>,
+    /// DRM connectors with a display attached, linux only
+    pub drm_connectors: Vec<String>,
+}
+
+impl DisplayTopology {
+    /// Mirrored outputs show up as one monitor but two connectors, so take the larger count.
+    pub fn active_count(&self) -> usize {
+        self.monitors.len().max(self.drm_connectors.len())
+    }
+
+    /// Identifies the set of attached displays, used to notice hot-plugs.
+    pub fn signature(&self) -> Vec<String> {
+        let mut signature: Vec<String> = self
+            .monitors
+            .iter()
+            .map(|monitor| {
+                format!(
+                    "{}@{},{}",
+                    monitor.name.clone().unwrap_or_default(),
+                    monitor.x,
+                    monitor.y
+                )
+            })
+            .chain(self.drm_connectors.iter().cloned())
+            .collect();
+        signature.sort();
+        signature
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum SessionStatus {
+    Starting,
+    Active,
+    Locked,
+    Ended,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct SessionState {
+    /// Random id of this exam session, keys the answer store and the audit trail
+    pub id: String,
+    pub status: SessionStatus,
+    pub mode: AppMode,
+    pub seat: Option<String>,
+    /// Unix timestamp in milliseconds
+    #[ts(type = "number")]
+    pub started_at: i64,
+    #[ts(type = "number")]
+    pub violation_count: usize,
+    /// Continued with `--resume` after the app was killed, see `utils::watchdog`
+    pub resumed: bool,
+    /// Time the proctor added to the exam
+    pub extra_minutes: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, TS)]
+#[serde(rename_all = "snake_case")]
+pub enum ViolationKind {
+    DisallowedDevice,
+    RemoteApplication,
+    MultipleDisplays,
+    DisplayHotPlug,
+    FocusLost,
+    Clipboard,
+    KeyboardGuardLost,
+    IntegrityFailure,
+}
+
+/// A single rule break recorded during the session.
+#[derive(Debug, Clone, Serialize, Deserialize, TS)]
+pub struct Violation {
+    #[ts(type = "number")]
+    pub id: u64,
+    pub kind: ViolationKind,
+    pub detail: String,
+    /// Unix timestamp in milliseconds
+    #[ts(type = "number")]
+    pub occurred_at: i64,
+}
 
 #[derive(Debug, Deserialize)]
 pub struct RawUdpEndpoint {
@@ -91,3 +244,48 @@ pub struct UdpEndpoint {
     pub creation_time: String,
     pub status: Option<String>,
 }
+
+/// How bad a single diagnostic finding is.
+/// The ordering matters: the worst finding decides the exit code of the diagnostics binary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Ok,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Severity::Ok => 0,
+            Severity::Warning => 1,
+            Severity::Critical => 2,
+        }
+    }
+}
+
+/// The outcome of one detector run by the diagnostics report.
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: String,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticReport {
+    pub generated_at: String,
+    pub host: HostInfo,
+    pub findings: Vec<Finding>,
+}
+
+impl DiagnosticReport {
+    pub fn worst(&self) -> Severity {
+        self.findings
+            .iter()
+            .map(|finding| finding.severity)
+            .max()
+            .unwrap_or(Severity::Ok)
+    }
+}
@@ -0,0 +1,190 @@
+//! Unlocking a locked session with a proctor's code, see `shared::unlock`.
+//!
+//! Every lock gets its own challenge, shown on the lock screen and sent with the heartbeats.
+//! A correct code ends that lock event, so it cannot be used again, and is audited with the
+//! proctor's id. After `max_attempts` wrong codes only the console can unlock the session.
+//! The secret is a deployment secret, see `utils::secrets`; without one there are no codes
+//! and only the console can unlock.
+
+use crate::utils::audit::audit;
+use crate::utils::events::LockNotice;
+use crate::utils::policy::Policy;
+use crate::utils::secrets::UNLOCK_SECRET;
+use crate::utils::session::{self, SessionStore};
+use crate::utils::types::ViolationKind;
+use serde::{Deserialize, Serialize};
+use shared::unlock::{validate_proctor, verify_response};
+use tauri::{AppHandle, Manager};
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct UnlockPolicy {
+    /// Violations that lock the session, e.g. `["focus_lost", "multiple_displays"]`
+    pub lock_on: Vec<ViolationKind>,
+    /// Wrong codes allowed for one lock
+    pub max_attempts: u32,
+}
+
+impl Default for UnlockPolicy {
+    fn default() -> Self {
+        Self {
+            lock_on: Vec::new(),
+            max_attempts: 5,
+        }
+    }
+}
+
+/// A lock of the session that has not been lifted yet.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LockEvent {
+    pub notice: LockNotice,
+    /// Wrong codes entered so far
+    pub attempts: u32,
+}
+
+impl LockEvent {
+    /// Checks a code against this lock's challenge and counts it when it is wrong. Once
+    /// `max_attempts` codes were wrong no code is checked any more.
+    pub fn check_code(
+        &mut self,
+        secret: &[u8],
+        proctor: &str,
+        code: &str,
+        max_attempts: u32,
+    ) -> Result<(), String> {
+        let challenge = self
+            .notice
+            .challenge
+            .clone()
+            .ok_or("this lock has no unlock challenge")?;
+        let checked = if self.attempts >= max_attempts {
+            Err("too many wrong codes, the proctor has to unlock from the console".to_string())
+        } else {
+            verify_response(secret, &challenge, proctor, code)
+        };
+        if checked.is_err() {
+            self.attempts = self.attempts.saturating_add(1);
+        }
+        checked
+    }
+}
+
+/// Secret the proctors' codes are made with, codes are off without one.
+pub fn unlock_secret() -> Option<Vec<u8>> {
+    UNLOCK_SECRET.load().ok()
+}
+
+pub fn unlock_with_code(app: &AppHandle, proctor: &str, code: &str) -> Result<(), String> {
+    let secret = unlock_secret().ok_or("unlock codes are not set up on this machine")?;
+    let proctor = validate_proctor(proctor)?;
+    let max_attempts = app.state::<Policy>().unlock.max_attempts;
+    let (lock_id, attempts, checked) = {
+        let store = app.state::<SessionStore>();
+        let mut session = store.0.lock().map_err(|err| err.to_string())?;
+        let event = session.lock.as_mut().ok_or("the session is not locked")?;
+        let checked = event.check_code(&secret, &proctor, code, max_attempts);
+        let outcome = (event.notice.lock_id, event.attempts, checked);
+        // a relaunch must not hand out a fresh set of attempts
+        session.persist();
+        outcome
+    };
+
+    match checked {
+        Ok(()) => {
+            session::unlock(app, "unlock_code", Some(&proctor));
+            Ok(())
+        }
+        Err(err) => {
+            log::warn!("Unlock code for lock {} rejected: {}", lock_id, err);
+            audit(
+                app,
+                "unlock_code_rejected",
+                serde_json::json!({
+                    "lock_id": lock_id,
+                    "proctor": proctor,
+                    "attempts": attempts,
+                    "error": err,
+                }),
+            );
+            Err(err)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use shared::unlock::{challenge, response};
+
+    const SECRET: &[u8] = b"unlock-test-secret";
+
+    fn locked(challenge: Option<String>) -> LockEvent {
+        LockEvent {
+            notice: LockNotice {
+                lock_id: 1,
+                reason: "copied".into(),
+                violation_id: Some(4),
+                challenge,
+            },
+            attempts: 0,
+        }
+    }
+
+    /// Proctor 7's code for a lock of `session`.
+    fn challenge_code(lock_id: u64, nonce: &[u8]) -> String {
+        response(
+            SECRET,
+            &challenge("session", lock_id, Some(4), nonce),
+            "Proctor 7",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn the_proctors_code_opens_the_lock() {
+        let challenge = challenge("session", 1, Some(4), &[1; 16]);
+        let code = response(SECRET, &challenge, "Proctor 7").unwrap();
+        let mut event = locked(Some(challenge));
+        assert_eq!(event.check_code(SECRET, "Proctor 7", &code, 5), Ok(()));
+        assert_eq!(event.attempts, 0);
+    }
+
+    #[test]
+    fn wrong_codes_count_until_the_lock_only_opens_from_the_console() {
+        let challenge = challenge("session", 1, Some(4), &[1; 16]);
+        let code = response(SECRET, &challenge, "Proctor 7").unwrap();
+        let mut event = locked(Some(challenge));
+        for attempt in 1..=3 {
+            assert!(event
+                .check_code(SECRET, "Proctor 7", "0000-0000", 3)
+                .is_err());
+            assert_eq!(event.attempts, attempt);
+        }
+        // the right code is no longer checked
+        let err = event.check_code(SECRET, "Proctor 7", &code, 3).unwrap_err();
+        assert!(err.contains("too many wrong codes"), "{}", err);
+        assert_eq!(event.attempts, 4);
+    }
+
+    #[test]
+    fn codes_for_another_lock_or_proctor_are_wrong() {
+        let challenge = challenge("session", 2, Some(4), &[2; 16]);
+        let mut event = locked(Some(challenge.clone()));
+        // made for the previous lock of the session
+        let earlier = challenge_code(1, &[1; 16]);
+        assert!(event.check_code(SECRET, "Proctor 7", &earlier, 5).is_err());
+        // given out to another proctor
+        let code = response(SECRET, &challenge, "Proctor 8").unwrap();
+        assert!(event.check_code(SECRET, "Proctor 7", &code, 5).is_err());
+        assert_eq!(event.attempts, 2);
+    }
+
+    #[test]
+    fn a_lock_without_a_challenge_takes_no_code() {
+        let mut event = locked(None);
+        assert!(event
+            .check_code(SECRET, "Proctor 7", "0000-0000", 5)
+            .is_err());
+        assert_eq!(event.attempts, 0);
+    }
+}
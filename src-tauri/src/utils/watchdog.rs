@@ -0,0 +1,134 @@
+//! The app's side of the mapper watchdog, see `shared::watchdog`.
+//!
+//! Records are signed with the audit key, so one left behind by a killed session is checked
+//! and moved into the audit log on the next start.
+
+use crate::utils::audit::{audit, audit_key};
+use serde::Deserialize;
+use shared::watchdog::{SignedRecord, WatchdogConfig, RECORD_PREFIX};
+use std::path::PathBuf;
+use tauri::{AppHandle, Manager};
+
+const RECORD_DIR: &str = "watchdog";
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct WatchdogPolicy {
+    /// Let the mapper watch the app while it runs
+    pub enabled: bool,
+    /// The app counts as killed after this long without a heartbeat
+    pub timeout_secs: u64,
+    /// Start the app again with `--resume` when it was killed
+    pub relaunch: bool,
+}
+
+impl Default for WatchdogPolicy {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            timeout_secs: 5,
+            relaunch: true,
+        }
+    }
+}
+
+fn record_dir(app: &AppHandle) -> Option<PathBuf> {
+    app.path()
+        .app_data_dir()
+        .map(|dir| dir.join(RECORD_DIR))
+        .map_err(|err| log::error!("Could not find the watchdog directory: {}", err))
+        .ok()
+}
+
+/// This executable with the arguments of this launch and `--resume <session_id>`.
+pub fn relaunch_command(session_id: &str) -> Option<Vec<String>> {
+    let exe = std::env::current_exe().ok()?;
+    let mut command = vec![exe.to_string_lossy().into_owned()];
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        // a resumed session is resumed again under the same id
+        if arg == "--resume" {
+            args.next();
+        } else if !arg.starts_with("--resume=") {
+            command.push(arg);
+        }
+    }
+    command.push("--resume".into());
+    command.push(session_id.to_string());
+    Some(command)
+}
+
+/// What the mapper needs to watch this session, `None` when the policy turns it off.
+pub fn config(
+    app: &AppHandle,
+    session_id: &str,
+    policy: &WatchdogPolicy,
+) -> Option<WatchdogConfig> {
+    if !policy.enabled {
+        return None;
+    }
+    let relaunch = match policy.relaunch {
+        true => relaunch_command(session_id).unwrap_or_default(),
+        false => Vec::new(),
+    };
+    Some(WatchdogConfig {
+        session_id: session_id.to_string(),
+        pid: std::process::id(),
+        key: hex::encode(audit_key().ok()?),
+        record_dir: record_dir(app)?.to_string_lossy().into_owned(),
+        timeout_ms: policy.timeout_secs * 1000,
+        relaunch,
+    })
+}
+
+/// Moves the records of earlier abnormal terminations into the audit log. Returns how many
+/// were found, records with a bad signature included.
+pub fn collect_records(app: &AppHandle) -> usize {
+    let Some(dir) = record_dir(app) else {
+        return 0;
+    };
+    let Ok(entries) = std::fs::read_dir(&dir) else {
+        return 0;
+    };
+    let Ok(key) = audit_key() else {
+        return 0;
+    };
+    let mut found = 0;
+    for path in entries.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let is_record = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with(RECORD_PREFIX));
+        if !is_record {
+            continue;
+        }
+        found += 1;
+        let detail = std::fs::read_to_string(&path)
+            .map_err(|err| err.to_string())
+            .and_then(|json| {
+                serde_json::from_str::<SignedRecord>(&json).map_err(|err| err.to_string())
+            })
+            .and_then(|signed| signed.verify(&key));
+        match detail {
+            Ok(record) => {
+                log::error!("The app was terminated abnormally: {:?}", record);
+                audit(
+                    app,
+                    "abnormal_termination",
+                    serde_json::json!({ "verified": true, "record": record }),
+                );
+            }
+            Err(err) => {
+                log::error!("Invalid termination record {}: {}", path.display(), err);
+                audit(
+                    app,
+                    "abnormal_termination",
+                    serde_json::json!({ "verified": false, "error": err }),
+                );
+            }
+        }
+        if let Err(err) = std::fs::remove_file(&path) {
+            log::error!("Could not remove {}: {}", path.display(), err);
+        }
+    }
+    found
+}
@@ -0,0 +1,145 @@
+//! Checks consoles against the pinned key the way discovery does once mDNS has found them.
+
+use app_lib::utils::discovery::{select, Candidate};
+use ed25519_dalek::SigningKey;
+use shared::discovery::{public_key_hex, sign_identity, IdentityProof, IDENTITY_PATH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+const PINNED_SEED: [u8; 32] = [7; 32];
+const OTHER_SEED: [u8; 32] = [8; 32];
+
+/// How a stand-in console answers `GET /identity?nonce=<hex>`.
+#[derive(Clone)]
+enum Answer {
+    /// Signs the nonce it was sent
+    Sign(SigningKey),
+    /// Replays a proof recorded for another nonce
+    Replay(IdentityProof),
+    /// Answers with something that is not a proof
+    Garbage,
+}
+
+/// Serves identity requests on a free loopback port and returns it as a candidate.
+async fn console(instance: &str, answer: Answer) -> Candidate {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            let answer = answer.clone();
+            tokio::spawn(async move {
+                let mut request = Vec::new();
+                let mut chunk = [0u8; 1024];
+                while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                    match stream.read(&mut chunk).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(read) => request.extend_from_slice(&chunk[..read]),
+                    }
+                }
+                let request = String::from_utf8_lossy(&request).to_string();
+                let target = request.split_whitespace().nth(1).unwrap_or_default();
+                let nonce = target
+                    .strip_prefix(IDENTITY_PATH)
+                    .and_then(|query| query.strip_prefix("?nonce="))
+                    .and_then(|nonce| hex::decode(nonce).ok());
+                let (status, body) = match (nonce, answer) {
+                    (None, _) => ("404 Not Found", String::new()),
+                    (Some(nonce), Answer::Sign(key)) => (
+                        "200 OK",
+                        serde_json::to_string(&sign_identity(&key, &nonce)).unwrap(),
+                    ),
+                    (Some(_), Answer::Replay(proof)) => {
+                        ("200 OK", serde_json::to_string(&proof).unwrap())
+                    }
+                    (Some(_), Answer::Garbage) => ("200 OK", "{\"hello\":1}".to_string()),
+                };
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    Candidate {
+        instance: format!("{}._secure-proctor._tcp.local.", instance),
+        base_url: format!("http://{}", address),
+        path: "/heartbeat".into(),
+    }
+}
+
+fn signed(seed: [u8; 32]) -> Answer {
+    Answer::Sign(SigningKey::from_bytes(&seed))
+}
+
+fn pinned() -> String {
+    public_key_hex(&SigningKey::from_bytes(&PINNED_SEED))
+}
+
+#[tokio::test]
+async fn a_console_holding_the_pinned_key_is_chosen() {
+    let candidate = console("proctor", signed(PINNED_SEED)).await;
+    let selection = select(&reqwest::Client::new(), vec![candidate.clone()], &pinned()).await;
+
+    let (chosen, endpoint) = selection.chosen.expect("the pinned console");
+    assert_eq!(chosen, candidate);
+    assert_eq!(endpoint, format!("{}/heartbeat", candidate.base_url));
+    assert!(selection.rejected.is_empty());
+}
+
+#[tokio::test]
+async fn a_console_with_another_key_is_rejected() {
+    let impostor = console("impostor", signed(OTHER_SEED)).await;
+    let selection = select(&reqwest::Client::new(), vec![impostor.clone()], &pinned()).await;
+
+    assert!(selection.chosen.is_none());
+    assert_eq!(selection.rejected.len(), 1);
+    let (rejected, err) = &selection.rejected[0];
+    assert_eq!(rejected, &impostor);
+    assert!(err.contains("not the pinned key"), "{}", err);
+}
+
+#[tokio::test]
+async fn impostors_ahead_of_the_console_are_skipped() {
+    // a proof of the pinned key, recorded for another nonce
+    let recorded = sign_identity(&SigningKey::from_bytes(&PINNED_SEED), &[0; 32]);
+    let candidates = vec![
+        console("impostor", signed(OTHER_SEED)).await,
+        console("replay", Answer::Replay(recorded)).await,
+        console("garbage", Answer::Garbage).await,
+        console("proctor", signed(PINNED_SEED)).await,
+        console("late", signed(PINNED_SEED)).await,
+    ];
+    let selection = select(&reqwest::Client::new(), candidates.clone(), &pinned()).await;
+
+    let (chosen, _) = selection.chosen.expect("the pinned console");
+    assert_eq!(chosen, candidates[3]);
+    let reasons: Vec<&str> = selection
+        .rejected
+        .iter()
+        .map(|(_, err)| err.as_str())
+        .collect();
+    assert_eq!(reasons.len(), 3);
+    assert!(reasons[0].contains("not the pinned key"), "{}", reasons[0]);
+    assert_eq!(reasons[1], "identity signature does not match");
+    assert!(reasons[2].starts_with("invalid identity"), "{}", reasons[2]);
+}
+
+#[tokio::test]
+async fn an_unreachable_console_is_rejected() {
+    // a port that was free a moment ago
+    let address = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .unwrap();
+    let gone = Candidate {
+        instance: "gone._secure-proctor._tcp.local.".into(),
+        base_url: format!("http://{}", address),
+        path: "/heartbeat".into(),
+    };
+    let selection = select(&reqwest::Client::new(), vec![gone], &pinned()).await;
+
+    assert!(selection.chosen.is_none());
+    assert_eq!(selection.rejected.len(), 1);
+}
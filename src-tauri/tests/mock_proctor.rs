@@ -0,0 +1,204 @@
+//! Runs the `mock_proctor` binary and talks to it the way the heartbeat client does.
+
+use ed25519_dalek::SigningKey;
+use shared::discovery::public_key_hex;
+use shared::proctor::{
+    sign, verify_response, CommandAction, Heartbeat, HeartbeatResponse, KEY_ENV, SIGNATURE_HEADER,
+};
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::time::{Duration, Instant};
+
+const KEY: &str = "mock-proctor-test-key";
+const SEED: [u8; 32] = [7; 32];
+
+struct MockProctor {
+    child: Child,
+    stdin: ChildStdin,
+    address: String,
+    identity: PathBuf,
+}
+
+impl MockProctor {
+    fn start(name: &str) -> Self {
+        let identity =
+            std::env::temp_dir().join(format!("mock-proctor-{}-{}.key", name, std::process::id()));
+        std::fs::write(&identity, hex::encode(SEED)).expect("write the identity");
+        // a free port, released again for the mock to take
+        let address = TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("find a free port")
+            .to_string();
+        let mut child = Command::new(env!("CARGO_BIN_EXE_mock_proctor"))
+            .args(["--listen", &address, "--identity"])
+            .arg(&identity)
+            .env(KEY_ENV, KEY)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .spawn()
+            .expect("start mock_proctor");
+        let stdin = child.stdin.take().expect("piped stdin");
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while TcpStream::connect(&address).is_err() {
+            assert!(Instant::now() < deadline, "mock_proctor did not listen");
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Self {
+            child,
+            stdin,
+            address,
+            identity,
+        }
+    }
+
+    fn queue(&mut self, line: &str) {
+        writeln!(self.stdin, "{}", line).expect("write a command");
+    }
+
+    /// Posts a heartbeat and returns the status code, the signature header and the body.
+    fn post(&self, body: &[u8], signature: Option<&str>) -> (u16, Option<String>, Vec<u8>) {
+        let mut stream = TcpStream::connect(&self.address).expect("connect");
+        let mut request = format!(
+            "POST /heartbeat HTTP/1.1\r\nHost: {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n",
+            self.address,
+            body.len()
+        );
+        if let Some(signature) = signature {
+            request.push_str(&format!("{}: {}\r\n", SIGNATURE_HEADER, signature));
+        }
+        request.push_str("\r\n");
+        stream.write_all(request.as_bytes()).expect("send the head");
+        stream.write_all(body).expect("send the body");
+        let mut response = Vec::new();
+        stream.read_to_end(&mut response).expect("read the answer");
+
+        let head_end = response
+            .windows(4)
+            .position(|window| window == b"\r\n\r\n")
+            .expect("a complete answer")
+            + 4;
+        let head = String::from_utf8_lossy(&response[..head_end]).to_string();
+        let status = head
+            .split_whitespace()
+            .nth(1)
+            .and_then(|code| code.parse().ok())
+            .expect("a status code");
+        let signature = head.lines().find_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            name.trim()
+                .eq_ignore_ascii_case(SIGNATURE_HEADER)
+                .then(|| value.trim().to_string())
+        });
+        (status, signature, response[head_end..].to_vec())
+    }
+
+    /// Sends a correctly signed heartbeat and checks the answer like the app does.
+    fn heartbeat(&self, heartbeat: &Heartbeat) -> HeartbeatResponse {
+        let body = serde_json::to_vec(heartbeat).expect("heartbeats serialize");
+        let (status, signature, reply) = self.post(&body, Some(&sign(KEY.as_bytes(), &body)));
+        assert_eq!(status, 200);
+        let console_key = public_key_hex(&SigningKey::from_bytes(&SEED));
+        verify_response(&console_key, &reply, &signature.expect("a signed answer"))
+            .expect("answer signed by the identity");
+        let response: HeartbeatResponse = serde_json::from_slice(&reply).expect("an answer");
+        response.answers(heartbeat).expect("answers this heartbeat");
+        response
+    }
+}
+
+impl Drop for MockProctor {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+        let _ = std::fs::remove_file(&self.identity);
+    }
+}
+
+fn heartbeat(seq: u64) -> Heartbeat {
+    Heartbeat {
+        seq,
+        nonce: format!("{:032x}", seq),
+        session_id: "0123456789abcdef0123456789abcdef".into(),
+        seat: Some("A1".into()),
+        fingerprint: None,
+        status: "active".into(),
+        mode: "kiosk".into(),
+        violation_count: 0,
+        violations: Vec::new(),
+        acked_commands: Vec::new(),
+        unlock_challenge: None,
+        sent_at: 0,
+    }
+}
+
+#[test]
+fn answers_are_signed_and_bound_to_the_heartbeat() {
+    let proctor = MockProctor::start("signed");
+    let response = proctor.heartbeat(&heartbeat(1));
+    assert_eq!(response.seq, 1);
+    assert!(response.commands.is_empty());
+
+    // another seat's key cannot check the answer
+    let body = serde_json::to_vec(&heartbeat(2)).unwrap();
+    let (_, signature, reply) = proctor.post(&body, Some(&sign(KEY.as_bytes(), &body)));
+    let other = public_key_hex(&SigningKey::from_bytes(&[8; 32]));
+    assert!(verify_response(&other, &reply, &signature.unwrap()).is_err());
+}
+
+#[test]
+fn queued_commands_go_out_until_acknowledged() {
+    let mut proctor = MockProctor::start("commands");
+    proctor.queue("lock left the room");
+    proctor.queue("extend 10");
+
+    // stdin is read on its own thread
+    let deadline = Instant::now() + Duration::from_secs(10);
+    let mut seq = 0;
+    let commands = loop {
+        seq += 1;
+        let response = proctor.heartbeat(&heartbeat(seq));
+        if response.commands.len() == 2 {
+            break response.commands;
+        }
+        assert!(Instant::now() < deadline, "commands were not queued");
+        std::thread::sleep(Duration::from_millis(50));
+    };
+    assert_eq!(commands[0].id, "cmd-1");
+    assert_eq!(
+        commands[0].action,
+        CommandAction::Lock {
+            reason: "left the room".into()
+        }
+    );
+    assert_eq!(
+        commands[1].action,
+        CommandAction::ExtendTime { minutes: 10 }
+    );
+
+    let mut acked = heartbeat(seq + 1);
+    acked.acked_commands = vec!["cmd-1".into()];
+    let remaining = proctor.heartbeat(&acked).commands;
+    assert_eq!(remaining.len(), 1);
+    assert_eq!(remaining[0].id, "cmd-2");
+}
+
+#[test]
+fn heartbeats_with_a_bad_mac_are_rejected() {
+    let proctor = MockProctor::start("rejected");
+    let body = serde_json::to_vec(&heartbeat(1)).unwrap();
+
+    let (status, signature, _) = proctor.post(&body, Some(&sign(b"another key", &body)));
+    assert_eq!(status, 401);
+    assert_eq!(signature, None);
+
+    let (status, _, _) = proctor.post(&body, None);
+    assert_eq!(status, 401);
+
+    let mut tampered = heartbeat(1);
+    tampered.seat = Some("B2".into());
+    let tampered = serde_json::to_vec(&tampered).unwrap();
+    let (status, _, _) = proctor.post(&tampered, Some(&sign(KEY.as_bytes(), &body)));
+    assert_eq!(status, 401);
+}
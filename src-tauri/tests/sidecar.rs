@@ -0,0 +1,322 @@
+//! Supervises the `dummy_sidecar` binary the way the app supervises the mapper.
+
+use app_lib::utils::mode::AppMode;
+use app_lib::utils::sidecar::{
+    configure_line, supervise_with, RestartBudget, SidecarEvent, SidecarHost, SidecarPolicy,
+};
+use app_lib::utils::sync::backoff;
+use shared::ipc::MapperMessage;
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::Command;
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug)]
+enum Entry {
+    Spawned(Instant),
+    Message(MapperMessage),
+    Exited(serde_json::Value),
+    Restarted,
+    GaveUp(String),
+}
+
+/// The handles of the running dummy: its stdin and a way to kill it.
+struct Running {
+    stdin: mpsc::UnboundedSender<Vec<u8>>,
+    kill: oneshot::Sender<()>,
+}
+
+struct DummyHost {
+    args: Vec<&'static str>,
+    running: Mutex<Option<Running>>,
+    log: Mutex<Vec<Entry>>,
+    stopping: AtomicBool,
+    /// Set `stopping` as soon as the first process starts
+    stop_after_spawn: bool,
+}
+
+impl DummyHost {
+    fn new(args: &[&'static str]) -> Self {
+        Self {
+            args: args.to_vec(),
+            running: Mutex::new(None),
+            log: Mutex::new(Vec::new()),
+            stopping: AtomicBool::new(false),
+            stop_after_spawn: false,
+        }
+    }
+
+    fn record(&self, entry: Entry) {
+        self.log.lock().unwrap().push(entry);
+    }
+
+    fn spawns(&self) -> Vec<Instant> {
+        let log = self.log.lock().unwrap();
+        log.iter()
+            .filter_map(|entry| match entry {
+                Entry::Spawned(at) => Some(*at),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn exits(&self) -> Vec<serde_json::Value> {
+        let log = self.log.lock().unwrap();
+        log.iter()
+            .filter_map(|entry| match entry {
+                Entry::Exited(detail) => Some(detail.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn count(&self, matches: fn(&Entry) -> bool) -> usize {
+        self.log
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|entry| matches(entry))
+            .count()
+    }
+}
+
+impl SidecarHost for DummyHost {
+    fn spawn(&self) -> Result<mpsc::Receiver<SidecarEvent>, String> {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_dummy_sidecar"))
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|err| err.to_string())?;
+        self.record(Entry::Spawned(Instant::now()));
+        if self.stop_after_spawn {
+            self.stopping.store(true, Ordering::SeqCst);
+        }
+
+        let mut stdin = child.stdin.take().expect("piped stdin");
+        let stdout = child.stdout.take().expect("piped stdout");
+        let (stdin_sender, mut stdin_receiver) = mpsc::unbounded_channel::<Vec<u8>>();
+        tokio::spawn(async move {
+            while let Some(bytes) = stdin_receiver.recv().await {
+                if stdin.write_all(&bytes).await.is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (sender, receiver) = mpsc::channel(64);
+        let (kill, mut killed) = oneshot::channel();
+        tokio::spawn(async move {
+            let mut lines = BufReader::new(stdout).lines();
+            loop {
+                tokio::select! {
+                    line = lines.next_line() => match line {
+                        Ok(Some(line)) => {
+                            let _ = sender.send(SidecarEvent::Stdout(line.into_bytes())).await;
+                        }
+                        _ => break,
+                    },
+                    _ = &mut killed => {
+                        let _ = child.kill().await;
+                        break;
+                    }
+                }
+            }
+            let status = child.wait().await.ok();
+            let _ = sender
+                .send(SidecarEvent::Terminated {
+                    code: status.and_then(|status| status.code()),
+                    signal: None,
+                })
+                .await;
+        });
+
+        *self.running.lock().unwrap() = Some(Running {
+            stdin: stdin_sender,
+            kill,
+        });
+        Ok(receiver)
+    }
+
+    fn write(&self, bytes: &[u8]) -> Result<(), String> {
+        let running = self.running.lock().unwrap();
+        let running = running.as_ref().ok_or("not running")?;
+        running
+            .stdin
+            .send(bytes.to_vec())
+            .map_err(|_| "stdin closed".to_string())
+    }
+
+    fn kill(&self) {
+        if let Some(running) = self.running.lock().unwrap().take() {
+            let _ = running.kill.send(());
+        }
+    }
+
+    fn stopping(&self) -> bool {
+        self.stopping.load(Ordering::SeqCst)
+    }
+
+    fn message(&self, message: MapperMessage) {
+        self.record(Entry::Message(message));
+    }
+
+    fn exited(&self, detail: serde_json::Value) {
+        self.running.lock().unwrap().take();
+        self.record(Entry::Exited(detail));
+    }
+
+    fn restarted(&self) {
+        self.record(Entry::Restarted);
+    }
+
+    fn gave_up(&self, reason: String) {
+        self.record(Entry::GaveUp(reason));
+    }
+}
+
+fn policy(heartbeat_timeout_secs: u64, max_restarts: usize) -> SidecarPolicy {
+    SidecarPolicy {
+        heartbeat_timeout_secs,
+        max_restarts,
+        restart_window_secs: 60,
+        max_backoff_secs: 30,
+    }
+}
+
+fn configure() -> String {
+    configure_line(&["block alt+tab".into()], AppMode::Kiosk, None)
+}
+
+#[tokio::test]
+async fn a_crashing_sidecar_is_restarted_with_backoff_until_the_budget_is_spent() {
+    let host = DummyHost::new(&["--crash", "3"]);
+    let policy = policy(10, 2);
+    supervise_with(&host, configure(), &policy).await;
+
+    let spawns = host.spawns();
+    assert_eq!(spawns.len(), 3);
+    let max_backoff = Duration::from_secs(policy.max_backoff_secs);
+    for (attempt, pair) in spawns.windows(2).enumerate() {
+        let delay = backoff(attempt as u32 + 1, max_backoff);
+        assert!(
+            pair[1] - pair[0] >= delay,
+            "restart {} came too soon",
+            attempt + 1
+        );
+    }
+    for exit in host.exits() {
+        assert_eq!(exit["reason"], "terminated");
+        assert_eq!(exit["code"], 3);
+    }
+    assert_eq!(host.exits().len(), 3);
+    assert_eq!(host.count(|entry| matches!(entry, Entry::Restarted)), 2);
+    let reasons: Vec<String> = host
+        .log
+        .lock()
+        .unwrap()
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::GaveUp(reason) => Some(reason.clone()),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(reasons.len(), 1);
+    assert!(
+        reasons[0].contains("2 restarts within 60 seconds"),
+        "{}",
+        reasons[0]
+    );
+    // every process was configured and answered
+    assert_eq!(
+        host.count(|entry| matches!(entry, Entry::Message(MapperMessage::Ready { .. }))),
+        3
+    );
+}
+
+#[tokio::test]
+async fn a_silent_sidecar_is_killed_and_restarted() {
+    let host = DummyHost::new(&["--silent"]);
+    supervise_with(&host, configure(), &policy(1, 1)).await;
+
+    assert_eq!(host.spawns().len(), 2);
+    let exits = host.exits();
+    assert_eq!(exits.len(), 2);
+    for exit in exits {
+        assert_eq!(exit["reason"], "heartbeat_timeout");
+    }
+    assert_eq!(host.count(|entry| matches!(entry, Entry::GaveUp(_))), 1);
+}
+
+#[tokio::test]
+async fn a_healthy_sidecar_is_left_running() {
+    let host = DummyHost::new(&[]);
+    let policy = policy(1, 0);
+    let supervised = supervise_with(&host, configure(), &policy);
+    assert!(tokio::time::timeout(Duration::from_secs(3), supervised)
+        .await
+        .is_err());
+    host.kill();
+
+    assert_eq!(host.spawns().len(), 1);
+    assert!(host.exits().is_empty());
+    match &host.log.lock().unwrap()[1] {
+        Entry::Message(MapperMessage::Ready {
+            backend,
+            rules,
+            mode,
+        }) => {
+            assert_eq!(backend, "dummy");
+            assert_eq!(*rules, 1);
+            assert_eq!(mode, "kiosk");
+        }
+        other => panic!("expected ready, got {:?}", other),
+    }
+    assert!(
+        host.count(|entry| matches!(entry, Entry::Message(MapperMessage::Heartbeat { .. }))) > 5
+    );
+}
+
+#[tokio::test]
+async fn nothing_is_restarted_while_stopping() {
+    let mut host = DummyHost::new(&["--crash", "3"]);
+    host.stop_after_spawn = true;
+    supervise_with(&host, configure(), &policy(10, 5)).await;
+
+    assert_eq!(host.spawns().len(), 1);
+    assert!(host.exits().is_empty());
+    assert_eq!(host.count(|entry| matches!(entry, Entry::GaveUp(_))), 0);
+}
+
+#[test]
+fn restarts_are_budgeted_per_window() {
+    let mut budget = RestartBudget::new(&SidecarPolicy {
+        max_restarts: 2,
+        restart_window_secs: 60,
+        max_backoff_secs: 3,
+        ..SidecarPolicy::default()
+    });
+    let start = Instant::now();
+    assert_eq!(budget.next_restart(start), Some(Duration::from_secs(1)));
+    assert_eq!(
+        budget.next_restart(start + Duration::from_secs(1)),
+        Some(Duration::from_secs(2))
+    );
+    assert_eq!(budget.next_restart(start + Duration::from_secs(2)), None);
+    // the first restart leaves the window, making room for one more
+    assert_eq!(
+        budget.next_restart(start + Duration::from_secs(61)),
+        Some(Duration::from_secs(2))
+    );
+    assert_eq!(budget.next_restart(start + Duration::from_secs(61)), None);
+    // after a quiet window the backoff starts over
+    assert_eq!(
+        budget.next_restart(start + Duration::from_secs(200)),
+        Some(Duration::from_secs(1))
+    );
+}